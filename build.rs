@@ -1,12 +1,11 @@
 #![allow(dead_code)]
-#![feature(plugin)]
+#![allow(clippy::needless_range_loop, clippy::needless_return, clippy::upper_case_acronyms)]
 
-#[plugin]
 #[macro_use]
 extern crate log;
 
-use std::default::Default;
-use std::io;
+use std::fs::File;
+use std::io::Write;
 use inftrees::{Code, LENS, DISTS, inflate_table};
 
 /*
@@ -28,7 +27,7 @@ use inftrees::{Code, LENS, DISTS, inflate_table};
     a.out > inffixed.h
  */
 
-fn makefixed(w: &mut Writer) {
+fn makefixed(w: &mut dyn Write) {
     let mut fixed: [Code; 544] = [Default::default(); 544];
     let mut work: [u16; 288] = [Default::default(); 288];         // work area for code table building
 
@@ -46,7 +45,7 @@ fn makefixed(w: &mut Writer) {
 
     let mut next :usize = 0;     // index into 'fixed' table
     let lenfix: usize = 0;       // index into 'fixed' table
-    let (err, _) = inflate_table(LENS, &lens, 288, &mut fixed, &mut next, 9, work.as_mut_slice());
+    let (err, _) = inflate_table(LENS, &lens, 288, &mut fixed, &mut next, 9, &mut work);
     assert!(err == 0);
 
     /* distance table */
@@ -56,15 +55,15 @@ fn makefixed(w: &mut Writer) {
     }
     let distfix: usize = next;      // index into 'fixed' table
 
-    let (err, _) = inflate_table(DISTS, &lens, 32, &mut fixed, &mut next, 5, work.as_mut_slice());
+    let (err, _) = inflate_table(DISTS, &lens, 32, &mut fixed, &mut next, 5, &mut work);
     assert!(err == 0);
 
-    let lencode = fixed.slice_from(lenfix);
+    let lencode = &fixed[lenfix..];
     // let lenbits: usize = 9;
-    let distcode = fixed.slice_from(distfix);
+    let distcode = &fixed[distfix..];
     // let distbits: usize = 5;
 
-    w.write_str("
+    w.write_all("
 // WARNING -- GENERATED CODE -- DO NOT EDIT
 //
 // This file contains the generated \"fixed\" tables for zlib.
@@ -73,27 +72,27 @@ fn makefixed(w: &mut Writer) {
 
 use super::inftrees::Code;
 
-").unwrap();
+".as_bytes()).unwrap();
 
     let size = 1 << 9;
-    w.write_str(format!("pub static LENFIX: [Code; {}] = [\n", size).as_slice()).unwrap();
-    for low in range(0, size) {
-        w.write_str(format!("    Code {{ op: 0x{:02x}, bits: {:2}, val: 0x{:04x} }},\n", 
+    writeln!(w, "pub static LENFIX: [Code; {}] = [", size).unwrap();
+    for low in 0..size {
+        writeln!(w, "    Code {{ op: 0x{:02x}, bits: {:2}, val: 0x{:04x} }},", 
             if (low & 127) == 99 { 64 } else { lencode[low].op },
                 lencode[low].bits,
-                lencode[low].val).as_slice()).unwrap();
+                lencode[low].val).unwrap();
     }
-    w.write_str("];\n\n").unwrap();
+    w.write_all("];\n\n".as_bytes()).unwrap();
 
     let size = 1 << 5;
-    w.write_str(format!("pub static DISTFIX: [Code; {}] = [\n", size).as_slice()).unwrap();
-    for low in range(0, size) {
-        w.write_str(format!("    Code {{ op: 0x{:02x}, bits: {:2}, val: 0x{:04x} }},\n",
+    writeln!(w, "pub static DISTFIX: [Code; {}] = [", size).unwrap();
+    for low in 0..size {
+        writeln!(w, "    Code {{ op: 0x{:02x}, bits: {:2}, val: 0x{:04x} }},",
             distcode[low].op,
             distcode[low].bits,
-            distcode[low].val).as_slice()).unwrap();
+            distcode[low].val).unwrap();
     }
-    w.write_str("];\n").unwrap();
+    w.write_all("];\n".as_bytes()).unwrap();
 }
 
 // Return state with length and distance decoding tables and index sizes set to
@@ -109,7 +108,8 @@ use super::inftrees::Code;
 mod inftrees;
 
 fn main() {
-    let gen_path = Path::new("src/inflate/inffixed.rs");
-    let mut gen_file = io::File::create(&gen_path);
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/inflate/inftrees.rs");
+    let mut gen_file = File::create("src/inflate/inffixed.rs").unwrap();
     makefixed(&mut gen_file);
 }
@@ -11,16 +11,32 @@ pub fn adler32(adler: u32, buf: &[u8]) -> u32 {
     let mut sum2: u32 = (adler >> 16) & 0xffff;
     let mut adler: u32 = adler & 0xffff;
 
-    // This is the "short loop" from adler32.c.
-    /* in case short lengths are provided, keep it somewhat fast */
-    for &b in buf.iter() {
-        adler += b as u32;
-        sum2 += adler;
+    // Process the data in chunks of at most NMAX bytes, so that the sums
+    // cannot overflow before they are reduced modulo BASE.
+    for chunk in buf.chunks(NMAX as usize) {
+        for &b in chunk.iter() {
+            adler += b as u32;
+            sum2 += adler;
+        }
+        adler %= BASE;
+        sum2 %= BASE;
     }
-    if adler >= BASE {
-        adler -= BASE;
-    }
-    sum2 %= BASE;
     return adler | (sum2 << 16);
 }
 
+/// Combines two Adler-32 checksums into one.  If `adler1` is the Adler-32 of a sequence A and
+/// `adler2` is the Adler-32 of a sequence B of length `len2`, then this returns the Adler-32
+/// of A followed by B.
+pub fn adler32_combine(adler1: u32, adler2: u32, len2: u64) -> u32 {
+    // the derivation of this formula is left as an exercise for the reader
+    let rem = (len2 % BASE as u64) as u32;
+    let mut sum1 = adler1 & 0xffff;
+    let mut sum2 = (rem * sum1) % BASE;
+    sum1 += (adler2 & 0xffff) + BASE - 1;
+    sum2 += ((adler1 >> 16) & 0xffff) + ((adler2 >> 16) & 0xffff) + BASE - rem;
+    if sum1 >= BASE { sum1 -= BASE; }
+    if sum1 >= BASE { sum1 -= BASE; }
+    if sum2 >= (BASE << 1) { sum2 -= BASE << 1; }
+    if sum2 >= BASE { sum2 -= BASE; }
+    sum1 | (sum2 << 16)
+}
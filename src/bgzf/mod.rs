@@ -0,0 +1,121 @@
+// Support for BGZF ("blocked GZIP") files, such as BAM and tabix-indexed VCF files.  See the
+// SAM/BAM format specification for the format.
+//
+// A BGZF file is a series of GZIP members, each holding at most 64 KiB of data and taking at
+// most 64 KiB in the file.  The extra field of each header has a "BC" subfield that gives
+// the size of the member, so readers can find the blocks without decoding them.  A position
+// in the uncompressed data is given by a "virtual offset": the offset in the file of the
+// block's header in the high 48 bits, and the offset within the block's data in the low 16.
+
+use std::io;
+use std::io::{Read, Write, ErrorKind};
+
+use crate::{io_error, read_le_u64};
+
+pub use self::reader::BgzfReader;
+pub use self::writer::BgzfWriter;
+
+mod reader;
+mod writer;
+
+/// Maximum size of a BGZF block, compressed or uncompressed.
+pub const MAX_BLOCK_SIZE: usize = 0x10000;
+
+/// The empty block that ends a BGZF file.
+pub const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43,
+    0x02, 0x00, 0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// Size of the header of a BGZF block, which has only the BC subfield in its extra field
+const BLOCK_HEADER_SIZE: usize = 18;
+
+// Size of the GZIP trailer (CRC-32 and ISIZE)
+const BLOCK_TRAILER_SIZE: usize = 8;
+
+/// Makes a virtual offset from the offset of a block in the file, and an offset within the
+/// block's uncompressed data.
+pub fn make_virtual_offset(block_offset: u64, within_block: usize) -> u64 {
+    assert!(block_offset < (1 << 48) && within_block < MAX_BLOCK_SIZE);
+    (block_offset << 16) | within_block as u64
+}
+
+/// Splits a virtual offset into the offset of a block in the file, and an offset within the
+/// block's uncompressed data.
+pub fn split_virtual_offset(virtual_offset: u64) -> (u64, usize) {
+    (virtual_offset >> 16, (virtual_offset & 0xffff) as usize)
+}
+
+// Finds the BC subfield in the extra field of a GZIP header, and returns the size of the
+// block, including the header and trailer.
+fn block_size_from_extra(extra: &[u8]) -> Option<usize> {
+    let mut pos: usize = 0;
+    while pos + 4 <= extra.len() {
+        let len = (extra[pos + 2] as usize) | ((extra[pos + 3] as usize) << 8);
+        if extra[pos] == b'B' && extra[pos + 1] == b'C' && len == 2 && pos + 6 <= extra.len() {
+            return Some(((extra[pos + 4] as usize) | ((extra[pos + 5] as usize) << 8)) + 1);
+        }
+        pos += 4 + len;
+    }
+    None
+}
+
+/// A ".gzi" index of a BGZF file, as written by `bgzip -i`.  It maps offsets in the
+/// uncompressed data to the blocks that contain them, so that a `BgzfReader` can seek by
+/// uncompressed offset.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct GziIndex {
+    // (compressed offset, uncompressed offset) of the start of each block but the first
+    entries: Vec<(u64, u64)>,
+}
+
+impl GziIndex {
+    /// Creates an index from (compressed offset, uncompressed offset) pairs for the start of
+    /// each block, in order.  The first block, at (0, 0), is implied and can be left out.
+    pub fn new(entries: Vec<(u64, u64)>) -> GziIndex {
+        GziIndex { entries: entries }
+    }
+
+    /// Returns the (compressed offset, uncompressed offset) pairs in the index.
+    pub fn entries(&self) -> &[(u64, u64)] {
+        self.entries.as_slice()
+    }
+
+    /// Returns the virtual offset of an offset in the uncompressed data.
+    pub fn virtual_offset(&self, offset: u64) -> u64 {
+        let (block_offset, block_start) = match self.entries.iter().rev().find(|e| e.1 <= offset) {
+            Some(&entry) => entry,
+            None => (0, 0)
+        };
+        make_virtual_offset(block_offset, (offset - block_start) as usize)
+    }
+
+    /// Reads an index in the ".gzi" format: the number of entries, and then the compressed
+    /// and uncompressed offset of each entry, all as 64-bit little-endian integers.
+    pub fn read_from<R:Read>(src: &mut R) -> io::Result<GziIndex> {
+        let count = read_le_u64(src)?;
+        let mut entries: Vec<(u64, u64)> = Vec::new();
+        for _ in 0..count {
+            let compressed = read_le_u64(src)?;
+            let uncompressed = read_le_u64(src)?;
+            if let Some(&(last_compressed, last_uncompressed)) = entries.last() {
+                if compressed <= last_compressed || uncompressed < last_uncompressed {
+                    return Err(io_error(ErrorKind::InvalidData, "invalid gzi index",
+                        Some(format!("entry {} is out of order", entries.len()))));
+                }
+            }
+            entries.push((compressed, uncompressed));
+        }
+        Ok(GziIndex { entries: entries })
+    }
+
+    /// Writes the index in the ".gzi" format.
+    pub fn write_to<W:Write>(&self, dst: &mut W) -> io::Result<()> {
+        dst.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for &(compressed, uncompressed) in self.entries.iter() {
+            dst.write_all(&compressed.to_le_bytes())?;
+            dst.write_all(&uncompressed.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use std::cmp::min;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, ErrorKind};
+
+use crate::{io_error, fill_vec};
+use crate::inflate::{Inflater, InflateResult, InflateError};
+use super::{GziIndex, MAX_BLOCK_SIZE, block_size_from_extra, make_virtual_offset, split_virtual_offset};
+
+/// Reads the uncompressed data of a BGZF file from `src`.
+///
+/// Each block is decoded whole, and its CRC-32 and length are checked.  Every GZIP member
+/// must have a BC subfield, and the size that it gives must match the size of the member;
+/// other GZIP files are rejected.  Empty blocks, such as the one at the end of the file,
+/// are skipped.
+///
+/// If `src` implements `Seek`, then `seek()` moves to a virtual offset, and
+/// `seek_uncompressed()` moves to an offset in the uncompressed data, using a `GziIndex`.
+pub struct BgzfReader<R> {
+    src: R,
+    state: Inflater,
+
+    inbuf: Vec<u8>,
+    next_in: usize,
+    src_eof: bool,
+    src_offset: u64,            // offset in 'src' of the end of 'inbuf'
+
+    block: Vec<u8>,             // uncompressed data of the current block
+    block_len: usize,           // length of the data in 'block'
+    block_pos: usize,           // offset in 'block' of the next byte to return
+    block_offset: u64,          // offset in 'src' of the current block
+    next_block_offset: u64,     // offset in 'src' of the block after the current one
+}
+
+impl<R:Read> BgzfReader<R> {
+    /// Creates a new BgzfReader which reads a BGZF file from `src`, starting at the current
+    /// position of `src`, which is taken to be offset 0.
+    pub fn new(src: R) -> BgzfReader<R> {
+        debug!("BgzfReader::new()");
+        BgzfReader {
+            src: src,
+            state: Inflater::new_gzip(),
+            inbuf: Vec::with_capacity(MAX_BLOCK_SIZE),
+            next_in: 0,
+            src_eof: false,
+            src_offset: 0,
+            block: vec![0u8; MAX_BLOCK_SIZE],
+            block_len: 0,
+            block_pos: 0,
+            block_offset: 0,
+            next_block_offset: 0,
+        }
+    }
+
+    /// Returns the virtual offset of the next byte that `read()` will return.  At the end of
+    /// a block, this is the start of the next block.
+    pub fn virtual_offset(&self) -> u64 {
+        if self.block_pos == self.block_len {
+            make_virtual_offset(self.next_block_offset, 0)
+        }
+        else {
+            make_virtual_offset(self.block_offset, self.block_pos)
+        }
+    }
+
+    pub fn inner(&mut self) -> &mut R {
+        &mut self.src
+    }
+
+    // Decodes the next block into 'block'.  Returns false at the end of the input.
+    fn read_block(&mut self) -> io::Result<bool> {
+        self.block_offset = self.next_block_offset;
+        self.block_len = 0;
+        self.block_pos = 0;
+        if self.next_in == self.inbuf.len() {
+            self.fill_buffer()?;
+            if self.next_in == self.inbuf.len() {
+                return Ok(false);
+            }
+        }
+
+        self.state.reset();
+        self.state.capture_header();
+        let mut block_size: usize = 0;
+        let mut expected_size: Option<usize> = None;
+        loop {
+            let result = self.state.inflate(None, &self.inbuf[self.next_in..], &mut self.block[self.block_len..]);
+            match result {
+                InflateResult::Decoded(in_bytes, out_bytes) | InflateResult::Boundary(in_bytes, out_bytes) => {
+                    self.next_in += in_bytes;
+                    block_size += in_bytes;
+                    self.block_len += out_bytes;
+                    if in_bytes == 0 && out_bytes == 0 {
+                        return Err(self.invalid_block(ErrorKind::InvalidData, "bgzf block is too large", block_size));
+                    }
+
+                    // Check for the BC subfield as soon as the header has been decoded.
+                    if expected_size.is_none() {
+                        if let Some(header) = self.state.header() {
+                            expected_size = match header.extra {
+                                Some(ref extra) => block_size_from_extra(extra),
+                                None => None
+                            };
+                            if expected_size.is_none() {
+                                return Err(self.invalid_block(ErrorKind::InvalidData, "not a bgzf block", block_size));
+                            }
+                        }
+                    }
+                }
+                InflateResult::Eof(_) => break,
+                InflateResult::NeedInput => {
+                    if self.block_len == MAX_BLOCK_SIZE {
+                        return Err(self.invalid_block(ErrorKind::InvalidData, "bgzf block is too large", block_size));
+                    }
+                    if self.next_in == self.inbuf.len() && !self.src_eof {
+                        self.fill_buffer()?;
+                    }
+                    if self.next_in == self.inbuf.len() {
+                        return Err(self.invalid_block(ErrorKind::UnexpectedEof, "unexpected end of input", block_size));
+                    }
+                }
+                InflateResult::Error(err, _) => {
+                    let desc = err.description();
+                    let detail = match err {
+                        InflateError::IncorrectDataCheck(expected, actual) =>
+                            format!("in block at offset {}: expected 0x{:08x}, computed 0x{:08x}", self.block_offset, expected, actual),
+                        InflateError::IncorrectLengthCheck(expected, actual) =>
+                            format!("in block at offset {}: expected {} bytes, decoded {} bytes", self.block_offset, expected, actual),
+                        _ => format!("in block at offset {}", self.block_offset)
+                    };
+                    warn!("{} {}", desc, detail);
+                    return Err(io_error(ErrorKind::InvalidData, desc, Some(detail)));
+                }
+                InflateResult::NeedDictionary(_) => unreachable!(),
+            }
+        }
+
+        match expected_size {
+            Some(expected_size) if expected_size == block_size => (),
+            Some(expected_size) => {
+                return Err(io_error(ErrorKind::InvalidData, "incorrect bgzf block size",
+                    Some(format!("in block at offset {}: expected {} bytes, found {} bytes",
+                        self.block_offset, expected_size, block_size))));
+            }
+            None => return Err(self.invalid_block(ErrorKind::InvalidData, "not a bgzf block", block_size))
+        }
+
+        debug!("bgzf block: offset={} size={} len={}", self.block_offset, block_size, self.block_len);
+        self.next_block_offset = self.block_offset + block_size as u64;
+        Ok(true)
+    }
+
+    fn invalid_block(&self, kind: ErrorKind, desc: &str, block_size: usize) -> io::Error {
+        warn!("{} in block at offset {}, after {} bytes", desc, self.block_offset, block_size);
+        io_error(kind, desc, Some(format!("in block at offset {}", self.block_offset)))
+    }
+
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        self.next_in = 0;
+        let n = fill_vec(&mut self.src, &mut self.inbuf)?;
+        if n == 0 {
+            self.src_eof = true;
+        }
+        self.src_offset += n as u64;
+        Ok(())
+    }
+}
+
+impl<R:Read+Seek> BgzfReader<R> {
+    /// Moves to a virtual offset, such as one returned by `virtual_offset()` or found in a
+    /// BAI or tabix index.  Offsets in the file are relative to where `src` was when the
+    /// BgzfReader was created.
+    pub fn seek(&mut self, virtual_offset: u64) -> io::Result<()> {
+        let (block_offset, within_block) = split_virtual_offset(virtual_offset);
+        debug!("seek: virtual_offset=0x{:x} block_offset={} within_block={}", virtual_offset, block_offset, within_block);
+        // Decode the block, unless it is the current one.
+        if block_offset != self.block_offset || self.next_block_offset == self.block_offset {
+            self.src.seek(SeekFrom::Current(block_offset as i64 - self.src_offset as i64))?;
+            self.inbuf.clear();
+            self.next_in = 0;
+            self.src_eof = false;
+            self.src_offset = block_offset;
+            self.next_block_offset = block_offset;
+            self.read_block()?;
+        }
+        if within_block > self.block_len {
+            return Err(io_error(ErrorKind::InvalidInput, "virtual offset is past the end of its block",
+                Some(format!("virtual offset 0x{:x}, block length {}", virtual_offset, self.block_len))));
+        }
+        self.block_pos = within_block;
+        Ok(())
+    }
+
+    /// Moves to an offset in the uncompressed data, using the ".gzi" index of the file.
+    pub fn seek_uncompressed(&mut self, index: &GziIndex, offset: u64) -> io::Result<()> {
+        self.seek(index.virtual_offset(offset))
+    }
+}
+
+impl<R:Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.block_pos == self.block_len {
+            if !self.read_block()? {
+                return Ok(0);
+            }
+        }
+        let n = min(buf.len(), self.block_len - self.block_pos);
+        buf[..n].copy_from_slice(&self.block[self.block_pos..self.block_pos + n]);
+        self.block_pos += n;
+        Ok(n)
+    }
+}
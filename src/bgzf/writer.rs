@@ -0,0 +1,137 @@
+use std::cmp::min;
+use std::io;
+use std::io::{Write, ErrorKind};
+
+use crate::io_error;
+use crate::crc::crc32;
+use crate::Flush;
+use crate::WINDOW_BITS_MAX;
+use crate::deflate::{Deflater, DeflateResult};
+use super::{GziIndex, MAX_BLOCK_SIZE, EOF_BLOCK, BLOCK_HEADER_SIZE, BLOCK_TRAILER_SIZE, make_virtual_offset};
+
+// Amount of data in each block.  This is less than MAX_BLOCK_SIZE so that the compressed
+// block fits in MAX_BLOCK_SIZE, even if the data cannot be compressed.
+const BLOCK_DATA_SIZE: usize = 0xff00;
+
+/// Writes a BGZF file to `dst`.
+///
+/// Data is collected into blocks of 65280 bytes, and each block is compressed with DEFLATE
+/// and written as a GZIP member.  `flush()` ends the current block early, so that the next
+/// data starts a new block; this is useful for starting records at the start of a block.
+///
+/// Call `finish()` after the last data is written.  This writes the last block and the empty
+/// block that marks the end of the file, and returns the inner writer.  `index()` returns a
+/// ".gzi" index of the blocks that have been written.
+pub struct BgzfWriter<W> {
+    dst: W,
+    offset: u64,                // number of bytes written to 'dst'
+    total_in: u64,              // number of bytes of data in the blocks written to 'dst'
+    data: Vec<u8>,              // data for the next block
+    index: GziIndex,
+
+    deflater: Deflater,
+    outbuf: Vec<u8>,
+}
+
+impl<W:Write> BgzfWriter<W> {
+    /// Creates a new BgzfWriter which writes a BGZF file to `dst`.  `level` is the compression
+    /// level, and has the same meaning as in `Deflater::new_zlib()`.
+    pub fn new(level: i32, dst: W) -> BgzfWriter<W> {
+        debug!("BgzfWriter::new()");
+        BgzfWriter {
+            dst: dst,
+            offset: 0,
+            total_in: 0,
+            data: Vec::with_capacity(BLOCK_DATA_SIZE),
+            index: GziIndex::new(Vec::new()),
+            deflater: Deflater::new_raw(level, WINDOW_BITS_MAX),
+            outbuf: vec![0u8; MAX_BLOCK_SIZE],
+        }
+    }
+
+    /// Returns the virtual offset of the next byte that will be written.
+    pub fn virtual_offset(&self) -> u64 {
+        make_virtual_offset(self.offset, self.data.len())
+    }
+
+    /// Returns a ".gzi" index of the blocks that have been written so far.  Call this after
+    /// `flush()` to include all of the data that has been written.
+    pub fn index(&self) -> &GziIndex {
+        &self.index
+    }
+
+    /// Writes the last block and the end-of-file block, and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_block()?;
+        self.dst.write_all(&EOF_BLOCK)?;
+        self.dst.flush()?;
+        Ok(self.dst)
+    }
+
+    pub fn inner(&mut self) -> &mut W {
+        &mut self.dst
+    }
+
+    // Compresses the data in 'data' and writes it as a block.  Does nothing if there is no data.
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.data.is_empty() {
+            return Ok(());
+        }
+
+        // Like bgzip, index the start of each block but the first.
+        if self.offset != 0 {
+            self.index.entries.push((self.offset, self.total_in));
+        }
+
+        self.deflater.reset();
+        let compressed_len = match self.deflater.deflate(Flush::Finish, &self.data, &mut self.outbuf) {
+            DeflateResult::Finished(in_bytes, out_bytes) if in_bytes == self.data.len() => out_bytes,
+            result => {
+                warn!("deflate returned {:?}", result);
+                return Err(io_error(ErrorKind::Other, "deflate failed", Some(format!("{:?}", result))));
+            }
+        };
+        let block_size = BLOCK_HEADER_SIZE + compressed_len + BLOCK_TRAILER_SIZE;
+        if block_size > MAX_BLOCK_SIZE {
+            return Err(io_error(ErrorKind::Other, "bgzf block is too large",
+                Some(format!("{} bytes of data compressed to {} bytes", self.data.len(), compressed_len))));
+        }
+
+        // The header has an extra field with only the BC subfield, which gives the size of
+        // the block, minus 1.
+        let mut header = [0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 0, 0];
+        header[16] = (block_size - 1) as u8;
+        header[17] = ((block_size - 1) >> 8) as u8;
+        self.dst.write_all(&header)?;
+        self.dst.write_all(&self.outbuf[..compressed_len])?;
+        self.dst.write_all(&crc32(0, &self.data).to_le_bytes())?;
+        self.dst.write_all(&(self.data.len() as u32).to_le_bytes())?;
+        debug!("bgzf block: offset={} size={} len={}", self.offset, block_size, self.data.len());
+
+        self.offset += block_size as u64;
+        self.total_in += self.data.len() as u64;
+        self.data.clear();
+        Ok(())
+    }
+}
+
+impl<W:Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pos: usize = 0;
+        while pos < buf.len() {
+            let n = min(buf.len() - pos, BLOCK_DATA_SIZE - self.data.len());
+            self.data.extend_from_slice(&buf[pos..pos + n]);
+            pos += n;
+            if self.data.len() == BLOCK_DATA_SIZE {
+                self.write_block()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Writes the current block, even if it is not full, and flushes the inner writer.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.dst.flush()
+    }
+}
@@ -1,14 +1,14 @@
-#![allow(unstable)]
-
 extern crate zlib;
 
+use std::env;
+use std::fs::File;
 use std::io;
-use std::os;
-use zlib::inflate::InflateReader;
+use std::io::{Read, Write};
+use zlib::inflate::BufInflateReader;
 
 fn main()
 {
-    let args = os::args();
+    let args: Vec<String> = env::args().collect();
 
     if args.len() != 3 {
         println!("usage: gzip <input> <output>");
@@ -16,40 +16,42 @@ fn main()
         return;
     }
 
-    let input_path = Path::new(&args[1]);
-    let output_path = Path::new(&args[2]);
 
     let in_bufsize: usize = 1 << 22;
     let out_bufsize: usize = 1 << 22;
 
-    // open compressed input file, create a decompressor for it
-    let input_file = io::BufferedReader::new(io::File::open(&input_path).unwrap());
-    let mut inflater = InflateReader::new_gzip(in_bufsize, input_file);
+    // open compressed input file, create a decompressor for it.  the decompressor reads
+    // directly from the BufReader's buffer.
+    let input_file = io::BufReader::with_capacity(in_bufsize, File::open(&args[1]).unwrap());
+    let mut inflater = BufInflateReader::new_gzip(input_file);
 
     println!("opened input file");
 
     // open the output file
-    let mut output_file = io::BufferedWriter::new(io::File::create(&output_path).unwrap());
+    let mut output_file = io::BufWriter::new(File::create(&args[2]).unwrap());
 
     println!("opened output file");
 
-    let mut buffer: Vec<u8> = Vec::with_capacity(out_bufsize);
+    let mut buffer: Vec<u8> = vec![0; out_bufsize];
 
     let mut total_out: u64 = 0;
 
     loop {
-        match inflater.push(out_bufsize, &mut buffer) {
+        match inflater.read(&mut buffer) {
+            Ok(0) => {
+                break;
+            }
             Ok(chunk_bytes) => {
-                output_file.write(buffer.as_slice()).unwrap();
-                buffer.clear();
+                output_file.write_all(&buffer[..chunk_bytes]).unwrap();
                 total_out += chunk_bytes as u64;
             }
             Err(err) => {
-                println!("push() returned error: {} {:?}", err.desc, err.detail);
+                println!("read() returned error: {}", err);
                 break;
             }
         }
     }
+    output_file.flush().unwrap();
 
     println!("done.  wrote {} byte(s).", total_out);
 }
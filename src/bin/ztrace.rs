@@ -1,20 +1,16 @@
-#![allow(unstable)]
-
 extern crate zlib;
 
-use std::io;
-use std::iter::repeat;
-use std::os;
-use std::os::set_exit_status;
+use std::env;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::process;
 use zlib::inflate::{Inflater,InflateResult};
-use std::io::IoErrorKind;
 
 macro_rules! bad_arg {
     ($arg:expr, $msg:expr) => {
         {
             println!("arg '{}' is invalid: {}", $arg, $msg);
-            set_exit_status(1);
-            return;
+            process::exit(1);
         }
     }
 }
@@ -23,22 +19,19 @@ macro_rules! get_int_arg {
     ($arg:expr, $valopt:expr, $min:expr) => {
         {
             if let Some(aval) = $valopt {
-                let value: usize = if let Some(value) = aval.parse::<usize>() { value } else {
+                let value: usize = if let Ok(value) = aval.parse::<usize>() { value } else {
                     println!("arg '{}' is invalid: value is not a valid number", $arg);
-                    set_exit_status(1);
-                    return;
+                    process::exit(1);
                 };
                 if value < $min {
                     println!("arg '{}' is invalid: the value is too small", $arg);
-                    set_exit_status(1);
-                    return;
+                    process::exit(1);
                 }
                 value
             }
             else {
                 println!("arg '{}' is invalid: it requires that a value be specified, i.e. -foo:<nnn>", $arg);
-                set_exit_status(1);
-                return;
+                process::exit(1);
             }
         }
     }
@@ -46,7 +39,7 @@ macro_rules! get_int_arg {
 
 fn usage() {
     println!("usage: [flags] <input-path>");
-    println!("");
+    println!();
     println!("    -v            verbose mode");
     println!("    -i:<nnn>      iteration count");
     println!("    -ib:<nnn>     input buffer size");
@@ -54,7 +47,7 @@ fn usage() {
 }
 
 struct CheckFileState {
-    reader: io::File,
+    reader: File,
 }
 
 
@@ -70,16 +63,14 @@ fn main() {
     
     let arg_prefix = "-";
 
-    let args = os::args();
+    let args: Vec<String> = env::args().collect();
     if args.len() == 1 {
         usage();
-        set_exit_status(1);
-        return;
+        process::exit(1);
     }
-    for i in range(1, args.len()) {
-        let arg = &args[i];
-        if arg.starts_with(arg_prefix) {
-            let mut ai = arg.slice_from(arg_prefix.len()).splitn(1, ':');
+    for arg in args.iter().skip(1) {
+        if let Some(arg_rest) = arg.strip_prefix(arg_prefix) {
+            let mut ai = arg_rest.splitn(2, ':');
             let aname: &str = if let Some(aname) = ai.next() { aname } else {
                 bad_arg!(arg, "expected arg name");
             };
@@ -102,7 +93,7 @@ fn main() {
                     verbose_print_blocks = true;
                 }
                 "c" => {
-                    if check_filename == None {
+                    if check_filename.is_none() {
                         if let Some(val) = valopt {
                             check_filename = Some(val.to_string());
                         }
@@ -122,15 +113,12 @@ fn main() {
                 }
             }
         }
+        else if input_filename.is_none() {
+            input_filename = Some(arg.to_string());
+        }
         else {
-            if input_filename == None {
-                input_filename = Some(arg.to_string());
-            }
-            else {
-                println!("error: input filename specified more than once.");
-                set_exit_status(1);
-                return;
-            }
+            println!("error: input filename specified more than once.");
+            process::exit(1);
         }
     }
 
@@ -140,32 +128,28 @@ fn main() {
     }
     else {
         println!("error: input filename not specified.");
-        set_exit_status(1);
-        return;
+        process::exit(1);
     };
-    let input_path = Path::new(&input_filename);
 
     // open compressed input file
-    let mut input_file = io::File::open(&input_path);
+    let mut input_file = File::open(&input_filename).unwrap();
 
     // If we are going to read the entire file, then do so now.
     // Else, set up the input buffer for reading in chunks.
-    let mut input_buffer: Vec<u8>;
+    let mut input_buffer: Vec<u8> = Vec::new();
     if read_entire_file {
-        input_buffer = input_file.read_to_end().unwrap();
+        input_file.read_to_end(&mut input_buffer).unwrap();
         // println!("read entire input file, size = {}", input_buffer.len());
     }
     else {
         println!("using buffered mode.");
         println!("    input buffer size: 0x{:x} {}", input_buffer_size, input_buffer_size);
         println!("    output buffer size: 0x{:x} {}", output_buffer_size, output_buffer_size);
-        input_buffer = Vec::with_capacity(input_buffer_size);
     }
 
     // If a check file was specified, then open it.
     let mut check_state = if let Some(ref check_fn) = check_filename {
-        let check_path = Path::new(check_fn);
-        let check_file_raw = io::File::open(&check_path).unwrap();
+        let check_file_raw = File::open(check_fn).unwrap();
         Some(CheckFileState {
             reader: check_file_raw
         })
@@ -177,25 +161,24 @@ fn main() {
     let mut check_buffer: Vec<u8> = Vec::new();
 
     // Allocate output buffer
-    let mut output_buffer: Vec<u8> = Vec::with_capacity(output_buffer_size);
-    output_buffer.extend(repeat(0).take(output_buffer_size));
+    let mut output_buffer: Vec<u8> = vec![0; output_buffer_size];
 
     let out_data = output_buffer.as_mut_slice();
 
     let mut state = Inflater::new_gzip();
     let mut cycle: usize = 0;
 
-    for iter in range(0, iter_count) {
+    for iter in 0..iter_count {
         if verbose {
             println!("starting iteration #{}", iter);
         }
 
         // This is the decode loop for an entire file.
-        input_file.seek(0, io::SeekSet).unwrap();
+        input_file.seek(SeekFrom::Start(0)).unwrap();
         state.reset();
 
         if let Some(ref mut cs) = check_state {
-            cs.reader.seek(0, io::SeekSet).unwrap();
+            cs.reader.seek(SeekFrom::Start(0)).unwrap();
         }
         check_buffer.clear();
 
@@ -215,21 +198,19 @@ fn main() {
                 // println!("input buffer is empty; loading data");
                 input_buffer.clear();
                 input_pos = 0;
-                match input_file.push(input_buffer_size, &mut input_buffer) {
+                match push(&mut input_file, input_buffer_size, &mut input_buffer) {
+                    Ok(0) => {
+                        // println!("input stream EOF");
+                        input_eof = true;
+                    }
                     Ok(bytes_read) => {
                         if verbose {
                             println!("zlibtest: loaded {} input bytes", bytes_read);
                         }
                     }
                     Err(err) => {
-                        if err.kind == IoErrorKind::EndOfFile {
-                            // println!("input stream EOF");
-                            input_eof = true;
-                        }
-                        else {
-                            println!("unexpected input error: {}", err.desc);
-                            break;
-                        }
+                        println!("unexpected input error: {}", err);
+                        break;
                     }
                 };
             }
@@ -238,7 +219,7 @@ fn main() {
                 println!("calling inflate, cycle = {}, input_pos = {}, input_buffer.len = {}", cycle, input_pos, input_buffer.len());
             }
 
-            match state.inflate(None, input_buffer.slice_from(input_pos), out_data) {
+            match state.inflate(None, &input_buffer[input_pos..], out_data) {
                 InflateResult::Eof(_) => {
                     if verbose {
                         println!("zlib says Z_STREAM_END");
@@ -246,12 +227,12 @@ fn main() {
                     break;
                 }
 
-                InflateResult::InvalidData => {
-                    println!("InvalidData");
+                InflateResult::Error(err, offset) => {
+                    println!("Error: {} at input offset {}", err.description(), offset);
                     break;
                 }
 
-                InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                InflateResult::Decoded(input_bytes_read, output_bytes_written) | InflateResult::Boundary(input_bytes_read, output_bytes_written) => {
                     // println!("InflateDecoded: input_bytes_read: {} output_bytes_written: {}", input_bytes_read, output_bytes_written);                
                     // println!("zlibtest: in_read={}, out_written={}", input_bytes_read, output_bytes_written);
                     total_in += input_bytes_read as u64;
@@ -259,7 +240,7 @@ fn main() {
                         println!("zlibtest: cycle = {}, input_bytes_read = {}, output_bytes_written = {}", cycle, input_bytes_read, output_bytes_written);
                         println!("total_in = {}", total_in);
                         if verbose_print_blocks {
-                            print_block(out_data.slice(0, output_bytes_written));
+                            print_block(&out_data[0..output_bytes_written]);
                         }
                     }
 
@@ -269,21 +250,21 @@ fn main() {
                     // Check the data that we just received against the same data in the known-good file.
                     if output_bytes_written != 0 {
                         if let Some(ref mut cs) = check_state {
-                	        assert!(check_buffer.len() == 0);
+                	        assert!(check_buffer.is_empty());
 
                             // read chunks from the check stream and verify them
                             let mut cpos = 0;
                             while cpos < output_bytes_written {
                                 let clen_want = output_bytes_written - cpos;
-                	            assert!(check_buffer.len() == 0);
-                	            let clen_got = cs.reader.push(clen_want, &mut check_buffer).unwrap();
+                	            assert!(check_buffer.is_empty());
+                	            let clen_got = push(&mut cs.reader, clen_want, &mut check_buffer).unwrap();
                                 assert!(clen_got <= clen_want);
 
-                	            for i in range(0, clen_got) {
-                		            if check_buffer[i] != out_data[cpos + i] {
+                	            for (i, &expected) in check_buffer.iter().enumerate() {
+                		            if expected != out_data[cpos + i] {
                 			            panic!("outputs differ!  at output offset {}, expected {} found {}",
                                             total_out + (i as u64),
-                                            check_buffer[i],
+                                            expected,
                                             out_data[cpos + i]);
                 		            }
                 	            }
@@ -301,6 +282,11 @@ fn main() {
                     println!("NeedInput");
                     unimplemented!();
                 }
+
+                InflateResult::NeedDictionary(dictid) => {
+                    println!("error: stream requires a preset dictionary, with id 0x{:08x}", dictid);
+                    process::exit(1);
+                }
             }
 
             cycle += 1;
@@ -317,8 +303,7 @@ fn print_block(data: &[u8]) {
 
     println!("print_block: len={}", data.len());
 
-    for i in (0..data.len()) {
-        let b = data[i];
+    for (i, &b) in data.iter().enumerate() {
         s.push(' ');
         s.push(HEX[(b >> 4) as usize]);
         s.push(HEX[(b & 0xf) as usize]);
@@ -328,7 +313,16 @@ fn print_block(data: &[u8]) {
         }
     }
 
-    if (data.len() % width) != 0 {
+    if !data.len().is_multiple_of(width) {
         println!("{}", s);
     }
 }
+
+// Reads up to 'len' bytes from 'src' and appends them to 'buf'.  Returns 0 at the end of 'src'.
+fn push<R:Read>(src: &mut R, len: usize, buf: &mut Vec<u8>) -> std::io::Result<usize> {
+    let start = buf.len();
+    buf.resize(start + len, 0);
+    let result = src.read(&mut buf[start..]);
+    buf.truncate(start + *result.as_ref().unwrap_or(&0));
+    result
+}
@@ -0,0 +1,97 @@
+/* crc32.c -- compute the CRC-32 of a data stream
+ * Copyright (C) 1995-2006, 2010, 2011, 2012 Mark Adler
+ * For conditions of distribution and use, see copyright notice in zlib.h
+ */
+
+// The CRC-32 itself comes from the crc32fast crate.  This has the part of crc32.c that the
+// crate does not provide.
+
+/// Updates a running CRC-32 with the bytes in `buf`, like crc32() in zlib.  The CRC-32 of
+/// no data is 0.
+pub fn crc32(crc: u32, buf: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new_with_initial(crc);
+    hasher.update(buf);
+    hasher.finalize()
+}
+
+const GF2_DIM: usize = 32;          // dimension of GF(2) vectors (length of CRC)
+
+fn gf2_matrix_times(mat: &[u32; GF2_DIM], vec: u32) -> u32 {
+    let mut sum: u32 = 0;
+    let mut vec = vec;
+    let mut i: usize = 0;
+    while vec != 0 {
+        if (vec & 1) != 0 {
+            sum ^= mat[i];
+        }
+        vec >>= 1;
+        i += 1;
+    }
+    sum
+}
+
+fn gf2_matrix_square(square: &mut [u32; GF2_DIM], mat: &[u32; GF2_DIM]) {
+    for n in 0..GF2_DIM {
+        square[n] = gf2_matrix_times(mat, mat[n]);
+    }
+}
+
+/// Combines two CRC-32 checksums into one.  If `crc1` is the CRC-32 of a sequence A and `crc2`
+/// is the CRC-32 of a sequence B of length `len2`, then this returns the CRC-32 of A followed
+/// by B.  The time taken is proportional to the logarithm of `len2`.
+pub fn crc32_combine(crc1: u32, crc2: u32, len2: u64) -> u32 {
+    // degenerate case
+    if len2 == 0 {
+        return crc1;
+    }
+
+    let mut even = [0u32; GF2_DIM];     // even-power-of-two zeros operator
+    let mut odd = [0u32; GF2_DIM];      // odd-power-of-two zeros operator
+
+    // put operator for one zero bit in odd
+    odd[0] = 0xedb88320;                // CRC-32 polynomial
+    let mut row: u32 = 1;
+    for n in 1..GF2_DIM {
+        odd[n] = row;
+        row <<= 1;
+    }
+
+    // put operator for two zero bits in even
+    gf2_matrix_square(&mut even, &odd);
+
+    // put operator for four zero bits in odd
+    gf2_matrix_square(&mut odd, &even);
+
+    // apply len2 zeros to crc1 (first square will put the operator for one
+    // zero byte, eight zero bits, in even)
+    let mut crc1 = crc1;
+    let mut len2 = len2;
+    loop {
+        // apply zeros operator for this bit of len2
+        gf2_matrix_square(&mut even, &odd);
+        if (len2 & 1) != 0 {
+            crc1 = gf2_matrix_times(&even, crc1);
+        }
+        len2 >>= 1;
+
+        // if no more bits set, then done
+        if len2 == 0 {
+            break;
+        }
+
+        // another iteration of the loop with odd and even swapped
+        gf2_matrix_square(&mut odd, &even);
+        if (len2 & 1) != 0 {
+            crc1 = gf2_matrix_times(&odd, crc1);
+        }
+        len2 >>= 1;
+
+        // if no more bits set, then done
+        if len2 == 0 {
+            break;
+        }
+    }
+
+    // return combined crc
+    crc1 ^ crc2
+}
@@ -1,93 +1,211 @@
+/* deflate.c -- compress data using the deflation algorithm
+ * Copyright (C) 1995-2013 Jean-loup Gailly and Mark Adler
+ * For conditions of distribution and use, see copyright notice in zlib.h
+ */
 
-// ===========================================================================
-// Internal compression state.
-
-use GZipHeader;
-use treedefs::{
-    StaticTreeDesc,
-    LITERALS,
-    L_CODES,
-    MAX_BITS,
-    EXTRA_BLBITS,
-    BL_CODES,
-    D_CODES,
-    MAX_MATCH,
-    MIN_MATCH,
-    EXTRA_DBITS,
-    EXTRA_LBITS
-};
-use statictrees::{
-    STATIC_LTREE_CODES,
-    STATIC_LTREE_LENGTHS,
-    DIST_CODE,
-    LENGTH_CODE,
-    STATIC_DTREE_LENGTHS,
-    STATIC_DTREE_CODES
-};
+/*
+ *  ALGORITHM
+ *
+ *      The "deflation" process depends on being able to identify portions
+ *      of the input text which are identical to earlier input (within a
+ *      sliding window trailing behind the input currently being processed).
+ *
+ *      The most straightforward technique turns out to be the fastest for
+ *      most input files: try all possible matches and select the longest.
+ *      The key feature of this algorithm is that insertions into the string
+ *      dictionary are very simple and thus fast, and deletions are avoided
+ *      completely. Insertions are performed at each input character, whereas
+ *      string matches are performed only when the previous match ends. So it
+ *      is preferable to spend more time in matches to allow very fast string
+ *      insertions and avoid deletions. The matching algorithm for small
+ *      strings is inspired from that of Rabin & Karp. A brute force approach
+ *      is used to find longer strings when a small match has been found.
+ *      A similar algorithm is used in comic (by Jan-Mark Wams) and freeze
+ *      (by Leonid Broukhis).
+ *         A previous version of this file used a more sophisticated algorithm
+ *      (by Fiala and Greene) which is guaranteed to run in linear amortized
+ *      time, but has a larger average cost, uses more memory and is patented.
+ *      However the F&G algorithm may be faster for some highly redundant
+ *      files if the parameter max_chain_length (described below) is too large.
+ *
+ *  ACKNOWLEDGEMENTS
+ *
+ *      The idea of lazy evaluation of matches is due to Jan-Mark Wams, and
+ *      I found it in 'freeze' written by Leonid Broukhis.
+ *      Thanks to many people for bug reports and testing.
+ *
+ *  REFERENCES
+ *
+ *      Deutsch, L.P.,"DEFLATE Compressed Data Format Specification".
+ *      Available in http://tools.ietf.org/html/rfc1951
+ *
+ *      A description of the Rabin and Karp algorithm is given in the book
+ *         "Algorithms" by R. Sedgewick, Addison-Wesley, p252.
+ *
+ *      Fiala,E.R., and Greene,D.H.
+ *         Data Compression with Finite Windows, Comm.ACM, 32,4 (1989) 490-595
+ */
 
-/// Maximum heap size
-const HEAP_SIZE: usize = 2 * L_CODES + 1;
 
-/// Size of bit buffer in bi_buf
-const BUF_SIZE: usize = 16;
+use crate::crc::crc32;
+use crate::adler32::adler32;
+use crate::treedefs::{CtData, L_CODES, D_CODES, BL_CODES, MAX_BITS, MAX_MATCH, MIN_MATCH};
+use self::trees::{
+    TreeDesc,
+    HEAP_SIZE,
+    STATIC_L_DESC,
+    STATIC_D_DESC,
+    STATIC_BL_DESC,
+    put_byte,
+    _tr_init,
+    _tr_align,
+    _tr_flush_bits,
+    _tr_flush_block,
+    _tr_stored_block,
+    _tr_tally_lit,
+    _tr_tally_dist,
+};
+use crate::ZStream;
+use crate::Flush;
+use crate::PRESET_DICT;
+use crate::ZERR;
+use crate::Z_DEFLATED;
+use crate::Z_UNKNOWN;
+use crate::Z_DEFAULT_COMPRESSION;
+use crate::{Z_FILTERED, Z_HUFFMAN_ONLY, Z_RLE, Z_FIXED, Z_DEFAULT_STRATEGY};
+use crate::WINDOW_BITS_DEFAULT;
+use crate::WINDOW_BITS_MIN;
+use crate::WINDOW_BITS_MAX;
+
+pub use self::writer::DeflateWriter;
+pub use self::parallel::{ParallelGzipWriter, PARALLEL_BLOCK_SIZE_DEFAULT};
+
+mod trees;
+mod writer;
+mod parallel;
+
+/// Default memLevel, which controls the size of the hash table and of the
+/// literal buffer.  (8 is the zlib default.)
+pub const DEF_MEM_LEVEL: usize = 8;
+
+/// Maximum value for memLevel
+pub const MAX_MEM_LEVEL: usize = 9;
 
 /* Stream status */
 const INIT_STATE: usize = 42;
-const EXTRA_STATE: usize = 69;
-const NAME_STATE: usize = 73;
-const COMMENT_STATE: usize = 91;
-const HCRC_STATE: usize = 103;
 const BUSY_STATE: usize = 113;
 const FINISH_STATE: usize = 666;
 
-// A Pos is an index in the character window. We use short instead of int to
-// save space in the various tables. IPos is used only for parameter passing.
-type Pos = u16;
-type Posf = u16;    // replace with Pos
-type IPos = u32;
-
-// was "internal_state" / "deflate_state"
-struct DeflateState {
-    status: usize,                   // as the name implies
-    pending_buf: Vec<u8>,           // output still pending
-    pending_buf_size: usize,         // size of pending_buf // use pending_buf.len()
-    pending_out: usize,              // next pending byte to output to the stream (is index into what, pending_buf?)
-    pending: usize,                  // number of bytes in the pending buffer
-    wrap: usize,                     // bit 0 true for zlib, bit 1 true for gzip
-    gzhead: Option<GZipHeader>,     // gzip header information to write
-    gzindex: usize,                  // where in extra, name, or comment
-    method: u8,                     // can only be DEFLATED
-    last_flush: isize,                // value of flush param for previous deflate call
-
-    // used by deflate.c:
-    w_size: usize,        // LZ77 window size (32K by default)
-    w_bits: usize,        // log2(w_size)  (8..16)
-    w_mask: usize,        // w_size - 1
+/// Operating system code written into gzip headers.  (3 is Unix.)
+const OS_CODE: u8 = 0x03;
+
+/// Tail of hash chains
+const NIL: usize = 0;
+
+/// Matches of length 3 are discarded if their distance exceeds TOO_FAR
+const TOO_FAR: usize = 4096;
+
+/// Minimum amount of lookahead, except at the end of the input file.
+/// See deflate.c for comments about the MIN_MATCH+1.
+const MIN_LOOKAHEAD: usize = MAX_MATCH + MIN_MATCH + 1;
+
+/// Describes the results of calling `deflate()`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum DeflateResult
+{
+    Encoded(usize, usize),      // consumed N bytes of input, wrote N bytes of output
+    Finished(usize, usize),     // same, and the stream is now complete (all output has been written)
+    BufError,                   // no progress was possible; provide more input or more output space
+    StreamError,                // the call was not valid, e.g. more input was provided after Flush::Finish
+}
+
+// was block_state
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+enum BlockState {
+    NeedMore,       // block not completed, need more input or more output
+    BlockDone,      // block flush performed
+    FinishStarted,  // finish started, need only more output at next deflate
+    FinishDone,     // finish done, accept no more input or output
+}
+
+// Identifies the compression function for a level.  (zlib uses a function pointer.)
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+enum CompressFunc {
+    Stored,
+    Fast,
+    Slow,
+}
+
+/* Values for max_lazy_match, good_match and max_chain_length, depending on
+ * the desired pack level (0..9). The values given below have been tuned to
+ * exclude worst case performance for pathological files. Better values may be
+ * found for specific files.
+ */
+struct Config {
+    good_length: usize, /* reduce lazy search above this match length */
+    max_lazy: usize,    /* do not perform lazy search above this match length */
+    nice_length: usize, /* quit search above this match length */
+    max_chain: usize,
+    func: CompressFunc,
+}
+
+static CONFIGURATION_TABLE: [Config; 10] = [
+/*      good lazy nice chain */
+/* 0 */ Config { good_length: 0,  max_lazy: 0,   nice_length: 0,   max_chain: 0,    func: CompressFunc::Stored },  /* store only */
+/* 1 */ Config { good_length: 4,  max_lazy: 4,   nice_length: 8,   max_chain: 4,    func: CompressFunc::Fast },    /* max speed, no lazy matches */
+/* 2 */ Config { good_length: 4,  max_lazy: 5,   nice_length: 16,  max_chain: 8,    func: CompressFunc::Fast },
+/* 3 */ Config { good_length: 4,  max_lazy: 6,   nice_length: 32,  max_chain: 32,   func: CompressFunc::Fast },
+
+/* 4 */ Config { good_length: 4,  max_lazy: 4,   nice_length: 16,  max_chain: 16,   func: CompressFunc::Slow },    /* lazy matches */
+/* 5 */ Config { good_length: 8,  max_lazy: 16,  nice_length: 32,  max_chain: 32,   func: CompressFunc::Slow },
+/* 6 */ Config { good_length: 8,  max_lazy: 16,  nice_length: 128, max_chain: 128,  func: CompressFunc::Slow },
+/* 7 */ Config { good_length: 8,  max_lazy: 32,  nice_length: 128, max_chain: 256,  func: CompressFunc::Slow },
+/* 8 */ Config { good_length: 32, max_lazy: 128, nice_length: 258, max_chain: 1024, func: CompressFunc::Slow },
+/* 9 */ Config { good_length: 32, max_lazy: 258, nice_length: 258, max_chain: 4096, func: CompressFunc::Slow },  /* max compression */
+];
+
+/* Note: the deflate() code requires max_lazy >= MIN_MATCH and max_chain >= 4
+ * For deflate_fast() (levels <= 3) good is ignored and lazy has a different
+ * meaning.
+ */
+
+/// Compresses ("deflates") a stream of data.  Supports zlib, GZIP, and raw `DEFLATE` streams.
+/// Use `Deflater::new_zlib()`, `Deflater::new_gzip()`, or `Deflater::new_raw()` to create a stream.
+pub struct Deflater // was deflate_state
+{
+    status: usize,              // as the name implies
+    pending_buf: Vec<u8>,       // output still pending
+    pending_buf_size: usize,    // size of pending_buf
+    pending_out: usize,         // next pending byte to output to the stream; is an index into pending_buf
+    pending: usize,             // number of bytes in the pending buffer
+    wrap: isize,                // bit 0 true for zlib, bit 1 true for gzip; negative once the trailer is written
+    last_flush: isize,          // value of flush param for previous deflate call, or -1
+
+    w_size: usize,              // LZ77 window size (32K by default)
+    w_bits: usize,              // log2(w_size)  (8..16)
+    w_mask: usize,              // w_size - 1
 
     // Sliding window. Input bytes are read into the second half of the window,
     // and move to the first half later to keep a dictionary of at least wSize
     // bytes. With this organization, matches are limited to a distance of
     // wSize-MAX_MATCH bytes, but this ensures that IO is always
-    // performed with a length multiple of the block size. Also, it limits
-    // the window size to 64K, which is quite useful on MSDOS.
-    // To do: use the user input buffer as sliding window.
+    // performed with a length multiple of the block size.
     window: Vec<u8>,
 
-    // Actual size of window: 2*wSize, except when the user input buffer
-    // is directly used as sliding window.
+    // Actual size of window: 2*wSize
     window_size: usize,
 
     // Link to older string with same hash index. To limit the size of this
     // array to 64K, this link is maintained only for the last 32K strings.
     // An index in this array is thus a window index modulo 32K.
-    prev: Vec<Pos>,
+    prev: Vec<u16>,
 
-    head: Vec<Pos>,       // Heads of the hash chains or NIL.
+    head: Vec<u16>,             // Heads of the hash chains or NIL.
 
-    ins_h: usize,          // hash index of string to be inserted
-    hash_size: usize,      // number of elements in hash table
-    hash_bits: usize,      // log2(hash_size)
-    hash_mask: usize,      // hash_size-1
+    ins_h: usize,               // hash index of string to be inserted
+    hash_size: usize,           // number of elements in hash table
+    hash_bits: usize,           // log2(hash_size)
+    hash_mask: usize,           // hash_size-1
 
     // Number of bits by which ins_h must be shifted at each input
     // step. It must be such that after MIN_MATCH steps, the oldest
@@ -99,12 +217,12 @@ struct DeflateState {
     // negative when the window is moved backwards.
     block_start: isize,
 
-    match_length: usize,           // length of best match
-    prev_match: IPos,             // previous match
-    match_available: isize,         // set if previous match exists
-    strstart: usize,               // start of string to insert
-    match_start: usize,            // start of matching string
-    lookahead: usize,              // number of valid bytes ahead in window
+    match_length: usize,        // length of best match
+    prev_match: usize,          // previous match
+    match_available: bool,      // set if previous match exists
+    strstart: usize,            // start of string to insert
+    match_start: usize,         // start of matching string
+    lookahead: usize,           // number of valid bytes ahead in window
 
     // Length of the best match at previous step. Matches not greater than this
     // are discarded. This is used in the lazy match evaluation.
@@ -116,51 +234,37 @@ struct DeflateState {
 
     // Attempt to find a better match only when the current match is strictly
     // smaller than this value. This mechanism is used only for compression
-    // levels >= 4.
+    // levels >= 4.  For levels <= 3, this is max_insert_length: insert new
+    // strings in the hash table only if the match length is not greater than
+    // this length. This saves time but degrades compression.
     max_lazy_match: usize,
 
-// #   define max_insert_length  max_lazy_match
-    /* Insert new strings in the hash table only if the match length is not
-     * greater than this length. This saves time but degrades compression.
-     * max_insert_length is used only for compression levels <= 3.
-     */
-
-    level: usize,    /* compression level (1..9) */
-    strategy: usize, /* favor or force Huffman coding*/
-
-    good_match: usize,
-    /* Use a faster search when the previous match is longer than this */
-
-    nice_match: isize, /* Stop searching when current match exceeds this */
+    level: usize,               // compression level (0..9)
+    strategy: usize,            // favor or force Huffman coding
 
-                /* used by trees.c: */
-    /* Didn't use ct_data typedef below to suppress compiler warning */
-    dyn_ltree_fc: [u16; HEAP_SIZE],   /* literal and length tree */
-    dyn_ltree_dl: [u16; HEAP_SIZE],
+    good_match: usize,          // Use a faster search when the previous match is longer than this
+    nice_match: usize,          // Stop searching when current match exceeds this
 
-    dyn_dtree_fc: [u16; 2*D_CODES+1], /* distance tree */
-    dyn_dtree_dl: [u16; 2*D_CODES+1], /* distance tree */
+    // used by trees.rs:
+    dyn_ltree: Vec<CtData>,     // literal and length tree
+    dyn_dtree: Vec<CtData>,     // distance tree
+    bl_tree: Vec<CtData>,       // Huffman tree for bit lengths
 
-    bl_tree_fc: [u16; 2*BL_CODES+1],  /* Huffman tree for bit lengths */
-    bl_tree_dl: [u16; 2*BL_CODES+1],  /* Huffman tree for bit lengths */
+    l_desc: TreeDesc,           // desc. for literal tree
+    d_desc: TreeDesc,           // desc. for distance tree
+    bl_desc: TreeDesc,          // desc. for bit length tree
 
-    l_desc: TreeDesc,               /* desc. for literal tree */
-    d_desc: TreeDesc,               /* desc. for distance tree */
-    bl_desc: TreeDesc,              /* desc. for bit length tree */
+    bl_count: [u16; MAX_BITS+1],    // number of codes at each bit length for an optimal tree
 
-    bl_count: [u16; MAX_BITS+1],
-    /* number of codes at each bit length for an optimal tree */
-
-    heap: [isize; 2*L_CODES+1],      /* heap used to build the Huffman trees */
-    heap_len: usize,               /* number of elements in the heap */
-    heap_max: usize,               /* element of largest frequency */
     // The sons of heap[n] are heap[2*n] and heap[2*n+1]. heap[0] is not used.
     // The same heap array is used to build all trees.
+    heap: [usize; 2*L_CODES+1], // heap used to build the Huffman trees
+    heap_len: usize,            // number of elements in the heap
+    heap_max: usize,            // element of largest frequency
 
-    /// Depth of each subtree used as tie breaker for trees of equal frequency
-    depth: [u8; 2*L_CODES+1],
+    depth: [u8; 2*L_CODES+1],   // Depth of each subtree used as tie breaker for trees of equal frequency
 
-    l_buf: Vec<u8>,          /* buffer for literals or lengths */
+    l_buf: Vec<u8>,             // buffer for literals or lengths
 
     // Size of match buffer for literals/lengths.  There are 4 reasons for
     // limiting lit_bufsize to 64K:
@@ -181,1143 +285,1270 @@ struct DeflateState {
     //   - I can't count above 4
     lit_bufsize: usize,
 
-    // running index in l_buf
-    last_lit: usize,      
+    last_lit: usize,            // running index in l_buf
 
-    /// Buffer for distances. To simplify the code, d_buf and l_buf have
-    /// the same number of elements. To use different lengths, an extra flag
-    /// array would be necessary.
+    // Buffer for distances. To simplify the code, d_buf and l_buf have
+    // the same number of elements. To use different lengths, an extra flag
+    // array would be necessary.
     d_buf: Vec<u16>,
 
-    opt_len: usize,        /* bit length of current block with optimal trees */
-    static_len: usize,     /* bit length of current block with static trees */
-    matches: usize,       /* number of string matches in current block */
-    insert: usize,        /* bytes at end of window left to insert */
+    opt_len: isize,             // bit length of current block with optimal trees
+    static_len: isize,          // bit length of current block with static trees
+    matches: usize,             // number of string matches in current block
+    insert: usize,              // bytes at end of window left to insert
 
-// #ifdef DEBUG
-    compressed_len: usize, /* total bit length of compressed file mod 2^32 */
-    bits_sent: usize,      /* bit length of compressed data sent mod 2^32 */
-// #endif
+    compressed_len: u64,        // total bit length of compressed file
+    bits_sent: u64,             // bit length of compressed data sent
 
+    // Output buffer. bits are inserted starting at the bottom (least
+    // significant bits).
     bi_buf: u16,
-    /* Output buffer. bits are inserted starting at the bottom (least
-     * significant bits).
-     */
+
+    // Number of valid bits in bi_buf.  All bits above the last valid bit
+    // are always zero.
     bi_valid: usize,
-    /* Number of valid bits in bi_buf.  All bits above the last valid bit
-     * are always zero.
-     */
-
-    high_water: usize,
-    /* High water mark offset in window for initialized bytes -- bytes above
-     * this are set to zero in order to avoid memory check warnings when
-     * longest match routines access bytes past the input.  This is then
-     * updated to the new high water mark.
-     */
-}
 
-/* Output a byte on the stream.
- * IN assertion: there is enough room in pending_buf.
- */
-pub fn put_byte(s: &mut DeflateState, c: u8)
-{
-    s.pending_buf[s.pending] = c;
-    s.pending += 1;
+    strm: ZStream,
 }
 
+/// Holds the input and output buffers for a single call to `deflate()`.  This is the
+/// part of zlib's `z_stream` that does not persist between calls.
+struct DeflateStream<'a> {
+    input: &'a [u8],
+    next_in: usize,             // next input byte; is an index into input
+    output: &'a mut [u8],
+    next_out: usize,            // next output byte; is an index into output
+}
 
+impl<'a> DeflateStream<'a> {
+    #[inline]
+    fn avail_in(&self) -> usize {
+        self.input.len() - self.next_in
+    }
 
-/// Minimum amount of lookahead, except at the end of the input file.
-/// See deflate.c for comments about the MIN_MATCH+1.
-pub const MIN_LOOKAHEAD: usize = (MAX_MATCH+MIN_MATCH+1);
+    #[inline]
+    fn avail_out(&self) -> usize {
+        self.output.len() - self.next_out
+    }
+}
 
-// was MAX_DIST
-pub fn max_dist(s: &mut DeflateState) -> usize
-{
-    s.w_size - MIN_LOOKAHEAD
+// Flush the current block, with given end-of-file flag.
+// IN assertion: strstart is set to the end of the current match.
+macro_rules! FLUSH_BLOCK_ONLY {
+    ($s:expr, $strm:expr, $last:expr) => {
+        {
+            let buf = if $s.block_start >= 0 { Some($s.block_start as usize) } else { None };
+            let stored_len = ($s.strstart as isize - $s.block_start) as usize;
+            _tr_flush_block($s, buf, stored_len, $last);
+            $s.block_start = $s.strstart as isize;
+            flush_pending($s, $strm);
+            debug!("[FLUSH]");
+        }
+    }
 }
 
-// In order to simplify the code, particularly on 16 bit machines, match
-// distances are limited to MAX_DIST instead of WSIZE.
+// Same but force premature exit if necessary.
+macro_rules! FLUSH_BLOCK {
+    ($s:expr, $strm:expr, $last:expr) => {
+        {
+            FLUSH_BLOCK_ONLY!($s, $strm, $last);
+            if $strm.avail_out() == 0 {
+                return if $last { BlockState::FinishStarted } else { BlockState::NeedMore };
+            }
+        }
+    }
+}
 
-/// Number of bytes after end of data in window to initialize in order to avoid
-/// memory checker errors from longest match routines */
-pub const WIN_INIT: usize = MAX_MATCH;
+impl Deflater {
+    /// Creates a new Deflater which produces a zlib stream (RFC 1950).  `level` is a
+    /// compression level, from `Z_NO_COMPRESSION` (0) to `Z_BEST_COMPRESSION` (9), or
+    /// `Z_DEFAULT_COMPRESSION`.
+    pub fn new_zlib(level: i32) -> Deflater {
+        Deflater::internal_new(level, WINDOW_BITS_DEFAULT, DEF_MEM_LEVEL, 1)
+    }
 
-pub fn d_code(dist: u16) -> u16
-{
-    if dist < 256 {
-        DIST_CODE[dist as usize] as u16
+    /// Creates a new Deflater which produces a GZIP stream (RFC 1952).  The GZIP header
+    /// written by the Deflater does not contain a file name, comment, or modification time.
+    pub fn new_gzip(level: i32) -> Deflater {
+        Deflater::internal_new(level, WINDOW_BITS_DEFAULT, DEF_MEM_LEVEL, 2)
     }
-    else {
-        DIST_CODE[256 + (dist as usize >> 7)] as u16
+
+    /// Creates a new Deflater which produces a raw DEFLATE stream (RFC 1951), with no
+    /// header or trailer.  `window_bits` is the base two logarithm of the window size.
+    pub fn new_raw(level: i32, window_bits: usize) -> Deflater {
+        Deflater::internal_new(level, window_bits, DEF_MEM_LEVEL, 0)
     }
-}
 
-/*
-static _length_code: [u8, ..];
-static _dist_code: [u8, ..];
-*/
-
-// returns 'flush' value
-pub fn _tr_tally_lit(s: &mut DeflateState, c: u8) -> bool {
-    let cc: u8 = c;
-    s.d_buf[s.last_lit] = 0;
-    s.l_buf[s.last_lit] = cc;
-    s.last_lit += 1;
-    s.dyn_ltree_fc /* freq */[cc as usize] += 1;
-    s.last_lit == s.lit_bufsize - 1
-}
+    fn internal_new(level: i32, window_bits: usize, mem_level: usize, wrap: isize) -> Deflater {
+        let level = if level == Z_DEFAULT_COMPRESSION { 6 } else { level };
+        assert!(level >= 0 && level <= 9);
+        assert!(window_bits >= WINDOW_BITS_MIN && window_bits <= WINDOW_BITS_MAX);
+        assert!(mem_level >= 1 && mem_level <= MAX_MEM_LEVEL);
 
-// returns 'flush' value
-pub fn _tr_tally_dist(s: &mut DeflateState, distance: u16, length: u8) -> bool {
-    let len = length;
-    let mut dist = distance;
-    s.d_buf[s.last_lit as usize] = dist;
-    s.l_buf[s.last_lit as usize] = len;
-    s.last_lit += 1;
-    dist -= 1;
-    s.dyn_ltree_fc/*freq*/[LENGTH_CODE[len as usize] as usize + LITERALS + 1] += 1;
-    s.dyn_dtree_fc/*freq*/[d_code(dist) as usize] += 1;
-    s.last_lit == s.lit_bufsize - 1
-}
+        // until 256-byte window bug fixed
+        let w_bits = if window_bits == 8 { 9 } else { window_bits };
+        let w_size: usize = 1 << w_bits;
 
-impl DeflateState {
-    fn new() -> DeflateState {
-        let mut s = DeflateState {
-            status: 0,
-            pending_buf: Vec::new(),
-            pending_buf_size: 0,
-            pending: 0,
+        let hash_bits = mem_level + 7;
+        let hash_size: usize = 1 << hash_bits;
+
+        let lit_bufsize: usize = 1 << (mem_level + 6); // 16K elements by default
+        let pending_buf_size = lit_bufsize * 4;
+
+        let mut s = Deflater {
+            status: INIT_STATE,
+            pending_buf: vec![0u8; pending_buf_size],
+            pending_buf_size: pending_buf_size,
             pending_out: 0,
-            wrap: 0,
-            gzhead: None,
-            gzindex: 0,
-            method: 0,
+            pending: 0,
+            wrap: wrap,
             last_flush: 0,
-            w_size: 0,
-            w_bits: 0,
-            w_mask: 0,
-            window: Vec::new(),
+
+            w_size: w_size,
+            w_bits: w_bits,
+            w_mask: w_size - 1,
+            window: vec![0u8; w_size * 2],
             window_size: 0,
-            prev: Vec::new(),
-            head: Vec::new(),
+            prev: vec![0u16; w_size],
+            head: vec![0u16; hash_size],
+
             ins_h: 0,
-            hash_size: 0,
-            hash_bits: 0,
-            hash_mask: 0,
-            hash_shift: 0,
+            hash_size: hash_size,
+            hash_bits: hash_bits,
+            hash_mask: hash_size - 1,
+            hash_shift: (hash_bits + MIN_MATCH - 1) / MIN_MATCH,
+
             block_start: 0,
             match_length: 0,
             prev_match: 0,
-            match_available: 0,
+            match_available: false,
             strstart: 0,
             match_start: 0,
             lookahead: 0,
             prev_length: 0,
             max_chain_length: 0,
             max_lazy_match: 0,
-        // #   define max_insert_length  max_lazy_match
-            level: 0,
-            strategy: 0,
+            level: level as usize,
+            strategy: Z_DEFAULT_STRATEGY,
             good_match: 0,
             nice_match: 0,
-            dyn_ltree_fc:  [0; HEAP_SIZE],   /* literal and length tree */
-            dyn_ltree_dl:  [0; HEAP_SIZE],   /* literal and length tree */
-            dyn_dtree_fc:  [0; 2*D_CODES+1], /* distance tree */
-            dyn_dtree_dl:  [0; 2*D_CODES+1], /* distance tree */
-            bl_tree_fc  :  [0; 2*BL_CODES+1],  /* Huffman tree for bit lengths */
-            bl_tree_dl  :  [0; 2*BL_CODES+1],  /* Huffman tree for bit lengths */
-
-            l_desc: TreeDesc::new(StaticTreeDesc {
-                lengths: &STATIC_LTREE_LENGTHS,
-                codes: &STATIC_LTREE_CODES,
-                extra_bits: &EXTRA_LBITS,
-                extra_base: LITERALS+1,
-                elems: L_CODES,
-                max_length: MAX_BITS
-            }),
-
-            d_desc: TreeDesc::new(StaticTreeDesc {
-                lengths: &STATIC_DTREE_LENGTHS,
-                codes: &STATIC_DTREE_CODES,
-                extra_bits: &EXTRA_DBITS,
-                extra_base: 0,
-                elems: D_CODES,
-                max_length: MAX_BITS
-            }),
-
-            bl_desc: TreeDesc::new(StaticTreeDesc {
-                lengths: &[],
-                codes: &[],
-                extra_bits: &EXTRA_BLBITS,
-                extra_base: 0,
-                elems: BL_CODES,
-                max_length: MAX_BL_BITS
-            }),
 
-            bl_count: [0u16; MAX_BITS+1],
+            dyn_ltree: vec![CtData { fc: 0, dl: 0 }; HEAP_SIZE],
+            dyn_dtree: vec![CtData { fc: 0, dl: 0 }; 2*D_CODES+1],
+            bl_tree: vec![CtData { fc: 0, dl: 0 }; 2*BL_CODES+1],
 
+            l_desc: TreeDesc::new(&STATIC_L_DESC),
+            d_desc: TreeDesc::new(&STATIC_D_DESC),
+            bl_desc: TreeDesc::new(&STATIC_BL_DESC),
+
+            bl_count: [0u16; MAX_BITS+1],
             heap: [0; 2*L_CODES+1],
             heap_len: 0,
             heap_max: 0,
-
             depth: [0u8; 2*L_CODES+1],
 
-            l_buf: Vec::new(),
-
-            lit_bufsize: 0,
+            l_buf: vec![0u8; lit_bufsize],
+            lit_bufsize: lit_bufsize,
             last_lit: 0,
-
-            d_buf: Vec::new(),
+            d_buf: vec![0u16; lit_bufsize],
 
             opt_len: 0,
             static_len: 0,
             matches: 0,
             insert: 0,
 
-        // #ifdef DEBUG
             compressed_len: 0,
             bits_sent: 0,
-        // #endif
 
             bi_buf: 0,
             bi_valid: 0,
 
-            high_water: 0,
+            strm: ZStream::new(),
         };
 
-        init_block(&mut s);
+        s.reset();
+        s
+    }
+
+    // Resets the state of the compressor, but does not reset the hash tables or the
+    // window.  (was deflateResetKeep)
+    fn reset_keep(&mut self) {
+        self.strm.total_in = 0;
+        self.strm.total_out = 0;
+        self.strm.msg = None;
+        self.strm.data_type = Z_UNKNOWN;
+
+        self.pending = 0;
+        self.pending_out = 0;
+
+        if self.wrap < 0 {
+            self.wrap = -self.wrap; // was made negative by deflate(..., Z_FINISH);
+        }
+        self.status = if self.wrap != 0 { INIT_STATE } else { BUSY_STATE };
+        self.strm.adler = if self.wrap == 2 { crc32(0, &[]) } else { adler32(1, &[]) };
+        self.last_flush = Flush::None as isize;
 
-        return s;
+        _tr_init(self);
     }
-}
 
+    /// Resets the state of the compressor.  This is equivalent to allocating a new Deflater,
+    /// with the same arguments that were used to construct this Deflater.
+    pub fn reset(&mut self) {
+        self.reset_keep();
+        lm_init(self);
+    }
 
-/* trees.c -- output deflated data using Huffman coding
- * Copyright (C) 1995-2012 Jean-loup Gailly
- * detect_data_type() function provided freely by Cosmin Truta, 2006
- * For conditions of distribution and use, see copyright notice in zlib.h
- */
-/*
- *  ALGORITHM
- *
- *      The "deflation" process uses several Huffman trees. The more
- *      common source values are represented by shorter bit sequences.
- *
- *      Each code tree is stored in a compressed form which is itself
- * a Huffman encoding of the lengths of all the code strings (in
- * ascending order by source values).  The actual code strings are
- * reconstructed from the lengths in the inflate process, as described
- * in the deflate specification.
- *
- *  REFERENCES
- *
- *      Deutsch, L.P.,"'Deflate' Compressed Data Format Specification".
- *      Available in ftp.uu.net:/pub/archiving/zip/doc/deflate-1.1.doc
- *
- *      Storer, James A.
- *          Data Compression:  Methods and Theory, pp. 49-50.
- *          Computer Science Press, 1988.  ISBN 0-7167-8156-5.
- *
- *      Sedgewick, R.
- *          Algorithms, p290.
- *          Addison-Wesley, 1983. ISBN 0-201-06672-6.
- */
+    /// Changes the compression level and the compression strategy.  (was deflateParams)
+    ///
+    /// `level` has the same meaning as in `new_zlib()`.  `strategy` is one of `Z_DEFAULT_STRATEGY`,
+    /// `Z_FILTERED`, `Z_HUFFMAN_ONLY`, `Z_RLE`, or `Z_FIXED`.  These select the tradeoff between
+    /// speed and compression; they never affect the correctness of the compressed stream.
+    ///
+    /// The parameters can always be changed before the first call to `deflate()`.  After that,
+    /// changing the strategy, or changing to a level that uses a different compression function,
+    /// requires that all of the data compressed so far be flushed first.  Call `deflate()` with
+    /// `Flush::Block` until all output has been written.  If this has not been done, then this
+    /// function returns `DeflateResult::BufError` and the parameters are not changed.  On
+    /// success, this function returns `DeflateResult::Encoded(0, 0)`.
+    pub fn set_params(&mut self, level: i32, strategy: usize) -> DeflateResult {
+        let level = if level == Z_DEFAULT_COMPRESSION { 6 } else { level };
+        if level < 0 || level > 9 || strategy > Z_FIXED {
+            self.strm.msg = Some("stream error");
+            return DeflateResult::StreamError;
+        }
+        let level = level as usize;
+
+        let func = CONFIGURATION_TABLE[self.level].func;
+        if (strategy != self.strategy || func != CONFIGURATION_TABLE[level].func) && self.strm.total_in != 0 {
+            // zlib flushes the current block here, by calling deflate() with Z_BLOCK.  That
+            // would require an output buffer, so it is left to the caller instead.
+            if self.pending != 0 || self.lookahead != 0 || self.strstart as isize != self.block_start {
+                self.strm.msg = Some("buffer error");
+                return DeflateResult::BufError;
+            }
+        }
 
-/* ===========================================================================
- * Constants
- */
+        if self.level != level {
+            let config = &CONFIGURATION_TABLE[level];
+            self.level = level;
+            self.max_lazy_match = config.max_lazy;
+            self.good_match = config.good_length;
+            self.nice_match = config.nice_length;
+            self.max_chain_length = config.max_chain;
+        }
+        self.strategy = strategy;
+        DeflateResult::Encoded(0, 0)
+    }
 
-/// Bit length codes must not exceed `MAX_BL_BITS` bits
-const MAX_BL_BITS: usize = 7;
+    /// Provides a preset dictionary for the stream.  (was deflateSetDictionary)
+    ///
+    /// The compressor may find matches in the dictionary, as if it had been compressed just
+    /// before the stream.  Only the last 32 KiB (or the window size) of the dictionary is used.
+    /// The decompressor must be given the same dictionary.  For a zlib stream, call this before
+    /// the first call to `deflate()`; the dictionary ID is written in the zlib header.  For a
+    /// raw DEFLATE stream, call this before the first call to `deflate()` or after a flush with
+    /// no pending input.  Returns `ZERR::StreamError` for GZIP streams, which cannot have a
+    /// dictionary, or if it is called at the wrong time.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> ZERR {
+        let wrap = self.wrap;
+        if wrap == 2 || (wrap == 1 && self.status != INIT_STATE) || self.lookahead != 0 {
+            return ZERR::StreamError;
+        }
 
-/// End of block literal code
-const END_BLOCK: usize = 256;
+        // When using the zlib wrapper, compute the Adler-32 of the dictionary.
+        if wrap == 1 {
+            self.strm.adler = adler32(self.strm.adler, dictionary);
+        }
+        self.wrap = 0;              // avoid computing the Adler-32 in read_buf()
+        let total_in = self.strm.total_in;
+
+        // If the dictionary would fill the window, just replace the history.
+        let mut dictionary = dictionary;
+        if dictionary.len() >= self.w_size {
+            if wrap == 0 {
+                // already empty otherwise
+                clear_hash(self);
+                self.strstart = 0;
+                self.block_start = 0;
+                self.insert = 0;
+            }
+            dictionary = &dictionary[dictionary.len() - self.w_size..];
+        }
+
+        // Insert the dictionary into the window and the hash chains.
+        let mut output = [0u8; 0];
+        let mut strm = DeflateStream {
+            input: dictionary,
+            next_in: 0,
+            output: output.as_mut_slice(),
+            next_out: 0,
+        };
+        fill_window(self, &mut strm);
+        while self.lookahead >= MIN_MATCH {
+            let mut str = self.strstart;
+            for _ in 0..self.lookahead - (MIN_MATCH - 1) {
+                let c = self.window[str + MIN_MATCH - 1];
+                update_hash(self, c);
+                self.prev[str & self.w_mask] = self.head[self.ins_h];
+                self.head[self.ins_h] = str as u16;
+                str += 1;
+            }
+            self.strstart = str;
+            self.lookahead = MIN_MATCH - 1;
+            fill_window(self, &mut strm);
+        }
+        self.strstart += self.lookahead;
+        self.block_start = self.strstart as isize;
+        self.insert = self.lookahead;
+        self.lookahead = 0;
+        self.match_length = MIN_MATCH - 1;
+        self.prev_length = MIN_MATCH - 1;
+        self.match_available = false;
+        self.strm.total_in = total_in;
+        self.wrap = wrap;
+        ZERR::Ok
+    }
 
-/// Repeat previous bit length 3-6 times (2 bits of repeat count)
-const REP_3_6: usize = 16;
+    /// Compresses as much data as possible, and stops when the input buffer becomes empty
+    /// or the output buffer becomes full.
+    ///
+    /// `flush` has the same meaning as in zlib's `deflate()`.  `Flush::None` allows the
+    /// compressor to decide how much data to accumulate before producing output.
+    /// `Flush::SyncFlush` and `Flush::FullFlush` flush all pending output and align it on
+    /// a byte boundary.  `Flush::Finish` completes the stream; call `deflate()` again with
+    /// `Flush::Finish` (and more output space) until it returns `DeflateResult::Finished`.
+    pub fn deflate(
+        &mut self,
+        flush: Flush,
+        input: &[u8],
+        output: &mut [u8]) -> DeflateResult
+    {
+        debug!("deflate: flush={:?} avail_in={} avail_out={}", flush, input.len(), output.len());
+
+        if flush == Flush::Trees || (self.status == FINISH_STATE && flush != Flush::Finish) {
+            self.strm.msg = Some("stream error");
+            return DeflateResult::StreamError;
+        }
+        if output.len() == 0 {
+            self.strm.msg = Some("buffer error");
+            return DeflateResult::BufError;
+        }
 
-/// Repeat a zero length 3-10 times  (3 bits of repeat count)
-const REPZ_3_10: usize = 17;
+        let mut strm = DeflateStream {
+            input: input,
+            next_in: 0,
+            output: output,
+            next_out: 0,
+        };
 
-/// Repeat a zero length 11-138 times  (7 bits of repeat count)
-const REPZ_11_138: usize = 18;
+        let s = self;
+        let old_flush = s.last_flush;
+        s.last_flush = flush as isize;
+
+        // Write the header
+        if s.status == INIT_STATE {
+            if s.wrap == 2 {
+                s.strm.adler = crc32(0, &[]);
+                put_byte(s, 31);
+                put_byte(s, 139);
+                put_byte(s, 8);
+                put_byte(s, 0);     // flags
+                put_byte(s, 0);     // mtime
+                put_byte(s, 0);
+                put_byte(s, 0);
+                put_byte(s, 0);
+                let xfl = gzip_xfl(s.level, s.strategy);
+                put_byte(s, xfl);
+                put_byte(s, OS_CODE);
+                s.status = BUSY_STATE;
+            }
+            else {
+                let mut header = (Z_DEFLATED + (((s.w_bits - 8) as u32) << 4)) << 8;
+                header |= zlib_level_flags(s.level, s.strategy) << 6;
+                if s.strstart != 0 {
+                    header |= PRESET_DICT;
+                }
+                header += 31 - (header % 31);
+
+                s.status = BUSY_STATE;
+                put_short_msb(s, header);
+
+                // Save the adler32 of the preset dictionary:
+                if s.strstart != 0 {
+                    let adler = s.strm.adler;
+                    put_short_msb(s, adler >> 16);
+                    put_short_msb(s, adler & 0xffff);
+                }
+                s.strm.adler = adler32(1, &[]);
+            }
+        }
 
-// from trees.c
+        // Flush as much pending output as possible
+        if s.pending != 0 {
+            flush_pending(s, &mut strm);
+            if strm.avail_out() == 0 {
+                // Since avail_out is 0, deflate will be called again with
+                // more output space, but possibly with both pending and
+                // avail_in equal to zero. There won't be anything to do,
+                // but this is not an error situation so make sure we
+                // return OK instead of BUF_ERROR at next call of deflate:
+                s.last_flush = -1;
+                return DeflateResult::Encoded(strm.next_in, strm.next_out);
+            }
+        }
+        else if strm.avail_in() == 0 && rank(flush as isize) <= rank(old_flush) && flush != Flush::Finish {
+            // Make sure there is something to do and avoid duplicate consecutive
+            // flushes. For repeated and useless calls with Z_FINISH, we keep
+            // returning Z_STREAM_END instead of Z_BUF_ERROR.
+            s.strm.msg = Some("buffer error");
+            return DeflateResult::BufError;
+        }
 
-struct TreeDesc {
-    // dyn_tree_freq: Vec<u16>,           /* the dynamic tree */
-    // dyn_tree_other: Vec<u8>,
-    fc: Vec<u16>,           /* the dynamic tree */
-    dl: Vec<u8>,
+        // User must not provide more input after the first FINISH:
+        if s.status == FINISH_STATE && strm.avail_in() != 0 {
+            s.strm.msg = Some("buffer error");
+            return DeflateResult::BufError;
+        }
 
-    max_code: usize,            /* largest code with non zero frequency */
-    stat_desc: StaticTreeDesc, /* the corresponding static tree */
-}
+        // Start a new block or continue the current one.
+        if strm.avail_in() != 0 || s.lookahead != 0 || (flush != Flush::None && s.status != FINISH_STATE) {
+            let bstate =
+                if s.strategy == Z_HUFFMAN_ONLY { deflate_huff(s, &mut strm, flush) }
+                else if s.strategy == Z_RLE { deflate_rle(s, &mut strm, flush) }
+                else {
+                    match CONFIGURATION_TABLE[s.level].func {
+                        CompressFunc::Stored => deflate_stored(s, &mut strm, flush),
+                        CompressFunc::Fast => deflate_fast(s, &mut strm, flush),
+                        CompressFunc::Slow => deflate_slow(s, &mut strm, flush),
+                    }
+                };
+
+            if bstate == BlockState::FinishStarted || bstate == BlockState::FinishDone {
+                s.status = FINISH_STATE;
+            }
+            if bstate == BlockState::NeedMore || bstate == BlockState::FinishStarted {
+                if strm.avail_out() == 0 {
+                    s.last_flush = -1; // avoid BUF_ERROR next call, see above
+                }
+                return DeflateResult::Encoded(strm.next_in, strm.next_out);
+                // If flush != Z_NO_FLUSH && avail_out == 0, the next call
+                // of deflate should use the same flush parameter to make sure
+                // that the flush is complete. So we don't have to output an
+                // empty block here, this will be done at next call. This also
+                // ensures that for a very small output buffer, we emit at most
+                // one empty block.
+            }
+            if bstate == BlockState::BlockDone {
+                if flush == Flush::PartialFlush {
+                    _tr_align(s);
+                }
+                else if flush != Flush::Block { // FULL_FLUSH or SYNC_FLUSH
+                    _tr_stored_block(s, 0, 0, false);
+                    // For a full flush, this empty block will be recognized
+                    // as a special marker by inflate_sync().
+                    if flush == Flush::FullFlush {
+                        clear_hash(s);             // forget history
+                        if s.lookahead == 0 {
+                            s.strstart = 0;
+                            s.block_start = 0;
+                            s.insert = 0;
+                        }
+                    }
+                }
+                flush_pending(s, &mut strm);
+                if strm.avail_out() == 0 {
+                    s.last_flush = -1; // avoid BUF_ERROR at next call, see above
+                    return DeflateResult::Encoded(strm.next_in, strm.next_out);
+                }
+            }
+        }
+        assert!(strm.avail_out() > 0, "bug2");
 
-#[derive(Copy)]
-struct TreeRef {
-    pub codes: &'static [u16],
-    pub lengths: &'static [u8],
-}
+        if flush != Flush::Finish {
+            return DeflateResult::Encoded(strm.next_in, strm.next_out);
+        }
+        if s.wrap <= 0 {
+            return DeflateResult::Finished(strm.next_in, strm.next_out);
+        }
 
-impl TreeDesc {
-    fn new(stat_desc: StaticTreeDesc) -> TreeDesc {
-        TreeDesc {
-            fc: Vec::new(),
-            dl: Vec::new(),
-            max_code: 0,
-            stat_desc: stat_desc
+        // Write the trailer
+        if s.wrap == 2 {
+            let adler = s.strm.adler;
+            let total_in = s.strm.total_in as u32;
+            put_byte(s, (adler & 0xff) as u8);
+            put_byte(s, ((adler >> 8) & 0xff) as u8);
+            put_byte(s, ((adler >> 16) & 0xff) as u8);
+            put_byte(s, ((adler >> 24) & 0xff) as u8);
+            put_byte(s, (total_in & 0xff) as u8);
+            put_byte(s, ((total_in >> 8) & 0xff) as u8);
+            put_byte(s, ((total_in >> 16) & 0xff) as u8);
+            put_byte(s, ((total_in >> 24) & 0xff) as u8);
+        }
+        else {
+            let adler = s.strm.adler;
+            put_short_msb(s, adler >> 16);
+            put_short_msb(s, adler & 0xffff);
+        }
+        flush_pending(s, &mut strm);
+        // If avail_out is zero, the application will call deflate again
+        // to flush the rest.
+        if s.wrap > 0 {
+            s.wrap = -s.wrap; // write the trailer only once!
+        }
+        if s.pending != 0 {
+            DeflateResult::Encoded(strm.next_in, strm.next_out)
+        }
+        else {
+            DeflateResult::Finished(strm.next_in, strm.next_out)
         }
     }
 }
 
-fn send_code(s: &mut DeflateState, c: u8, tree: &TreeDesc)
-{
-    // if (z_verbose>2) fprintf(stderr,"\ncd %3d ",(c));
-    println!("send_code: {:3}", c);
-
-    send_bits(s,
-        tree.fc/*codes*/[c as usize] as u32,
-        tree.dl/*lengths*/[c as usize] as usize);
+// Ranks the flush values, so that deflate() can detect repeated, useless flushes.
+// Z_BLOCK ranks between Z_NO_FLUSH and Z_PARTIAL_FLUSH.
+fn rank(f: isize) -> isize {
+    (f << 1) - (if f > 4 { 9 } else { 0 })
 }
 
 /* ===========================================================================
- * Output a short LSB first on the stream.
- * IN assertion: there is enough room in pendingBuf.
+ * Update a hash value with the given input byte
+ * IN  assertion: all calls to to UPDATE_HASH are made with consecutive
+ *    input characters, so that a running hash key can be computed from the
+ *    previous key instead of complete recalculation each time.
  */
-fn put_short(s: &mut DeflateState, w: u16)
-{
-    put_byte(s, (w & 0xff) as u8);
-    put_byte(s, (w >> 8) as u8);
+#[inline]
+fn update_hash(s: &mut Deflater, c: u8) {
+    s.ins_h = ((s.ins_h << s.hash_shift) ^ (c as usize)) & s.hash_mask;
 }
 
-
 /* ===========================================================================
- * Send a value on a given number of bits.
- * IN assertion: length <= 16 and value fits in length bits.
+ * Insert string str in the dictionary and return the previous head
+ * of the hash chain (the most recent string with same hash key).
+ * IN  assertion: all calls to to INSERT_STRING are made with consecutive
+ *    input characters and the first MIN_MATCH bytes of str are valid
+ *    (except for the last MIN_MATCH-1 bytes of the input file).
  */
-// #ifdef DEBUG
-
-fn send_bits(s: &mut DeflateState, value: u32, length: usize)
-    // DeflateState *s;
-    // int value;  /* value to send */
-    // int length; /* number of bits */
-{
-    // Tracevv((stderr," l %2d v %4x ", length, value));
-    assert!(length > 0 && length <= 15);
-    s.bits_sent += length;
-
-    // If not enough room in bi_buf, use (valid) bits from bi_buf and
-    // (16 - bi_valid) bits from value, leaving (width - (16-bi_valid))
-    // unused bits in value.
-    if s.bi_valid > BUF_SIZE - length {
-        let s_bi_buf = s.bi_buf | (value << s.bi_valid) as u16;
-        s.bi_buf |= s_bi_buf;
-        put_short(s, s_bi_buf);
-        s.bi_buf = (value >> (BUF_SIZE - s.bi_valid)) as u16;
-        s.bi_valid += length - BUF_SIZE;
-    } else {
-        s.bi_buf |= (value << s.bi_valid) as u16;
-        s.bi_valid += length;
-    }
-}
-/* #else /* !DEBUG */
-
-#define send_bits(s, value, length) \
-{ int len = length;\
-  if (s.bi_valid > (int)Buf_size - len) {\
-    int val = value;\
-    s.bi_buf |= (ush)val << s.bi_valid;\
-    put_short(s, s.bi_buf);\
-    s.bi_buf = (ush)val >> (Buf_size - s.bi_valid);\
-    s.bi_valid += len - Buf_size;\
-  } else {\
-    s.bi_buf |= (ush)(value) << s.bi_valid;\
-    s.bi_valid += len;\
-  }\
+#[inline]
+fn insert_string(s: &mut Deflater, str: usize) -> usize {
+    let c = s.window[str + (MIN_MATCH - 1)];
+    update_hash(s, c);
+    let match_head = s.head[s.ins_h] as usize;
+    s.prev[str & s.w_mask] = match_head as u16;
+    s.head[s.ins_h] = str as u16;
+    match_head
 }
-#endif /* DEBUG */
-*/
-
-
 
 /* ===========================================================================
- * Initialize a new block.
+ * Initialize the hash table (avoiding 64K overflow for 16 bit systems).
+ * prev[] will be initialized on the fly.
  */
-
-fn init_block(s: &mut DeflateState)
-{
-    /* Initialize the trees. */
-    for n in range(0, L_CODES) { s.l_desc.fc[n] = 0; }
-    for n in range(0, D_CODES) { s.d_desc.fc[n] = 0; }
-    for n in range(0, BL_CODES) { s.bl_desc.fc[n] = 0; }
-
-    s.l_desc.fc[END_BLOCK] = 1;
-    s.opt_len = 0;
-    s.static_len = 0;
-    s.last_lit = 0;
-    s.matches = 0;
+fn clear_hash(s: &mut Deflater) {
+    for h in s.head.iter_mut() {
+        *h = NIL as u16;
+    }
 }
 
-const SMALLEST: usize = 1;
-/* Index within the heap array of least frequent node in the Huffman tree */
-
+// was MAX_DIST
+// In order to simplify the code, particularly on 16 bit machines, match
+// distances are limited to MAX_DIST instead of WSIZE.
+#[inline]
+fn max_dist(s: &Deflater) -> usize {
+    s.w_size - MIN_LOOKAHEAD
+}
 
-/* ===========================================================================
- * Remove the smallest element from the heap and recreate the heap with
- * one less element. Updates heap and heap_len.
+/* =========================================================================
+ * Return the XFL byte of the gzip header: 2 for the slowest compression,
+ * 4 for the fastest, and 0 otherwise.
  */
-fn pqremove(s: &mut DeflateState, tree: &TreeDesc) -> isize {
-    let top = s.heap[SMALLEST];
-    s.heap_len -= 1;
-    s.heap[SMALLEST] = s.heap[s.heap_len];
-    pqdownheap(s, tree, SMALLEST);
-    return top;
+fn gzip_xfl(level: usize, strategy: usize) -> u8 {
+    if level == 9 { 2 }
+    else if strategy >= Z_HUFFMAN_ONLY || level < 2 { 4 }
+    else { 0 }
 }
 
-/* ===========================================================================
- * Compares two subtrees, using the tree depth as tie breaker when
- * the subtrees have equal frequency. This minimizes the worst case length.
+/* =========================================================================
+ * Return the FLEVEL field of the zlib header, which also describes how
+ * fast the compression was.
  */
-fn smaller(tree: &TreeDesc, n: usize, m: usize, depth: &[u8]) -> bool {
-    tree.fc/*freq*/[n] < tree.fc/*freq*/[m] || (tree.fc/*freq*/[n] == tree.fc/*freq*/[m] && depth[n] <= depth[m])
+fn zlib_level_flags(level: usize, strategy: usize) -> u32 {
+    if strategy >= Z_HUFFMAN_ONLY || level < 2 { 0 }
+    else if level < 6 { 1 }
+    else if level == 6 { 2 }
+    else { 3 }
 }
 
-/* ===========================================================================
- * Restore the heap property by moving down the tree starting at node k,
- * exchanging a node with the smallest of its two sons if necessary, stopping
- * when the heap property is re-established (each father smaller than its
- * two sons).
+/* =========================================================================
+ * Put a short in the pending buffer. The 16-bit value is put in MSB order.
+ * IN assertion: the stream state is correct and there is enough room in
+ * pending_buf.
  */
-fn pqdownheap(
-    s: &mut DeflateState,
-    tree: &TreeDesc,        /* the tree to restore */
-    k: usize)                /* node to move down */
-{
-    let mut k = k;
-    let v = s.heap[k];
-    let mut j = k << 1;  /* left son of k */
-    while j <= s.heap_len {
-        /* Set j to the smallest of the two sons: */
-        if j < s.heap_len && smaller(tree, s.heap[j+1] as usize, s.heap[j] as usize, s.depth.as_slice()) {
-            j += 1;
-        }
-        /* Exit if v is smaller than both sons */
-        if smaller(tree, v as usize, s.heap[j] as usize, &s.depth) {
-            break;
-        }
+fn put_short_msb(s: &mut Deflater, b: u32) {
+    put_byte(s, ((b >> 8) & 0xff) as u8);
+    put_byte(s, (b & 0xff) as u8);
+}
 
-        /* Exchange v with the smallest son */
-        s.heap[k] = s.heap[j];
-        k = j;
+/* =========================================================================
+ * Flush as much pending output as possible. All deflate() output goes
+ * through this function so some applications may wish to modify it
+ * to avoid allocating a large strm->next_out buffer and copying into it.
+ * (See also read_buf()).
+ */
+fn flush_pending(s: &mut Deflater, strm: &mut DeflateStream) {
+    _tr_flush_bits(s);
+    let mut len = s.pending;
+    if len > strm.avail_out() {
+        len = strm.avail_out();
+    }
+    if len == 0 {
+        return;
+    }
 
-        /* And continue down the tree, setting j to the left son of k */
-        j <<= 1;
+    let next_out = strm.next_out;
+    strm.output[next_out..next_out + len].copy_from_slice(&s.pending_buf[s.pending_out..s.pending_out + len]);
+    strm.next_out += len;
+    s.pending_out += len;
+    s.strm.total_out += len as u64;
+    s.pending -= len;
+    if s.pending == 0 {
+        s.pending_out = 0;
     }
-    s.heap[k] = v;
 }
 
-/*
-
 /* ===========================================================================
- * Compute the optimal bit lengths for a tree and update the total bit length
- * for the current block.
- * IN assertion: the fields freq and dad are set, heap[heap_max] and
- *    above are the tree nodes sorted by increasing frequency.
- * OUT assertions: the field len is set to the optimal bit length, the
- *     array bl_count contains the frequencies for each bit length.
- *     The length opt_len is updated; static_len is also updated if stree is
- *     not null.
+ * Read a new buffer from the current input stream, update the adler32
+ * and total number of bytes read.  All deflate() input goes through
+ * this function so some applications may wish to modify it to avoid
+ * allocating a large strm->next_in buffer and copying from it.
+ * (See also flush_pending()).
+ *
+ * The data is copied into the window, at strstart + lookahead.
  */
-fn gen_bitlen(s: &mut DeflateState, desc)
-     *s;
-    TreeDesc *desc;    /* the tree descriptor */
-{
-    ct_data *tree        = desc.dyn_tree;
-    int max_code         = desc.max_code;
-    const ct_data *stree = desc.stat_desc.static_tree;
-    const intf *extra    = desc.stat_desc.extra_bits;
-    int base             = desc.stat_desc.extra_base;
-    int max_length       = desc.stat_desc.max_length;
-    int h;              /* heap index */
-    int n, m;           /* iterate over the tree elements */
-    int bits;           /* bit length */
-    int xbits;          /* extra bits */
-    ush f;              /* frequency */
-    int overflow = 0;   /* number of elements with bit length too large */
-
-    for (bits = 0; bits <= MAX_BITS; bits++) s.bl_count[bits] = 0;
-
-    /* In a first pass, compute the optimal bit lengths (which may
-     * overflow in the case of the bit length tree).
-     */
-    tree[s.heap[s.heap_max]].Len = 0; /* root of the heap */
-
-    for (h = s.heap_max+1; h < HEAP_SIZE; h++) {
-        n = s.heap[h];
-        bits = tree[tree[n].Dad].Len + 1;
-        if (bits > max_length) bits = max_length, overflow++;
-        tree[n].Len = (ush)bits;
-        /* We overwrite tree[n].Dad which is no longer needed */
-
-        if (n > max_code) continue; /* not a leaf node */
-
-        s.bl_count[bits]++;
-        xbits = 0;
-        if (n >= base) xbits = extra[n-base];
-        f = tree[n].Freq;
-        s.opt_len += (ulg)f * (bits + xbits);
-        if (stree) s.static_len += (ulg)f * (stree[n].Len + xbits);
+fn read_buf(s: &mut Deflater, strm: &mut DeflateStream, size: usize) -> usize {
+    let mut len = strm.avail_in();
+    if len > size {
+        len = size;
     }
-    if (overflow == 0) return;
-
-    Trace((stderr,"\nbit length overflow\n"));
-    /* This happens for example on obj2 and pic of the Calgary corpus */
-
-    /* Find the first bit length which could increase: */
-    do {
-        bits = max_length-1;
-        while (s.bl_count[bits] == 0) bits--;
-        s.bl_count[bits]--;      /* move one leaf down the tree */
-        s.bl_count[bits+1] += 2; /* move one overflow item as its brother */
-        s.bl_count[max_length]--;
-        /* The brother of the overflow item also moves one step up,
-         * but this does not affect bl_count[max_length]
-         */
-        overflow -= 2;
-    } while (overflow > 0);
-
-    /* Now recompute all bit lengths, scanning in increasing frequency.
-     * h is still equal to HEAP_SIZE. (It is simpler to reconstruct all
-     * lengths instead of fixing only the wrong ones. This idea is taken
-     * from 'ar' written by Haruhiko Okumura.)
-     */
-    for (bits = max_length; bits != 0; bits--) {
-        n = s.bl_count[bits];
-        while (n != 0) {
-            m = s.heap[--h];
-            if (m > max_code) continue;
-            if ((unsigned) tree[m].Len != (unsigned) bits) {
-                Trace((stderr,"code %d bits %d.%d\n", m, tree[m].Len, bits));
-                s.opt_len += ((long)bits - (long)tree[m].Len)
-                              *(long)tree[m].Freq;
-                tree[m].Len = (ush)bits;
-            }
-            n--;
-        }
+    if len == 0 {
+        return 0;
     }
-}
 
-*/
+    let buf = s.strstart + s.lookahead;
+    let input = &strm.input[strm.next_in..strm.next_in + len];
+    s.window[buf..buf + len].copy_from_slice(input);
+    if s.wrap == 1 {
+        s.strm.adler = adler32(s.strm.adler, input);
+    }
+    else if s.wrap == 2 {
+        s.strm.adler = crc32(s.strm.adler, input);
+    }
+    strm.next_in += len;
+    s.strm.total_in += len as u64;
+    len
+}
 
-/*
+/* ===========================================================================
+ * Initialize the "longest match" routines for a new zlib stream
+ */
+fn lm_init(s: &mut Deflater) {
+    s.window_size = 2 * s.w_size;
+
+    clear_hash(s);
+
+    // Set the default configuration parameters:
+    let config = &CONFIGURATION_TABLE[s.level];
+    s.max_lazy_match = config.max_lazy;
+    s.good_match = config.good_length;
+    s.nice_match = config.nice_length;
+    s.max_chain_length = config.max_chain;
+
+    s.strstart = 0;
+    s.block_start = 0;
+    s.lookahead = 0;
+    s.insert = 0;
+    s.match_length = MIN_MATCH - 1;
+    s.prev_length = MIN_MATCH - 1;
+    s.match_available = false;
+    s.ins_h = 0;
+}
 
 /* ===========================================================================
- * Construct one Huffman tree and assigns the code bit strings and lengths.
- * Update the total bit length for the current block.
- * IN assertion: the field freq is set for all tree elements.
- * OUT assertions: the fields len and code are set to the optimal bit length
- *     and corresponding code. The length opt_len is updated; static_len is
- *     also updated if stree is not null. The field max_code is set.
+ * Set match_start to the longest match starting at the given string and
+ * return its length. Matches shorter or equal to prev_length are discarded,
+ * in which case the result is equal to prev_length and match_start is
+ * garbage.
+ * IN assertions: cur_match is the head of the hash chain for the current
+ *   string (strstart) and its distance is <= MAX_DIST, and prev_length >= 1
+ * OUT assertion: the match length is not greater than s->lookahead.
  */
-local void build_tree(s, desc)
-    DeflateState *s;
-    TreeDesc *desc; /* the tree descriptor */
-{
-    ct_data *tree         = desc.dyn_tree;
-    const ct_data *stree  = desc.stat_desc.static_tree;
-    int elems             = desc.stat_desc.elems;
-    int n, m;          /* iterate over heap elements */
-    int max_code = -1; /* largest code with non zero frequency */
-    int node;          /* new node being created */
-
-    /* Construct the initial heap, with least frequent element in
-     * heap[SMALLEST]. The sons of heap[n] are heap[2*n] and heap[2*n+1].
-     * heap[0] is not used.
-     */
-    s.heap_len = 0, s.heap_max = HEAP_SIZE;
-
-    for (n = 0; n < elems; n++) {
-        if (tree[n].Freq != 0) {
-            s.heap[++(s.heap_len)] = max_code = n;
-            s.depth[n] = 0;
-        } else {
-            tree[n].Len = 0;
-        }
+fn longest_match(s: &mut Deflater, cur_match: usize) -> usize {
+    let mut cur_match = cur_match;                 // current match
+    let mut chain_length = s.max_chain_length;     // max hash chain length
+    let scan = s.strstart;                         // current string; is an index into window
+    let mut best_len = s.prev_length;              // best match length so far
+    let mut nice_match = s.nice_match;             // stop if match long enough
+    let mut match_start = s.match_start;
+
+    // Stop when cur_match becomes <= limit. To simplify the code,
+    // we prevent matches with the string of window index 0.
+    let limit = if s.strstart > max_dist(s) { s.strstart - max_dist(s) } else { NIL };
+    let wmask = s.w_mask;
+
+    // The code is optimized for HASH_BITS >= 8 and MAX_MATCH-2 multiple of 16.
+    // It is easy to get rid of this optimization if necessary.
+    debug_assert!(s.hash_bits >= 8 && MAX_MATCH == 258, "Code too clever");
+
+    // Do not waste too much time if we already have a good match:
+    if s.prev_length >= s.good_match {
+        chain_length >>= 2;
     }
-
-    /* The pkzip format requires that at least one distance code exists,
-     * and that at least one bit should be sent even if there is only one
-     * possible code. So to avoid special checks later on we force at least
-     * two codes of non zero frequency.
-     */
-    while (s.heap_len < 2) {
-        node = s.heap[++(s.heap_len)] = (max_code < 2 ? ++max_code : 0);
-        tree[node].Freq = 1;
-        s.depth[node] = 0;
-        s.opt_len--; if (stree) s.static_len -= stree[node].Len;
-        /* node is 0 or 1 so it does not have extra bits */
+    // Do not look for matches beyond the end of the input. This is necessary
+    // to make deflate deterministic.
+    if nice_match > s.lookahead {
+        nice_match = s.lookahead;
     }
-    desc.max_code = max_code;
-
-    /* The elements heap[heap_len/2+1 .. heap_len] are leaves of the tree,
-     * establish sub-heaps of increasing lengths:
-     */
-    for (n = s.heap_len/2; n >= 1; n--) pqdownheap(s, tree, n);
-
-    /* Construct the Huffman tree by repeatedly combining the least two
-     * frequent nodes.
-     */
-    node = elems;              /* next internal node of the tree */
-    do {
-        pqremove(s, tree, n);  /* n = node of least frequency */
-        m = s.heap[SMALLEST]; /* m = node of next least frequency */
-
-        s.heap[--(s.heap_max)] = n; /* keep the nodes sorted by frequency */
-        s.heap[--(s.heap_max)] = m;
-
-        /* Create a new node father of n and m */
-        tree[node].Freq = tree[n].Freq + tree[m].Freq;
-        s.depth[node] = (uch)((s.depth[n] >= s.depth[m] ?
-                                s.depth[n] : s.depth[m]) + 1);
-        tree[n].Dad = tree[m].Dad = (ush)node;
-#ifdef DUMP_BL_TREE
-        if (tree == s.bl_tree) {
-            fprintf(stderr,"\nnode %d(%d), sons %d(%d) %d(%d)",
-                    node, tree[node].Freq, n, tree[n].Freq, m, tree[m].Freq);
-        }
-#endif
-        /* and insert the new node in the heap */
-        s.heap[SMALLEST] = node++;
-        pqdownheap(s, tree, SMALLEST);
 
-    } while (s.heap_len >= 2);
-
-    s.heap[--(s.heap_max)] = s.heap[SMALLEST];
+    debug_assert!(s.strstart <= s.window_size - MIN_LOOKAHEAD, "need lookahead");
+
+    {
+        let window = s.window.as_slice();
+        let prev = s.prev.as_slice();
+        let mut scan_end1 = window[scan + best_len - 1];
+        let mut scan_end = window[scan + best_len];
+
+        loop {
+            debug_assert!(cur_match < s.strstart, "no future");
+            let m = cur_match;
+
+            // Skip to next match if the match length cannot increase
+            // or if the match length is less than 2.
+            if window[m + best_len] == scan_end
+                && window[m + best_len - 1] == scan_end1
+                && window[m] == window[scan]
+                && window[m + 1] == window[scan + 1] {
+
+                // It is not necessary to compare scan[2] and match[2] since they
+                // are always equal when the other bytes match, given that
+                // the hash keys are equal and that HASH_BITS >= 8.  We compare
+                // them anyway; it is cheap and keeps the loop simple.
+                let mut len = 2;
+                while len < MAX_MATCH && window[scan + len] == window[m + len] {
+                    len += 1;
+                }
+
+                if len > best_len {
+                    match_start = cur_match;
+                    best_len = len;
+                    if len >= nice_match {
+                        break;
+                    }
+                    scan_end1 = window[scan + best_len - 1];
+                    scan_end = window[scan + best_len];
+                }
+            }
 
-    /* At this point, the fields freq and dad are set. We can now
-     * generate the bit lengths.
-     */
-    gen_bitlen(s, (tree_desc *)desc);
+            cur_match = prev[cur_match & wmask] as usize;
+            if cur_match <= limit {
+                break;
+            }
+            chain_length -= 1;
+            if chain_length == 0 {
+                break;
+            }
+        }
+    }
 
-    /* The field len is now set, we can generate the bit codes */
-    gen_codes ((ct_data *)tree, max_code, s.bl_count);
+    s.match_start = match_start;
+    if best_len <= s.lookahead {
+        best_len
+    }
+    else {
+        s.lookahead
+    }
 }
 
 /* ===========================================================================
- * Scan a literal or distance tree to determine the frequencies of the codes
- * in the bit length tree.
+ * Fill the window when the lookahead becomes insufficient.
+ * Updates strstart and lookahead.
+ *
+ * IN assertion: lookahead < MIN_LOOKAHEAD
+ * OUT assertions: strstart <= window_size-MIN_LOOKAHEAD
+ *    At least one byte has been read, or avail_in == 0; reads are
+ *    performed for at least two bytes (required for the zip translate_eol
+ *    option -- not supported here).
+ *
+ * zlib also zeroes the bytes just past the end of the input (the "high
+ * water" logic), so that memory checkers do not complain about the
+ * longest match routines reading them.  The window is always initialized
+ * here, so that is not necessary.
  */
-local void scan_tree (s, tree, max_code)
-    DeflateState *s;
-    ct_data *tree;   /* the tree to be scanned */
-    int max_code;    /* and its largest code of non zero frequency */
-{
-    int n;                     /* iterates over all tree elements */
-    int prevlen = -1;          /* last emitted length */
-    int curlen;                /* length of current code */
-    int nextlen = tree[0].Len; /* length of next code */
-    int count = 0;             /* repeat count of the current code */
-    int max_count = 7;         /* max repeat count */
-    int min_count = 4;         /* min repeat count */
-
-    if (nextlen == 0) max_count = 138, min_count = 3;
-    tree[max_code+1].Len = (ush)0xffff; /* guard */
-
-    for (n = 0; n <= max_code; n++) {
-        curlen = nextlen; nextlen = tree[n+1].Len;
-        if (++count < max_count && curlen == nextlen) {
-            continue;
-        } else if (count < min_count) {
-            s.bl_tree[curlen].Freq += count;
-        } else if (curlen != 0) {
-            if (curlen != prevlen) s.bl_tree[curlen].Freq++;
-            s.bl_tree[REP_3_6].Freq++;
-        } else if (count <= 10) {
-            s.bl_tree[REPZ_3_10].Freq++;
-        } else {
-            s.bl_tree[REPZ_11_138].Freq++;
+fn fill_window(s: &mut Deflater, strm: &mut DeflateStream) {
+    let wsize = s.w_size;
+
+    debug_assert!(s.lookahead < MIN_LOOKAHEAD, "already enough lookahead");
+
+    loop {
+        // Amount of free space at the end of the window.
+        let mut more = s.window_size - s.lookahead - s.strstart;
+
+        // If the window is almost full and there is insufficient lookahead,
+        // move the upper half to the lower one to make room in the upper half.
+        if s.strstart >= wsize + max_dist(s) {
+            {
+                let (lower, upper) = s.window.as_mut_slice().split_at_mut(wsize);
+                lower.copy_from_slice(&upper[..wsize]);
+            }
+            s.match_start = s.match_start.wrapping_sub(wsize);   // may wrap, as in C, when no match has been found
+            s.strstart -= wsize; // we now have strstart >= MAX_DIST
+            s.block_start -= wsize as isize;
+
+            // Slide the hash table (could be avoided with 32 bit values
+            // at the expense of memory usage). We slide even when level == 0
+            // to keep the hash table consistent if we switch back to level > 0
+            // later. (Using level 0 permanently is not an optimal usage of
+            // zlib, so we don't care about this pathological case.)
+            for p in s.head.iter_mut() {
+                let m = *p as usize;
+                *p = (if m >= wsize { m - wsize } else { NIL }) as u16;
+            }
+            for p in s.prev.iter_mut() {
+                let m = *p as usize;
+                *p = (if m >= wsize { m - wsize } else { NIL }) as u16;
+                // If n is not on any hash chain, prev[n] is garbage but
+                // its value will never be used.
+            }
+            more += wsize;
         }
-        count = 0; prevlen = curlen;
-        if (nextlen == 0) {
-            max_count = 138, min_count = 3;
-        } else if (curlen == nextlen) {
-            max_count = 6, min_count = 3;
-        } else {
-            max_count = 7, min_count = 4;
+        if strm.avail_in() == 0 {
+            break;
         }
-    }
-}
 
-/* ===========================================================================
- * Send a literal or distance tree in compressed form, using the codes in
- * bl_tree.
- */
-local void send_tree (s, tree, max_code)
-    DeflateState *s;
-    ct_data *tree; /* the tree to be scanned */
-    int max_code;       /* and its largest code of non zero frequency */
-{
-    int n;                     /* iterates over all tree elements */
-    int prevlen = -1;          /* last emitted length */
-    int curlen;                /* length of current code */
-    int nextlen = tree[0].Len; /* length of next code */
-    int count = 0;             /* repeat count of the current code */
-    int max_count = 7;         /* max repeat count */
-    int min_count = 4;         /* min repeat count */
-
-    /* tree[max_code+1].Len = -1; */  /* guard already set */
-    if (nextlen == 0) max_count = 138, min_count = 3;
-
-    for (n = 0; n <= max_code; n++) {
-        curlen = nextlen; nextlen = tree[n+1].Len;
-        if (++count < max_count && curlen == nextlen) {
-            continue;
-        } else if (count < min_count) {
-            do { send_code(s, curlen, s.bl_tree); } while (--count != 0);
-
-        } else if (curlen != 0) {
-            if (curlen != prevlen) {
-                send_code(s, curlen, s.bl_tree); count--;
+        // If there was no sliding:
+        //    strstart <= WSIZE+MAX_DIST-1 && lookahead <= MIN_LOOKAHEAD - 1 &&
+        //    more == window_size - lookahead - strstart
+        // => more >= window_size - (MIN_LOOKAHEAD-1 + WSIZE + MAX_DIST-1)
+        // => more >= window_size - 2*WSIZE + 2
+        // In the BIG_MEM or MMAP case (not yet supported),
+        //   window_size == input_size + MIN_LOOKAHEAD  &&
+        //   strstart + s->lookahead <= input_size => more >= MIN_LOOKAHEAD.
+        // Otherwise, window_size == 2*WSIZE so more >= 2.
+        // If there was sliding, more >= WSIZE. So in all cases, more >= 2.
+        debug_assert!(more >= 2, "more < 2");
+
+        let n = read_buf(s, strm, more);
+        s.lookahead += n;
+
+        // Initialize the hash value now that we have some input:
+        if s.lookahead + s.insert >= MIN_MATCH {
+            let mut str = s.strstart - s.insert;
+            s.ins_h = s.window[str] as usize;
+            let c = s.window[str + 1];
+            update_hash(s, c);
+            while s.insert != 0 {
+                let c = s.window[str + MIN_MATCH - 1];
+                update_hash(s, c);
+                s.prev[str & s.w_mask] = s.head[s.ins_h];
+                s.head[s.ins_h] = str as u16;
+                str += 1;
+                s.insert -= 1;
+                if s.lookahead + s.insert < MIN_MATCH {
+                    break;
+                }
             }
-            Assert(count >= 3 && count <= 6, " 3_6?");
-            send_code(s, REP_3_6, s.bl_tree); send_bits(s, count-3, 2);
-
-        } else if (count <= 10) {
-            send_code(s, REPZ_3_10, s.bl_tree); send_bits(s, count-3, 3);
-
-        } else {
-            send_code(s, REPZ_11_138, s.bl_tree); send_bits(s, count-11, 7);
         }
-        count = 0; prevlen = curlen;
-        if (nextlen == 0) {
-            max_count = 138, min_count = 3;
-        } else if (curlen == nextlen) {
-            max_count = 6, min_count = 3;
-        } else {
-            max_count = 7, min_count = 4;
+        // If the whole input has less than MIN_MATCH bytes, ins_h is garbage,
+        // but this is not important since only literal bytes will be emitted.
+
+        if !(s.lookahead < MIN_LOOKAHEAD && strm.avail_in() != 0) {
+            break;
         }
     }
 }
 
 /* ===========================================================================
- * Construct the Huffman tree for the bit lengths and return the index in
- * bl_order of the last bit length code to send.
+ * Copy without compression as much as possible from the input stream, return
+ * the current block state.
+ * This function does not insert new strings in the dictionary since
+ * uncompressible data is probably not useful. This function is used
+ * only for the level=0 compression option.
+ * NOTE: this function should be optimized to avoid extra copying from
+ * window to pending_buf.
  */
-local int build_bl_tree(s)
-    DeflateState *s;
-{
-    int max_blindex;  /* index of last bit length code of non zero freq */
-
-    /* Determine the bit length frequencies for literal and distance trees */
-    scan_tree(s, (ct_data *)s.dyn_ltree, s.l_desc.max_code);
-    scan_tree(s, (ct_data *)s.dyn_dtree, s.d_desc.max_code);
-
-    /* Build the bit length tree: */
-    build_tree(s, (tree_desc *)(&(s.bl_desc)));
-    /* opt_len now includes the length of the tree representations, except
-     * the lengths of the bit lengths codes and the 5+5+4 bits for the counts.
-     */
-
-    /* Determine the number of bit length codes to send. The pkzip format
-     * requires that at least 4 bit length codes be sent. (appnote.txt says
-     * 3 but the actual value used is 4.)
-     */
-    for (max_blindex = BL_CODES-1; max_blindex >= 3; max_blindex--) {
-        if (s.bl_tree[bl_order[max_blindex]].Len != 0) break;
+fn deflate_stored(s: &mut Deflater, strm: &mut DeflateStream, flush: Flush) -> BlockState {
+    // Stored blocks are limited to 0xffff bytes, pending_buf is limited
+    // to pending_buf_size, and each stored block has a 5 byte header:
+    let mut max_block_size: usize = 0xffff;
+    if max_block_size > s.pending_buf_size - 5 {
+        max_block_size = s.pending_buf_size - 5;
     }
-    /* Update opt_len to include the bit length tree and counts */
-    s.opt_len += 3*(max_blindex+1) + 5+5+4;
-    Tracev((stderr, "\ndyn trees: dyn %ld, stat %ld",
-            s.opt_len, s.static_len));
 
-    return max_blindex;
-}
+    // Copy as much as possible from input to output:
+    loop {
+        // Fill the window as much as possible:
+        if s.lookahead <= 1 {
+            debug_assert!(s.strstart < s.w_size + max_dist(s) || s.block_start >= s.w_size as isize,
+                "slide too late");
 
-/* ===========================================================================
- * Send the header for a block using dynamic Huffman trees: the counts, the
- * lengths of the bit length codes, the literal tree and the distance tree.
- * IN assertion: lcodes >= 257, dcodes >= 1, blcodes >= 4.
- */
-local void send_all_trees(s, lcodes, dcodes, blcodes)
-    DeflateState *s;
-    int lcodes, dcodes, blcodes; /* number of codes for each tree */
-{
-    int rank;                    /* index in bl_order */
-
-    Assert (lcodes >= 257 && dcodes >= 1 && blcodes >= 4, "not enough codes");
-    Assert (lcodes <= L_CODES && dcodes <= D_CODES && blcodes <= BL_CODES,
-            "too many codes");
-    Tracev((stderr, "\nbl counts: "));
-    send_bits(s, lcodes-257, 5); /* not +255 as stated in appnote.txt */
-    send_bits(s, dcodes-1,   5);
-    send_bits(s, blcodes-4,  4); /* not -3 as stated in appnote.txt */
-    for (rank = 0; rank < blcodes; rank++) {
-        Tracev((stderr, "\nbl code %2d ", bl_order[rank]));
-        send_bits(s, s.bl_tree[bl_order[rank]].Len, 3);
-    }
-    Tracev((stderr, "\nbl tree: sent %ld", s.bits_sent));
+            fill_window(s, strm);
+            if s.lookahead == 0 && flush == Flush::None {
+                return BlockState::NeedMore;
+            }
 
-    send_tree(s, (ct_data *)s.dyn_ltree, lcodes-1); /* literal tree */
-    Tracev((stderr, "\nlit tree: sent %ld", s.bits_sent));
+            if s.lookahead == 0 {
+                break; // flush the current block
+            }
+        }
+        debug_assert!(s.block_start >= 0, "block gone");
 
-    send_tree(s, (ct_data *)s.dyn_dtree, dcodes-1); /* distance tree */
-    Tracev((stderr, "\ndist tree: sent %ld", s.bits_sent));
-}
+        s.strstart += s.lookahead;
+        s.lookahead = 0;
 
-/* ===========================================================================
- * Send a stored block
- */
-void ZLIB_INTERNAL _tr_stored_block(s, buf, stored_len, last)
-    DeflateState *s;
-    charf *buf;       /* input block */
-    ulg stored_len;   /* length of input block */
-    int last;         /* one if this is the last block for a file */
-{
-    send_bits(s, (STORED_BLOCK<<1)+last, 3);    /* send block type */
-#ifdef DEBUG
-    s.compressed_len = (s.compressed_len + 3 + 7) & (ulg)~7L;
-    s.compressed_len += (stored_len + 4) << 3;
-#endif
-    copy_block(s, buf, (unsigned)stored_len, 1); /* with header */
+        // Emit a stored block if pending_buf will be full:
+        let max_start = s.block_start + max_block_size as isize;
+        if s.strstart as isize >= max_start {
+            s.lookahead = (s.strstart as isize - max_start) as usize;
+            s.strstart = max_start as usize;
+            FLUSH_BLOCK!(s, strm, false);
+        }
+        // Flush if we may have to slide, otherwise block_start may become
+        // negative and the data will be gone:
+        if (s.strstart as isize - s.block_start) as usize >= max_dist(s) {
+            FLUSH_BLOCK!(s, strm, false);
+        }
+    }
+    s.insert = 0;
+    if flush == Flush::Finish {
+        FLUSH_BLOCK!(s, strm, true);
+        return BlockState::FinishDone;
+    }
+    if s.strstart as isize > s.block_start {
+        FLUSH_BLOCK!(s, strm, false);
+    }
+    BlockState::BlockDone
 }
 
 /* ===========================================================================
- * Flush the bits in the bit buffer to pending output (leaves at most 7 bits)
+ * Compress as much as possible from the input stream, return the current
+ * block state.
+ * This function does not perform lazy evaluation of matches and inserts
+ * new strings in the dictionary only for unmatched strings or for short
+ * matches. It is used only for the fast compression options.
  */
-void ZLIB_INTERNAL _tr_flush_bits(s)
-    DeflateState *s;
-{
-    bi_flush(s);
-}
+fn deflate_fast(s: &mut Deflater, strm: &mut DeflateStream, flush: Flush) -> BlockState {
+    loop {
+        // Make sure that we always have enough lookahead, except
+        // at the end of the input file. We need MAX_MATCH bytes
+        // for the next match, plus MIN_MATCH bytes to insert the
+        // string following the next match.
+        if s.lookahead < MIN_LOOKAHEAD {
+            fill_window(s, strm);
+            if s.lookahead < MIN_LOOKAHEAD && flush == Flush::None {
+                return BlockState::NeedMore;
+            }
+            if s.lookahead == 0 {
+                break; // flush the current block
+            }
+        }
 
-/* ===========================================================================
- * Send one empty static block to give enough lookahead for inflate.
- * This takes 10 bits, of which 7 may remain in the bit buffer.
- */
-void ZLIB_INTERNAL _tr_align(s)
-    DeflateState *s;
-{
-    send_bits(s, STATIC_TREES<<1, 3);
-    send_code(s, END_BLOCK, static_ltree);
-#ifdef DEBUG
-    s.compressed_len += 10L; /* 3 for block type, 7 for EOB */
-#endif
-    bi_flush(s);
-}
+        // Insert the string window[strstart .. strstart+2] in the
+        // dictionary, and set hash_head to the head of the hash chain:
+        let mut hash_head = NIL;
+        if s.lookahead >= MIN_MATCH {
+            let str = s.strstart;
+            hash_head = insert_string(s, str);
+        }
 
-/* ===========================================================================
- * Determine the best encoding for the current block: dynamic trees, static
- * trees or store, and output the encoded block to the zip file.
- */
-void ZLIB_INTERNAL _tr_flush_block(s, buf, stored_len, last)
-    DeflateState *s;
-    charf *buf;       /* input block, or NULL if too old */
-    ulg stored_len;   /* length of input block */
-    int last;         /* one if this is the last block for a file */
-{
-    ulg opt_lenb, static_lenb; /* opt_len and static_len in bytes */
-    int max_blindex = 0;  /* index of last bit length code of non zero freq */
-
-    /* Build the Huffman trees unless a stored block is forced */
-    if (s.level > 0) {
-
-        /* Check if the file is binary or text */
-        if (s.strm.data_type == Z_UNKNOWN)
-            s.strm.data_type = detect_data_type(s);
-
-        /* Construct the literal and distance trees */
-        build_tree(s, (tree_desc *)(&(s.l_desc)));
-        Tracev((stderr, "\nlit data: dyn %ld, stat %ld", s.opt_len,
-                s.static_len));
-
-        build_tree(s, (tree_desc *)(&(s.d_desc)));
-        Tracev((stderr, "\ndist data: dyn %ld, stat %ld", s.opt_len,
-                s.static_len));
-        /* At this point, opt_len and static_len are the total bit lengths of
-         * the compressed block data, excluding the tree representations.
-         */
-
-        /* Build the bit length tree for the above two trees, and get the index
-         * in bl_order of the last bit length code to send.
-         */
-        max_blindex = build_bl_tree(s);
-
-        /* Determine the best encoding. Compute the block lengths in bytes. */
-        opt_lenb = (s.opt_len+3+7)>>3;
-        static_lenb = (s.static_len+3+7)>>3;
-
-        Tracev((stderr, "\nopt %lu(%lu) stat %lu(%lu) stored %lu lit %u ",
-                opt_lenb, s.opt_len, static_lenb, s.static_len, stored_len,
-                s.last_lit));
-
-        if (static_lenb <= opt_lenb) opt_lenb = static_lenb;
-
-    } else {
-        Assert(buf != (char*)0, "lost buf");
-        opt_lenb = static_lenb = stored_len + 5; /* force a stored block */
+        // Find the longest match, discarding those <= prev_length.
+        // At this point we have always match_length < MIN_MATCH
+        if hash_head != NIL && s.strstart - hash_head <= max_dist(s) {
+            // To simplify the code, we prevent matches with the string
+            // of window index 0 (in particular we have to avoid a match
+            // of the string with itself at the start of the input file).
+            s.match_length = longest_match(s, hash_head);
+            // longest_match() sets match_start
+        }
+        let bflush;
+        if s.match_length >= MIN_MATCH {
+            let dist = s.strstart - s.match_start;
+            let len = s.match_length - MIN_MATCH;
+            bflush = _tr_tally_dist(s, dist, len);
+
+            s.lookahead -= s.match_length;
+
+            // Insert new strings in the hash table only if the match length
+            // is not too large. This saves time but degrades compression.
+            if s.match_length <= s.max_lazy_match /* max_insert_length */ && s.lookahead >= MIN_MATCH {
+                s.match_length -= 1; // string at strstart already in table
+                loop {
+                    s.strstart += 1;
+                    let str = s.strstart;
+                    insert_string(s, str);
+                    // strstart never exceeds WSIZE-MAX_MATCH, so there are
+                    // always MIN_MATCH bytes ahead.
+                    s.match_length -= 1;
+                    if s.match_length == 0 {
+                        break;
+                    }
+                }
+                s.strstart += 1;
+            }
+            else {
+                s.strstart += s.match_length;
+                s.match_length = 0;
+                s.ins_h = s.window[s.strstart] as usize;
+                let c = s.window[s.strstart + 1];
+                update_hash(s, c);
+                // If lookahead < MIN_MATCH, ins_h is garbage, but it does not
+                // matter since it will be recomputed at next deflate call.
+            }
+        }
+        else {
+            // No match, output a literal byte
+            let c = s.window[s.strstart];
+            bflush = _tr_tally_lit(s, c);
+            s.lookahead -= 1;
+            s.strstart += 1;
+        }
+        if bflush {
+            FLUSH_BLOCK!(s, strm, false);
+        }
     }
-
-#ifdef FORCE_STORED
-    if (buf != (char*)0) { /* force stored block */
-#else
-    if (stored_len+4 <= opt_lenb && buf != (char*)0) {
-                       /* 4: two words for the lengths */
-#endif
-        /* The test buf != NULL is only necessary if LIT_BUFSIZE > WSIZE.
-         * Otherwise we can't have processed more than WSIZE input bytes since
-         * the last block flush, because compression would have been
-         * successful. If LIT_BUFSIZE <= WSIZE, it is never too late to
-         * transform a block into a stored block.
-         */
-        _tr_stored_block(s, buf, stored_len, last);
-
-#ifdef FORCE_STATIC
-    } else if (static_lenb >= 0) { /* force static trees */
-#else
-    } else if (s.strategy == Z_FIXED || static_lenb == opt_lenb) {
-#endif
-        send_bits(s, (STATIC_TREES<<1)+last, 3);
-        compress_block(s, (const ct_data *)static_ltree,
-                       (const ct_data *)static_dtree);
-#ifdef DEBUG
-        s.compressed_len += 3 + s.static_len;
-#endif
-    } else {
-        send_bits(s, (DYN_TREES<<1)+last, 3);
-        send_all_trees(s, s.l_desc.max_code+1, s.d_desc.max_code+1,
-                       max_blindex+1);
-        compress_block(s, (const ct_data *)s.dyn_ltree,
-                       (const ct_data *)s.dyn_dtree);
-#ifdef DEBUG
-        s.compressed_len += 3 + s.opt_len;
-#endif
+    s.insert = if s.strstart < MIN_MATCH - 1 { s.strstart } else { MIN_MATCH - 1 };
+    if flush == Flush::Finish {
+        FLUSH_BLOCK!(s, strm, true);
+        return BlockState::FinishDone;
     }
-    Assert (s.compressed_len == s.bits_sent, "bad compressed size");
-    /* The above check is made mod 2^32, for files larger than 512 MB
-     * and uLong implemented on 32 bits.
-     */
-    init_block(s);
-
-    if (last) {
-        bi_windup(s);
-#ifdef DEBUG
-        s.compressed_len += 7;  /* align on byte boundary */
-#endif
+    if s.last_lit != 0 {
+        FLUSH_BLOCK!(s, strm, false);
     }
-    Tracev((stderr,"\ncomprlen %lu(%lu) ", s.compressed_len>>3,
-           s.compressed_len-7*last));
+    BlockState::BlockDone
 }
 
 /* ===========================================================================
- * Save the match info and tally the frequency counts. Return true if
- * the current block must be flushed.
+ * Same as above, but achieves better compression. We use a lazy
+ * evaluation for matches: a match is finally adopted only if there is
+ * no better match at the next window position.
  */
-int ZLIB_INTERNAL _tr_tally (s, dist, lc)
-    DeflateState *s;
-    unsigned dist;  /* distance of matched string */
-    unsigned lc;    /* match length-MIN_MATCH or unmatched char (if dist==0) */
-{
-    s.d_buf[s.last_lit] = (ush)dist;
-    s.l_buf[s.last_lit++] = (uch)lc;
-    if (dist == 0) {
-        /* lc is the unmatched char */
-        s.dyn_ltree[lc].Freq++;
-    } else {
-        s.matches++;
-        /* Here, lc is the match length - MIN_MATCH */
-        dist--;             /* dist = match distance - 1 */
-        Assert((ush)dist < (ush)MAX_DIST(s) &&
-               (ush)lc <= (ush)(MAX_MATCH-MIN_MATCH) &&
-               (ush)d_code(dist) < (ush)D_CODES,  "_tr_tally: bad match");
-
-        s.dyn_ltree[_length_code[lc]+LITERALS+1].Freq++;
-        s.dyn_dtree[d_code(dist)].Freq++;
-    }
+fn deflate_slow(s: &mut Deflater, strm: &mut DeflateStream, flush: Flush) -> BlockState {
+    // Process the input block.
+    loop {
+        // Make sure that we always have enough lookahead, except
+        // at the end of the input file. We need MAX_MATCH bytes
+        // for the next match, plus MIN_MATCH bytes to insert the
+        // string following the next match.
+        if s.lookahead < MIN_LOOKAHEAD {
+            fill_window(s, strm);
+            if s.lookahead < MIN_LOOKAHEAD && flush == Flush::None {
+                return BlockState::NeedMore;
+            }
+            if s.lookahead == 0 {
+                break; // flush the current block
+            }
+        }
 
-#ifdef TRUNCATE_BLOCK
-    /* Try to guess if it is profitable to stop the current block here */
-    if ((s.last_lit & 0x1fff) == 0 && s.level > 2) {
-        /* Compute an upper bound for the compressed length */
-        ulg out_length = (ulg)s.last_lit*8L;
-        ulg in_length = (ulg)((long)s.strstart - s.block_start);
-        int dcode;
-        for (dcode = 0; dcode < D_CODES; dcode++) {
-            out_length += (ulg)s.dyn_dtree[dcode].Freq *
-                (5L+extra_dbits[dcode]);
+        // Insert the string window[strstart .. strstart+2] in the
+        // dictionary, and set hash_head to the head of the hash chain:
+        let mut hash_head = NIL;
+        if s.lookahead >= MIN_MATCH {
+            let str = s.strstart;
+            hash_head = insert_string(s, str);
+        }
+
+        // Find the longest match, discarding those <= prev_length.
+        s.prev_length = s.match_length;
+        s.prev_match = s.match_start;
+        s.match_length = MIN_MATCH - 1;
+
+        if hash_head != NIL && s.prev_length < s.max_lazy_match && s.strstart - hash_head <= max_dist(s) {
+            // To simplify the code, we prevent matches with the string
+            // of window index 0 (in particular we have to avoid a match
+            // of the string with itself at the start of the input file).
+            s.match_length = longest_match(s, hash_head);
+            // longest_match() sets match_start
+
+            if s.match_length <= 5 && (s.strategy == Z_FILTERED
+                || (s.match_length == MIN_MATCH && s.strstart - s.match_start > TOO_FAR)) {
+                // If prev_match is also MIN_MATCH, match_start is garbage
+                // but we will ignore the current match anyway.
+                s.match_length = MIN_MATCH - 1;
+            }
+        }
+        // If there was a match at the previous step and the current
+        // match is not better, output the previous match:
+        if s.prev_length >= MIN_MATCH && s.match_length <= s.prev_length {
+            let max_insert = s.strstart + s.lookahead - MIN_MATCH;
+            // Do not insert strings in hash table beyond this.
+
+            let dist = s.strstart - 1 - s.prev_match;
+            let len = s.prev_length - MIN_MATCH;
+            let bflush = _tr_tally_dist(s, dist, len);
+
+            // Insert in hash table all strings up to the end of the match.
+            // strstart-1 and strstart are already inserted. If there is not
+            // enough lookahead, the last two strings are not inserted in
+            // the hash table.
+            s.lookahead -= s.prev_length - 1;
+            s.prev_length -= 2;
+            loop {
+                s.strstart += 1;
+                if s.strstart <= max_insert {
+                    let str = s.strstart;
+                    insert_string(s, str);
+                }
+                s.prev_length -= 1;
+                if s.prev_length == 0 {
+                    break;
+                }
+            }
+            s.match_available = false;
+            s.match_length = MIN_MATCH - 1;
+            s.strstart += 1;
+
+            if bflush {
+                FLUSH_BLOCK!(s, strm, false);
+            }
+        }
+        else if s.match_available {
+            // If there was no match at the previous position, output a
+            // single literal. If there was a match but the current match
+            // is longer, truncate the previous match to a single literal.
+            let c = s.window[s.strstart - 1];
+            debug!("{}", c as char);
+            if _tr_tally_lit(s, c) {
+                FLUSH_BLOCK_ONLY!(s, strm, false);
+            }
+            s.strstart += 1;
+            s.lookahead -= 1;
+            if strm.avail_out() == 0 {
+                return BlockState::NeedMore;
+            }
+        }
+        else {
+            // There is no previous match to compare with, wait for
+            // the next step to decide.
+            s.match_available = true;
+            s.strstart += 1;
+            s.lookahead -= 1;
         }
-        out_length >>= 3;
-        Tracev((stderr,"\nlast_lit %u, in %ld, out ~%ld(%ld%%) ",
-               s.last_lit, in_length, out_length,
-               100L - out_length*100L/in_length));
-        if (s.matches < s.last_lit/2 && out_length < in_length/2) return 1;
     }
-#endif
-    return (s.last_lit == s.lit_bufsize-1);
-    /* We avoid equality with lit_bufsize because of wraparound at 64K
-     * on 16 bit machines and because stored blocks are restricted to
-     * 64K-1 bytes.
-     */
+    debug_assert!(flush != Flush::None, "no flush?");
+    if s.match_available {
+        let c = s.window[s.strstart - 1];
+        _tr_tally_lit(s, c);
+        s.match_available = false;
+    }
+    s.insert = if s.strstart < MIN_MATCH - 1 { s.strstart } else { MIN_MATCH - 1 };
+    if flush == Flush::Finish {
+        FLUSH_BLOCK!(s, strm, true);
+        return BlockState::FinishDone;
+    }
+    if s.last_lit != 0 {
+        FLUSH_BLOCK!(s, strm, false);
+    }
+    BlockState::BlockDone
 }
 
 /* ===========================================================================
- * Send the block data compressed using the given Huffman trees
+ * For Z_RLE, simply look for runs of bytes, generate matches only of distance
+ * one.  Do not maintain a hash table.  (It will be regenerated if this run of
+ * deflate switches away from Z_RLE.)
  */
-local void compress_block(s, ltree, dtree)
-    DeflateState *s;
-    const ct_data *ltree; /* literal tree */
-    const ct_data *dtree; /* distance tree */
-{
-    unsigned dist;      /* distance of matched string */
-    int lc;             /* match length or unmatched char (if dist == 0) */
-    unsigned lx = 0;    /* running index in l_buf */
-    unsigned code;      /* the code to send */
-    int extra;          /* number of extra bits to send */
-
-    if (s.last_lit != 0) do {
-        dist = s.d_buf[lx];
-        lc = s.l_buf[lx++];
-        if (dist == 0) {
-            send_code(s, lc, ltree); /* send a literal byte */
-            Tracecv(isgraph(lc), (stderr," '%c' ", lc));
-        } else {
-            /* Here, lc is the match length - MIN_MATCH */
-            code = _length_code[lc];
-            send_code(s, code+LITERALS+1, ltree); /* send the length code */
-            extra = extra_lbits[code];
-            if (extra != 0) {
-                lc -= base_length[code];
-                send_bits(s, lc, extra);       /* send the extra length bits */
+fn deflate_rle(s: &mut Deflater, strm: &mut DeflateStream, flush: Flush) -> BlockState {
+    loop {
+        // Make sure that we always have enough lookahead, except
+        // at the end of the input file. We need MAX_MATCH bytes
+        // for the longest run, plus one for the unrolled loop.
+        if s.lookahead <= MAX_MATCH {
+            fill_window(s, strm);
+            if s.lookahead <= MAX_MATCH && flush == Flush::None {
+                return BlockState::NeedMore;
             }
-            dist--; /* dist is now the match distance - 1 */
-            code = d_code(dist);
-            Assert (code < D_CODES, "bad d_code");
-
-            send_code(s, code, dtree);       /* send the distance code */
-            extra = extra_dbits[code];
-            if (extra != 0) {
-                dist -= base_dist[code];
-                send_bits(s, dist, extra);   /* send the extra distance bits */
+            if s.lookahead == 0 {
+                break; // flush the current block
             }
-        } /* literal or match pair ? */
-
-        /* Check that the overlay between pending_buf and d_buf+l_buf is ok: */
-        Assert((usize)(s.pending) < s.lit_bufsize + 2*lx,
-               "pendingBuf overflow");
-
-    } while (lx < s.last_lit);
+        }
 
-    send_code(s, END_BLOCK, ltree);
-}
+        // See how many times the previous byte repeats
+        s.match_length = 0;
+        if s.lookahead >= MIN_MATCH && s.strstart > 0 {
+            let window = s.window.as_slice();
+            let scan = s.strstart;
+            let prev = window[scan - 1];
+            if prev == window[scan] && prev == window[scan + 1] && prev == window[scan + 2] {
+                let mut len = MIN_MATCH;
+                while len < MAX_MATCH && window[scan + len] == prev {
+                    len += 1;
+                }
+                s.match_length = if len > s.lookahead { s.lookahead } else { len };
+            }
+            debug_assert!(s.strstart + MAX_MATCH <= s.window_size, "wild scan");
+        }
 
-/* ===========================================================================
- * Check if the data type is TEXT or BINARY, using the following algorithm:
- * - TEXT if the two conditions below are satisfied:
- *    a) There are no non-portable control characters belonging to the
- *       "black list" (0..6, 14..25, 28..31).
- *    b) There is at least one printable character belonging to the
- *       "white list" (9 {TAB}, 10 {LF}, 13 {CR}, 32..255).
- * - BINARY otherwise.
- * - The following partially-portable control characters form a
- *   "gray list" that is ignored in this detection algorithm:
- *   (7 {BEL}, 8 {BS}, 11 {VT}, 12 {FF}, 26 {SUB}, 27 {ESC}).
- * IN assertion: the fields Freq of dyn_ltree are set.
- */
-local int detect_data_type(s)
-    DeflateState *s;
-{
-    /* black_mask is the bit mask of black-listed bytes
-     * set bits 0..6, 14..25, and 28..31
-     * 0xf3ffc07f = binary 11110011111111111100000001111111
-     */
-    unsigned long black_mask = 0xf3ffc07fUL;
-    int n;
-
-    /* Check for non-textual ("black-listed") bytes. */
-    for (n = 0; n <= 31; n++, black_mask >>= 1)
-        if ((black_mask & 1) && (s.dyn_ltree[n].Freq != 0))
-            return Z_BINARY;
-
-    /* Check for textual ("white-listed") bytes. */
-    if (s.dyn_ltree[9].Freq != 0 || s.dyn_ltree[10].Freq != 0
-            || s.dyn_ltree[13].Freq != 0)
-        return Z_TEXT;
-    for (n = 32; n < LITERALS; n++)
-        if (s.dyn_ltree[n].Freq != 0)
-            return Z_TEXT;
-
-    /* There are no "black-listed" or "white-listed" bytes:
-     * this stream either is empty or has tolerated ("gray-listed") bytes only.
-     */
-    return Z_BINARY;
-}
-*/
+        // Emit match if have run of MIN_MATCH or longer, else emit literal
+        let bflush;
+        if s.match_length >= MIN_MATCH {
+            let len = s.match_length - MIN_MATCH;
+            bflush = _tr_tally_dist(s, 1, len);
 
-/*
-/* ===========================================================================
- * Flush the bit buffer, keeping at most 7 bits in it.
- */
-local void bi_flush(s)
-    DeflateState *s;
-{
-    if (s.bi_valid == 16) {
-        put_short(s, s.bi_buf);
-        s.bi_buf = 0;
-        s.bi_valid = 0;
-    } else if (s.bi_valid >= 8) {
-        put_byte(s, (Byte)s.bi_buf);
-        s.bi_buf >>= 8;
-        s.bi_valid -= 8;
+            s.lookahead -= s.match_length;
+            s.strstart += s.match_length;
+            s.match_length = 0;
+        }
+        else {
+            // No match, output a literal byte
+            let c = s.window[s.strstart];
+            bflush = _tr_tally_lit(s, c);
+            s.lookahead -= 1;
+            s.strstart += 1;
+        }
+        if bflush {
+            FLUSH_BLOCK!(s, strm, false);
+        }
     }
-}
-
-/* ===========================================================================
- * Flush the bit buffer and align the output on a byte boundary
- */
-local void bi_windup(s)
-    DeflateState *s;
-{
-    if (s.bi_valid > 8) {
-        put_short(s, s.bi_buf);
-    } else if (s.bi_valid > 0) {
-        put_byte(s, (Byte)s.bi_buf);
+    s.insert = 0;
+    if flush == Flush::Finish {
+        FLUSH_BLOCK!(s, strm, true);
+        return BlockState::FinishDone;
+    }
+    if s.last_lit != 0 {
+        FLUSH_BLOCK!(s, strm, false);
     }
-    s.bi_buf = 0;
-    s.bi_valid = 0;
-#ifdef DEBUG
-    s.bits_sent = (s.bits_sent+7) & ~7;
-#endif
+    BlockState::BlockDone
 }
 
 /* ===========================================================================
- * Copy a stored block, storing first the length and its
- * one's complement if requested.
+ * For Z_HUFFMAN_ONLY, do not look for matches.  Do not maintain a hash table.
+ * (It will be regenerated if this run of deflate switches away from Huffman.)
  */
-local void copy_block(s, buf, len, header)
-    DeflateState *s;
-    charf    *buf;    /* the input data */
-    unsigned len;     /* its length */
-    int      header;  /* true if block header must be written */
-{
-    bi_windup(s);        /* align on byte boundary */
-
-    if (header) {
-        put_short(s, (ush)len);
-        put_short(s, (ush)~len);
-#ifdef DEBUG
-        s.bits_sent += 2*16;
-#endif
+fn deflate_huff(s: &mut Deflater, strm: &mut DeflateStream, flush: Flush) -> BlockState {
+    loop {
+        // Make sure that we have a literal to write.
+        if s.lookahead == 0 {
+            fill_window(s, strm);
+            if s.lookahead == 0 {
+                if flush == Flush::None {
+                    return BlockState::NeedMore;
+                }
+                break; // flush the current block
+            }
+        }
+
+        // Output a literal byte
+        s.match_length = 0;
+        let c = s.window[s.strstart];
+        let bflush = _tr_tally_lit(s, c);
+        s.lookahead -= 1;
+        s.strstart += 1;
+        if bflush {
+            FLUSH_BLOCK!(s, strm, false);
+        }
     }
-#ifdef DEBUG
-    s.bits_sent += (ulg)len<<3;
-#endif
-    while (len--) {
-        put_byte(s, *buf++);
+    s.insert = 0;
+    if flush == Flush::Finish {
+        FLUSH_BLOCK!(s, strm, true);
+        return BlockState::FinishDone;
     }
+    if s.last_lit != 0 {
+        FLUSH_BLOCK!(s, strm, false);
+    }
+    BlockState::BlockDone
 }
-
-
-
-*/
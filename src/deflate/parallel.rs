@@ -0,0 +1,286 @@
+// Multi-threaded GZIP compression, in the manner of pigz.
+
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Write, ErrorKind};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::io_error;
+use crate::crc::crc32;
+use crate::crc::crc32_combine;
+use crate::Flush;
+use crate::Z_DEFAULT_COMPRESSION;
+use crate::Z_DEFAULT_STRATEGY;
+use crate::WINDOW_BITS_MAX;
+use crate::deflate::{Deflater, DeflateResult};
+use super::{OS_CODE, gzip_xfl};
+
+/// Default size of the blocks that are compressed in parallel.
+pub const PARALLEL_BLOCK_SIZE_DEFAULT: usize = 0x20000;
+
+// Amount of data before each block that is used as its dictionary
+const DICTIONARY_SIZE: usize = 1 << WINDOW_BITS_MAX;
+
+// A block to be compressed by a worker thread
+struct Job {
+    seq: u64,                   // position of the block in the stream
+    data: Vec<u8>,
+    dictionary: Vec<u8>,        // the data before the block, up to DICTIONARY_SIZE bytes
+    last: bool,                 // true for the last block, which ends the DEFLATE stream
+}
+
+// A block that has been compressed by a worker thread
+struct CompressedBlock {
+    output: Vec<u8>,            // raw DEFLATE data
+    crc: u32,                   // CRC-32 of the uncompressed data
+    len: u64,                   // length of the uncompressed data
+}
+
+/// Writes a GZIP stream to `dst`, compressing it on several threads.
+///
+/// The data is split into blocks (128 KiB by default), which are compressed in parallel.
+/// Each block is compressed as raw DEFLATE data, using the 32 KiB of data before it as a
+/// preset dictionary, and ends with a sync flush so that the compressed blocks can be joined.
+/// The result is a single GZIP member, which any GZIP decoder can read.  The output is
+/// slightly larger than that of `DeflateWriter`, by a few bytes per block.
+///
+/// Call `finish()` when all of the data has been written.  This writes the last block and
+/// the GZIP trailer, stops the threads, and returns the inner writer.
+pub struct ParallelGzipWriter<W> {
+    dst: W,
+    level: i32,
+    block_size: usize,
+
+    block: Vec<u8>,             // data for the next block
+    dictionary: Vec<u8>,        // the last DICTIONARY_SIZE bytes of data before 'block'
+    crc: u32,                   // CRC-32 of the data in the blocks written so far
+    total_in: u64,              // length of the data in the blocks written so far
+
+    next_seq: u64,              // sequence number of the next block to submit
+    next_write: u64,            // sequence number of the next block to write to 'dst'
+    max_pending: u64,           // limit on blocks that have been submitted but not written
+    done: BTreeMap<u64, CompressedBlock>,   // compressed blocks that cannot be written yet
+
+    // Dropping 'jobs' stops the workers.
+    jobs: Sender<Job>,
+    results: Receiver<(u64, io::Result<CompressedBlock>)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<W:Write> ParallelGzipWriter<W> {
+    /// Creates a new ParallelGzipWriter which writes a GZIP stream to `dst`, using `threads`
+    /// threads.  `level` has the same meaning as in `Deflater::new_zlib()`.
+    pub fn new(level: i32, threads: usize, dst: W) -> ParallelGzipWriter<W> {
+        ParallelGzipWriter::new_with_block_size(level, threads, PARALLEL_BLOCK_SIZE_DEFAULT, dst)
+    }
+
+    /// Creates a new ParallelGzipWriter which compresses blocks of `block_size` bytes.
+    /// Smaller blocks allow more parallelism for small inputs, but compress worse.
+    pub fn new_with_block_size(level: i32, threads: usize, block_size: usize, dst: W) -> ParallelGzipWriter<W> {
+        debug!("ParallelGzipWriter::new(): level={} threads={} block_size={}", level, threads, block_size);
+        assert!(level == Z_DEFAULT_COMPRESSION || (level >= 0 && level <= 9));
+        assert!(threads >= 1);
+        assert!(block_size >= 1);
+
+        let (jobs, job_receiver) = channel::<Job>();
+        let (result_sender, results) = channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let mut workers: Vec<JoinHandle<()>> = Vec::new();
+        for _ in 0..threads {
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            workers.push(thread::spawn(move || {
+                let mut deflater = Deflater::new_raw(level, WINDOW_BITS_MAX);
+                let mut outbuf: Vec<u8> = vec![0u8; 0x4000];
+                loop {
+                    // The lock is only held while waiting for a job.
+                    let job = match job_receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break
+                    };
+                    let result = compress_block(&mut deflater, &mut outbuf, &job);
+                    if result_sender.send((job.seq, result)).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+
+        ParallelGzipWriter {
+            dst: dst,
+            level: level,
+            block_size: block_size,
+            block: Vec::with_capacity(block_size),
+            dictionary: Vec::with_capacity(DICTIONARY_SIZE),
+            crc: crc32(0, &[]),
+            total_in: 0,
+            next_seq: 0,
+            next_write: 0,
+            max_pending: 2 * threads as u64,
+            done: BTreeMap::new(),
+            jobs: jobs,
+            results: results,
+            workers: workers,
+        }
+    }
+
+    pub fn inner(&mut self) -> &mut W {
+        &mut self.dst
+    }
+
+    /// Compresses the last block, writes the GZIP trailer, and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.submit_block(true)?;
+        self.wait_for_blocks(0)?;
+
+        let mut trailer: Vec<u8> = Vec::with_capacity(8);
+        trailer.extend_from_slice(&self.crc.to_le_bytes());
+        trailer.extend_from_slice(&(self.total_in as u32).to_le_bytes());
+        self.dst.write_all(&trailer)?;
+        self.dst.flush()?;
+        debug!("ParallelGzipWriter::finish(): {} blocks, total_in={} crc=0x{:08x}", self.next_seq, self.total_in, self.crc);
+
+        // Dropping the job sender stops the workers.
+        let ParallelGzipWriter { dst, jobs, workers, .. } = self;
+        drop(jobs);
+        for worker in workers {
+            if worker.join().is_err() {
+                return Err(worker_failed());
+            }
+        }
+        Ok(dst)
+    }
+
+    // Sends the data in 'block' to a worker.  If too many blocks are pending, first waits
+    // for some of them to be written.
+    fn submit_block(&mut self, last: bool) -> io::Result<()> {
+        let limit = self.max_pending - 1;
+        self.wait_for_blocks(limit)?;
+
+        let data = mem::replace(&mut self.block, Vec::with_capacity(self.block_size));
+
+        // The dictionary for the next block is the end of this block, and of the blocks
+        // before it if this block is short.
+        let dictionary = self.dictionary.clone();
+        self.dictionary.extend_from_slice(&data[data.len() - min(data.len(), DICTIONARY_SIZE)..]);
+        if self.dictionary.len() > DICTIONARY_SIZE {
+            let excess = self.dictionary.len() - DICTIONARY_SIZE;
+            self.dictionary = self.dictionary[excess..].to_vec();
+        }
+
+        let job = Job {
+            seq: self.next_seq,
+            data: data,
+            dictionary: dictionary,
+            last: last,
+        };
+        self.next_seq += 1;
+        if self.jobs.send(job).is_err() {
+            return Err(worker_failed());
+        }
+        Ok(())
+    }
+
+    // Writes compressed blocks to 'dst', in order, until no more than 'limit' blocks are
+    // pending.
+    fn wait_for_blocks(&mut self, limit: u64) -> io::Result<()> {
+        while self.next_seq - self.next_write > limit {
+            let (seq, result) = match self.results.recv() {
+                Ok(result) => result,
+                Err(_) => return Err(worker_failed())
+            };
+            self.done.insert(seq, result?);
+            while let Some(block) = self.done.remove(&self.next_write) {
+                if self.next_write == 0 {
+                    self.write_header()?;
+                }
+                self.dst.write_all(&block.output)?;
+                self.crc = crc32_combine(self.crc, block.crc, block.len);
+                self.total_in += block.len;
+                self.next_write += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes a GZIP header like the one that Deflater::new_gzip() writes.
+    fn write_header(&mut self) -> io::Result<()> {
+        let level = if self.level == Z_DEFAULT_COMPRESSION { 6 } else { self.level };
+        let xfl = gzip_xfl(level as usize, Z_DEFAULT_STRATEGY);
+        self.dst.write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, xfl, OS_CODE])
+    }
+}
+
+impl<W:Write> Write for ParallelGzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pos: usize = 0;
+        while pos < buf.len() {
+            let n = min(buf.len() - pos, self.block_size - self.block.len());
+            self.block.extend_from_slice(&buf[pos..pos + n]);
+            pos += n;
+            if self.block.len() == self.block_size {
+                self.submit_block(false)?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Compresses and writes all data that has been written so far, and then flushes the
+    /// inner writer.  This ends the current block early, which degrades compression a little.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.block.is_empty() {
+            self.submit_block(false)?;
+        }
+        self.wait_for_blocks(0)?;
+        self.dst.flush()
+    }
+}
+
+// Compresses one block, and computes its CRC-32, on a worker thread.  Every block but the
+// last ends with a sync flush, so that the next block starts on a byte boundary.
+fn compress_block(deflater: &mut Deflater, outbuf: &mut [u8], job: &Job) -> io::Result<CompressedBlock> {
+    deflater.reset();
+    if !job.dictionary.is_empty() {
+        deflater.set_dictionary(&job.dictionary);
+    }
+    let flush = if job.last { Flush::Finish } else { Flush::SyncFlush };
+    let mut output: Vec<u8> = Vec::with_capacity(job.data.len() / 2);
+    let mut inpos: usize = 0;
+    loop {
+        match deflater.deflate(flush, &job.data[inpos..], outbuf) {
+            DeflateResult::Encoded(in_bytes, out_bytes) => {
+                inpos += in_bytes;
+                output.extend_from_slice(&outbuf[..out_bytes]);
+                if !job.last && inpos == job.data.len() && out_bytes < outbuf.len() {
+                    break;
+                }
+            }
+            DeflateResult::Finished(_, out_bytes) if job.last => {
+                output.extend_from_slice(&outbuf[..out_bytes]);
+                break;
+            }
+            DeflateResult::BufError if !job.last && inpos == job.data.len() => {
+                // the flush completed exactly at the end of the last call
+                break;
+            }
+            result => {
+                warn!("compress_block: deflate returned {:?}", result);
+                return Err(io_error(ErrorKind::Other, "deflate failed", Some(format!("{:?}", result))));
+            }
+        }
+    }
+    Ok(CompressedBlock {
+        output: output,
+        crc: crc32(0, &job.data),
+        len: job.data.len() as u64,
+    })
+}
+
+fn worker_failed() -> io::Error {
+    io_error(ErrorKind::Other, "compression thread failed", None)
+}
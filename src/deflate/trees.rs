@@ -0,0 +1,1091 @@
+/* trees.c -- output deflated data using Huffman coding
+ * Copyright (C) 1995-2012 Jean-loup Gailly
+ * detect_data_type() function provided freely by Cosmin Truta, 2006
+ * For conditions of distribution and use, see copyright notice in zlib.h
+ */
+
+/*
+ *  ALGORITHM
+ *
+ *      The "deflation" process uses several Huffman trees. The more
+ *      common source values are represented by shorter bit sequences.
+ *
+ *      Each code tree is stored in a compressed form which is itself
+ * a Huffman encoding of the lengths of all the code strings (in
+ * ascending order by source values).  The actual code strings are
+ * reconstructed from the lengths in the inflate process, as described
+ * in the deflate specification.
+ *
+ *  REFERENCES
+ *
+ *      Deutsch, L.P.,"'Deflate' Compressed Data Format Specification".
+ *      Available in ftp.uu.net:/pub/archiving/zip/doc/deflate-1.1.doc
+ *
+ *      Storer, James A.
+ *          Data Compression:  Methods and Theory, pp. 49-50.
+ *          Computer Science Press, 1988.  ISBN 0-7167-8156-5.
+ *
+ *      Sedgewick, R.
+ *          Algorithms, p290.
+ *          Addison-Wesley, 1983. ISBN 0-201-06672-6.
+ */
+
+use std::mem;
+
+use super::Deflater;
+use crate::treedefs::{
+    CtData,
+    StaticTreeDesc,
+    LITERALS,
+    L_CODES,
+    MAX_BITS,
+    EXTRA_LBITS,
+    EXTRA_DBITS,
+    EXTRA_BLBITS,
+    BL_CODES,
+    BL_ORDER,
+    D_CODES,
+};
+use crate::statictrees::{
+    STATIC_LTREE_CODES,
+    STATIC_LTREE_LENGTHS,
+    STATIC_DTREE_CODES,
+    STATIC_DTREE_LENGTHS,
+    DIST_CODE,
+    LENGTH_CODE,
+    BASE_LENGTH,
+    BASE_DIST,
+};
+use crate::{STORED_BLOCK, STATIC_TREES, DYN_TREES};
+use crate::{Z_BINARY, Z_TEXT, Z_UNKNOWN, Z_FIXED};
+
+/* ===========================================================================
+ * Constants
+ */
+
+/// Maximum heap size
+pub const HEAP_SIZE: usize = 2 * L_CODES + 1;
+
+/// Bit length codes must not exceed `MAX_BL_BITS` bits
+const MAX_BL_BITS: usize = 7;
+
+/// End of block literal code
+const END_BLOCK: usize = 256;
+
+/// Repeat previous bit length 3-6 times (2 bits of repeat count)
+const REP_3_6: usize = 16;
+
+/// Repeat a zero length 3-10 times  (3 bits of repeat count)
+const REPZ_3_10: usize = 17;
+
+/// Repeat a zero length 11-138 times  (7 bits of repeat count)
+const REPZ_11_138: usize = 18;
+
+/// Size of bit buffer in bi_buf
+const BUF_SIZE: usize = 16;
+
+/// Index within the heap array of least frequent node in the Huffman tree
+const SMALLEST: usize = 1;
+
+pub static STATIC_L_DESC: StaticTreeDesc = StaticTreeDesc {
+    lengths: &STATIC_LTREE_LENGTHS,
+    codes: &STATIC_LTREE_CODES,
+    extra_bits: &EXTRA_LBITS,
+    extra_base: LITERALS + 1,
+    elems: L_CODES,
+    max_length: MAX_BITS
+};
+
+pub static STATIC_D_DESC: StaticTreeDesc = StaticTreeDesc {
+    lengths: &STATIC_DTREE_LENGTHS,
+    codes: &STATIC_DTREE_CODES,
+    extra_bits: &EXTRA_DBITS,
+    extra_base: 0,
+    elems: D_CODES,
+    max_length: MAX_BITS
+};
+
+pub static STATIC_BL_DESC: StaticTreeDesc = StaticTreeDesc {
+    lengths: &[],
+    codes: &[],
+    extra_bits: &EXTRA_BLBITS,
+    extra_base: 0,
+    elems: BL_CODES,
+    max_length: MAX_BL_BITS
+};
+
+// was tree_desc
+#[derive(Copy,Clone)]
+pub struct TreeDesc {
+    pub max_code: usize,                        // largest code with non zero frequency
+    pub stat_desc: &'static StaticTreeDesc,     // the corresponding static tree
+}
+
+impl TreeDesc {
+    pub fn new(stat_desc: &'static StaticTreeDesc) -> TreeDesc {
+        TreeDesc {
+            max_code: 0,
+            stat_desc: stat_desc
+        }
+    }
+}
+
+/// Identifies one of the three dynamic trees owned by the compressor.  The trees are
+/// temporarily moved out of the `Deflater` while they are being built or sent, so that
+/// the heap and the bit buffer (which also live in the `Deflater`) can be borrowed at
+/// the same time.
+#[derive(Copy,Clone,PartialEq,Eq)]
+enum TreeKind {
+    Literal,
+    Distance,
+    BitLength,
+}
+
+fn take_tree(s: &mut Deflater, kind: TreeKind) -> (Vec<CtData>, TreeDesc) {
+    match kind {
+        TreeKind::Literal => (mem::take(&mut s.dyn_ltree), s.l_desc),
+        TreeKind::Distance => (mem::take(&mut s.dyn_dtree), s.d_desc),
+        TreeKind::BitLength => (mem::take(&mut s.bl_tree), s.bl_desc),
+    }
+}
+
+fn put_tree(s: &mut Deflater, kind: TreeKind, tree: Vec<CtData>, desc: TreeDesc) {
+    match kind {
+        TreeKind::Literal => { s.dyn_ltree = tree; s.l_desc = desc; }
+        TreeKind::Distance => { s.dyn_dtree = tree; s.d_desc = desc; }
+        TreeKind::BitLength => { s.bl_tree = tree; s.bl_desc = desc; }
+    }
+}
+
+// was d_code
+// Mapping from a distance to a distance code. dist is the distance - 1 and
+// must not have side effects. DIST_CODE[256] and DIST_CODE[257] are never
+// used.
+#[inline]
+pub fn d_code(dist: usize) -> usize {
+    if dist < 256 {
+        DIST_CODE[dist] as usize
+    }
+    else {
+        DIST_CODE[256 + (dist >> 7)] as usize
+    }
+}
+
+/* ===========================================================================
+ * Output a byte on the stream.
+ * IN assertion: there is enough room in pending_buf.
+ */
+#[inline]
+pub fn put_byte(s: &mut Deflater, c: u8) {
+    s.pending_buf[s.pending] = c;
+    s.pending += 1;
+}
+
+/* ===========================================================================
+ * Output a short LSB first on the stream.
+ * IN assertion: there is enough room in pendingBuf.
+ */
+#[inline]
+pub fn put_short(s: &mut Deflater, w: u16) {
+    put_byte(s, (w & 0xff) as u8);
+    put_byte(s, (w >> 8) as u8);
+}
+
+/* ===========================================================================
+ * Send a value on a given number of bits.
+ * IN assertion: length <= 16 and value fits in length bits.
+ */
+fn send_bits(s: &mut Deflater, value: u32, length: usize) {
+    debug_assert!(length > 0 && length <= 15);
+    s.bits_sent += length as u64;
+
+    // If not enough room in bi_buf, use (valid) bits from bi_buf and
+    // (16 - bi_valid) bits from value, leaving (width - (16-bi_valid))
+    // unused bits in value.  The shifts are done in 32 bits, because bi_valid
+    // can be 16, which is not a valid shift count for a u16.
+    if s.bi_valid > BUF_SIZE - length {
+        s.bi_buf |= ((value << s.bi_valid) & 0xffff) as u16;
+        let bi_buf = s.bi_buf;
+        put_short(s, bi_buf);
+        s.bi_buf = (value >> (BUF_SIZE - s.bi_valid)) as u16;
+        s.bi_valid = s.bi_valid + length - BUF_SIZE;
+    }
+    else {
+        s.bi_buf |= ((value << s.bi_valid) & 0xffff) as u16;
+        s.bi_valid += length;
+    }
+}
+
+// Send a code of the given tree. c and tree must not have side effects
+#[inline]
+fn send_code(s: &mut Deflater, c: usize, tree: &[CtData]) {
+    Tracevv!("\ncd {:3} ", c);
+    send_bits(s, tree[c].fc /*Code*/ as u32, tree[c].dl /*Len*/ as usize);
+}
+
+// Send a code of one of the static trees.
+#[inline]
+fn send_static_code(s: &mut Deflater, c: usize, stat_desc: &StaticTreeDesc) {
+    send_bits(s, stat_desc.codes[c] as u32, stat_desc.lengths[c] as usize);
+}
+
+/* ===========================================================================
+ * Initialize the tree data structures for a new zlib stream.
+ */
+pub fn _tr_init(s: &mut Deflater) {
+    s.l_desc = TreeDesc::new(&STATIC_L_DESC);
+    s.d_desc = TreeDesc::new(&STATIC_D_DESC);
+    s.bl_desc = TreeDesc::new(&STATIC_BL_DESC);
+
+    s.bi_buf = 0;
+    s.bi_valid = 0;
+    s.compressed_len = 0;
+    s.bits_sent = 0;
+
+    // Initialize the first block of the first file:
+    init_block(s);
+}
+
+/* ===========================================================================
+ * Initialize a new block.
+ */
+fn init_block(s: &mut Deflater) {
+    // Initialize the trees.
+    for n in 0..L_CODES { s.dyn_ltree[n].fc /*Freq*/ = 0; }
+    for n in 0..D_CODES { s.dyn_dtree[n].fc /*Freq*/ = 0; }
+    for n in 0..BL_CODES { s.bl_tree[n].fc /*Freq*/ = 0; }
+
+    s.dyn_ltree[END_BLOCK].fc /*Freq*/ = 1;
+    s.opt_len = 0;
+    s.static_len = 0;
+    s.last_lit = 0;
+    s.matches = 0;
+}
+
+/* ===========================================================================
+ * Compares two subtrees, using the tree depth as tie breaker when
+ * the subtrees have equal frequency. This minimizes the worst case length.
+ */
+#[inline]
+fn smaller(tree: &[CtData], n: usize, m: usize, depth: &[u8]) -> bool {
+    tree[n].fc < tree[m].fc || (tree[n].fc == tree[m].fc && depth[n] <= depth[m])
+}
+
+/* ===========================================================================
+ * Restore the heap property by moving down the tree starting at node k,
+ * exchanging a node with the smallest of its two sons if necessary, stopping
+ * when the heap property is re-established (each father smaller than its
+ * two sons).
+ */
+fn pqdownheap(
+    s: &mut Deflater,
+    tree: &[CtData],        /* the tree to restore */
+    k: usize)               /* node to move down */
+{
+    let mut k = k;
+    let v = s.heap[k];
+    let mut j = k << 1;  /* left son of k */
+    while j <= s.heap_len {
+        /* Set j to the smallest of the two sons: */
+        if j < s.heap_len && smaller(tree, s.heap[j + 1], s.heap[j], &s.depth) {
+            j += 1;
+        }
+        /* Exit if v is smaller than both sons */
+        if smaller(tree, v, s.heap[j], &s.depth) {
+            break;
+        }
+
+        /* Exchange v with the smallest son */
+        s.heap[k] = s.heap[j];
+        k = j;
+
+        /* And continue down the tree, setting j to the left son of k */
+        j <<= 1;
+    }
+    s.heap[k] = v;
+}
+
+/* ===========================================================================
+ * Remove the smallest element from the heap and recreate the heap with
+ * one less element. Updates heap and heap_len.
+ */
+fn pqremove(s: &mut Deflater, tree: &[CtData]) -> usize {
+    let top = s.heap[SMALLEST];
+    s.heap[SMALLEST] = s.heap[s.heap_len];
+    s.heap_len -= 1;
+    pqdownheap(s, tree, SMALLEST);
+    top
+}
+
+/* ===========================================================================
+ * Compute the optimal bit lengths for a tree and update the total bit length
+ * for the current block.
+ * IN assertion: the fields freq and dad are set, heap[heap_max] and
+ *    above are the tree nodes sorted by increasing frequency.
+ * OUT assertions: the field len is set to the optimal bit length, the
+ *     array bl_count contains the frequencies for each bit length.
+ *     The length opt_len is updated; static_len is also updated if stree is
+ *     not null.
+ */
+fn gen_bitlen(s: &mut Deflater, tree: &mut [CtData], desc: &TreeDesc) {
+    let max_code = desc.max_code;
+    let stree = desc.stat_desc.lengths;
+    let extra = desc.stat_desc.extra_bits;
+    let base = desc.stat_desc.extra_base;
+    let max_length = desc.stat_desc.max_length;
+    let mut overflow: isize = 0;   /* number of elements with bit length too large */
+
+    for bits in 0..MAX_BITS + 1 {
+        s.bl_count[bits] = 0;
+    }
+
+    /* In a first pass, compute the optimal bit lengths (which may
+     * overflow in the case of the bit length tree).
+     */
+    tree[s.heap[s.heap_max]].dl /*Len*/ = 0; /* root of the heap */
+
+    let mut h = s.heap_max + 1;
+    while h < HEAP_SIZE {
+        let n = s.heap[h];
+        h += 1;
+        let mut bits = tree[tree[n].dl /*Dad*/ as usize].dl /*Len*/ as usize + 1;
+        if bits > max_length {
+            bits = max_length;
+            overflow += 1;
+        }
+        tree[n].dl /*Len*/ = bits as u16;
+        /* We overwrite tree[n].Dad which is no longer needed */
+
+        if n > max_code {
+            continue; /* not a leaf node */
+        }
+
+        s.bl_count[bits] += 1;
+        let xbits = if n >= base { extra[n - base] as usize } else { 0 };
+        let f = tree[n].fc /*Freq*/ as isize;
+        s.opt_len += f * (bits + xbits) as isize;
+        if stree.len() != 0 {
+            s.static_len += f * (stree[n] as usize + xbits) as isize;
+        }
+    }
+    if overflow == 0 {
+        return;
+    }
+
+    debug!("bit length overflow");
+    /* This happens for example on obj2 and pic of the Calgary corpus */
+
+    /* Find the first bit length which could increase: */
+    loop {
+        let mut bits = max_length - 1;
+        while s.bl_count[bits] == 0 {
+            bits -= 1;
+        }
+        s.bl_count[bits] -= 1;      /* move one leaf down the tree */
+        s.bl_count[bits + 1] += 2;  /* move one overflow item as its brother */
+        s.bl_count[max_length] -= 1;
+        /* The brother of the overflow item also moves one step up,
+         * but this does not affect bl_count[max_length]
+         */
+        overflow -= 2;
+        if overflow <= 0 {
+            break;
+        }
+    }
+
+    /* Now recompute all bit lengths, scanning in increasing frequency.
+     * h is still equal to HEAP_SIZE. (It is simpler to reconstruct all
+     * lengths instead of fixing only the wrong ones. This idea is taken
+     * from 'ar' written by Haruhiko Okumura.)
+     */
+    let mut bits = max_length;
+    while bits != 0 {
+        let mut n = s.bl_count[bits] as usize;
+        while n != 0 {
+            h -= 1;
+            let m = s.heap[h];
+            if m > max_code {
+                continue;
+            }
+            if tree[m].dl /*Len*/ as usize != bits {
+                debug!("code {} bits {}.{}", m, tree[m].dl, bits);
+                s.opt_len += (bits as isize - tree[m].dl as isize) * (tree[m].fc as isize);
+                tree[m].dl /*Len*/ = bits as u16;
+            }
+            n -= 1;
+        }
+        bits -= 1;
+    }
+}
+
+/* ===========================================================================
+ * Generate the codes for a given tree and bit counts (which need not be
+ * optimal).
+ * IN assertion: the array bl_count contains the bit length statistics for
+ * the given tree and the field len is set for all tree elements.
+ * OUT assertion: the field code is set for all tree elements of non
+ *     zero code length.
+ */
+fn gen_codes(tree: &mut [CtData], max_code: usize, bl_count: &[u16]) {
+    let mut next_code = [0u16; MAX_BITS + 1]; /* next code value for each bit length */
+    let mut code: u16 = 0;                     /* running code value */
+
+    /* The distribution counts are first used to generate the code values
+     * without bit reversal.
+     */
+    for bits in 1..MAX_BITS + 1 {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    /* Check that the bit counts in bl_count are consistent. The last code
+     * must be all ones.
+     */
+    for n in 0..max_code + 1 {
+        let len = tree[n].dl /*Len*/ as usize;
+        if len == 0 {
+            continue;
+        }
+        /* Now reverse the bits */
+        tree[n].fc /*Code*/ = bi_reverse(next_code[len] as u32, len) as u16;
+        next_code[len] += 1;
+    }
+}
+
+/* ===========================================================================
+ * Construct one Huffman tree and assigns the code bit strings and lengths.
+ * Update the total bit length for the current block.
+ * IN assertion: the field freq is set for all tree elements.
+ * OUT assertions: the fields len and code are set to the optimal bit length
+ *     and corresponding code. The length opt_len is updated; static_len is
+ *     also updated if stree is not null. The field max_code is set.
+ */
+fn build_tree(s: &mut Deflater, kind: TreeKind) {
+    let (mut tree, mut desc) = take_tree(s, kind);
+    build_tree_inner(s, tree.as_mut_slice(), &mut desc);
+    put_tree(s, kind, tree, desc);
+}
+
+fn build_tree_inner(s: &mut Deflater, tree: &mut [CtData], desc: &mut TreeDesc) {
+    let stree = desc.stat_desc.lengths;
+    let elems = desc.stat_desc.elems;
+    let mut max_code: isize = -1; /* largest code with non zero frequency */
+
+    /* Construct the initial heap, with least frequent element in
+     * heap[SMALLEST]. The sons of heap[n] are heap[2*n] and heap[2*n+1].
+     * heap[0] is not used.
+     */
+    s.heap_len = 0;
+    s.heap_max = HEAP_SIZE;
+
+    for n in 0..elems {
+        if tree[n].fc /*Freq*/ != 0 {
+            s.heap_len += 1;
+            s.heap[s.heap_len] = n;
+            max_code = n as isize;
+            s.depth[n] = 0;
+        }
+        else {
+            tree[n].dl /*Len*/ = 0;
+        }
+    }
+
+    /* The pkzip format requires that at least one distance code exists,
+     * and that at least one bit should be sent even if there is only one
+     * possible code. So to avoid special checks later on we force at least
+     * two codes of non zero frequency.
+     */
+    while s.heap_len < 2 {
+        let node = if max_code < 2 { max_code += 1; max_code as usize } else { 0 };
+        s.heap_len += 1;
+        s.heap[s.heap_len] = node;
+        tree[node].fc /*Freq*/ = 1;
+        s.depth[node] = 0;
+        s.opt_len -= 1;
+        if stree.len() != 0 {
+            s.static_len -= stree[node] as isize;
+        }
+        /* node is 0 or 1 so it does not have extra bits */
+    }
+    desc.max_code = max_code as usize;
+
+    /* The elements heap[heap_len/2+1 .. heap_len] are leaves of the tree,
+     * establish sub-heaps of increasing lengths:
+     */
+    let mut n = s.heap_len / 2;
+    while n >= 1 {
+        pqdownheap(s, tree, n);
+        n -= 1;
+    }
+
+    /* Construct the Huffman tree by repeatedly combining the least two
+     * frequent nodes.
+     */
+    let mut node = elems;              /* next internal node of the tree */
+    loop {
+        let n = pqremove(s, tree);     /* n = node of least frequency */
+        let m = s.heap[SMALLEST];      /* m = node of next least frequency */
+
+        s.heap_max -= 1;
+        s.heap[s.heap_max] = n; /* keep the nodes sorted by frequency */
+        s.heap_max -= 1;
+        s.heap[s.heap_max] = m;
+
+        /* Create a new node father of n and m */
+        tree[node].fc /*Freq*/ = tree[n].fc + tree[m].fc;
+        s.depth[node] = (if s.depth[n] >= s.depth[m] { s.depth[n] } else { s.depth[m] }) + 1;
+        tree[n].dl /*Dad*/ = node as u16;
+        tree[m].dl /*Dad*/ = node as u16;
+
+        /* and insert the new node in the heap */
+        s.heap[SMALLEST] = node;
+        node += 1;
+        pqdownheap(s, tree, SMALLEST);
+
+        if s.heap_len < 2 {
+            break;
+        }
+    }
+
+    s.heap_max -= 1;
+    s.heap[s.heap_max] = s.heap[SMALLEST];
+
+    /* At this point, the fields freq and dad are set. We can now
+     * generate the bit lengths.
+     */
+    gen_bitlen(s, tree, desc);
+
+    /* The field len is now set, we can generate the bit codes */
+    gen_codes(tree, desc.max_code, &s.bl_count);
+}
+
+/* ===========================================================================
+ * Scan a literal or distance tree to determine the frequencies of the codes
+ * in the bit length tree.
+ */
+fn scan_tree(s: &mut Deflater, kind: TreeKind) {
+    let (mut tree, desc) = take_tree(s, kind);
+    let max_code = desc.max_code;
+
+    let mut prevlen: isize = -1;                /* last emitted length */
+    let mut nextlen = tree[0].dl as isize;     /* length of next code */
+    let mut count = 0;                          /* repeat count of the current code */
+    let mut max_count = 7;                      /* max repeat count */
+    let mut min_count = 4;                      /* min repeat count */
+
+    if nextlen == 0 {
+        max_count = 138;
+        min_count = 3;
+    }
+    tree[max_code + 1].dl /*Len*/ = 0xffff; /* guard */
+
+    for n in 0..max_code + 1 {
+        let curlen = nextlen;
+        nextlen = tree[n + 1].dl as isize;
+        count += 1;
+        if count < max_count && curlen == nextlen {
+            continue;
+        }
+        else if count < min_count {
+            s.bl_tree[curlen as usize].fc /*Freq*/ += count as u16;
+        }
+        else if curlen != 0 {
+            if curlen != prevlen {
+                s.bl_tree[curlen as usize].fc += 1;
+            }
+            s.bl_tree[REP_3_6].fc += 1;
+        }
+        else if count <= 10 {
+            s.bl_tree[REPZ_3_10].fc += 1;
+        }
+        else {
+            s.bl_tree[REPZ_11_138].fc += 1;
+        }
+        count = 0;
+        prevlen = curlen;
+        if nextlen == 0 {
+            max_count = 138;
+            min_count = 3;
+        }
+        else if curlen == nextlen {
+            max_count = 6;
+            min_count = 3;
+        }
+        else {
+            max_count = 7;
+            min_count = 4;
+        }
+    }
+
+    put_tree(s, kind, tree, desc);
+}
+
+/* ===========================================================================
+ * Send a literal or distance tree in compressed form, using the codes in
+ * bl_tree.
+ */
+fn send_tree(s: &mut Deflater, kind: TreeKind, max_code: usize) {
+    let (tree, desc) = take_tree(s, kind);
+    let bl_tree = mem::take(&mut s.bl_tree);
+
+    let mut prevlen: isize = -1;                /* last emitted length */
+    let mut nextlen = tree[0].dl as isize;     /* length of next code */
+    let mut count = 0;                          /* repeat count of the current code */
+    let mut max_count = 7;                      /* max repeat count */
+    let mut min_count = 4;                      /* min repeat count */
+
+    /* tree[max_code+1].Len = -1; */  /* guard already set */
+    if nextlen == 0 {
+        max_count = 138;
+        min_count = 3;
+    }
+
+    for n in 0..max_code + 1 {
+        let curlen = nextlen;
+        nextlen = tree[n + 1].dl as isize;
+        count += 1;
+        if count < max_count && curlen == nextlen {
+            continue;
+        }
+        else if count < min_count {
+            while count != 0 {
+                send_code(s, curlen as usize, bl_tree.as_slice());
+                count -= 1;
+            }
+        }
+        else if curlen != 0 {
+            if curlen != prevlen {
+                send_code(s, curlen as usize, bl_tree.as_slice());
+                count -= 1;
+            }
+            debug_assert!(count >= 3 && count <= 6, " 3_6?");
+            send_code(s, REP_3_6, bl_tree.as_slice());
+            send_bits(s, count as u32 - 3, 2);
+        }
+        else if count <= 10 {
+            send_code(s, REPZ_3_10, bl_tree.as_slice());
+            send_bits(s, count as u32 - 3, 3);
+        }
+        else {
+            send_code(s, REPZ_11_138, bl_tree.as_slice());
+            send_bits(s, count as u32 - 11, 7);
+        }
+        count = 0;
+        prevlen = curlen;
+        if nextlen == 0 {
+            max_count = 138;
+            min_count = 3;
+        }
+        else if curlen == nextlen {
+            max_count = 6;
+            min_count = 3;
+        }
+        else {
+            max_count = 7;
+            min_count = 4;
+        }
+    }
+
+    s.bl_tree = bl_tree;
+    put_tree(s, kind, tree, desc);
+}
+
+/* ===========================================================================
+ * Construct the Huffman tree for the bit lengths and return the index in
+ * BL_ORDER of the last bit length code to send.
+ */
+fn build_bl_tree(s: &mut Deflater) -> usize {
+    /* Determine the bit length frequencies for literal and distance trees */
+    scan_tree(s, TreeKind::Literal);
+    scan_tree(s, TreeKind::Distance);
+
+    /* Build the bit length tree: */
+    build_tree(s, TreeKind::BitLength);
+    /* opt_len now includes the length of the tree representations, except
+     * the lengths of the bit lengths codes and the 5+5+4 bits for the counts.
+     */
+
+    /* Determine the number of bit length codes to send. The pkzip format
+     * requires that at least 4 bit length codes be sent. (appnote.txt says
+     * 3 but the actual value used is 4.)
+     */
+    let mut max_blindex = BL_CODES - 1;   /* index of last bit length code of non zero freq */
+    while max_blindex >= 3 {
+        if s.bl_tree[BL_ORDER[max_blindex] as usize].dl /*Len*/ != 0 {
+            break;
+        }
+        max_blindex -= 1;
+    }
+    /* Update opt_len to include the bit length tree and counts */
+    s.opt_len += (3 * (max_blindex + 1) + 5 + 5 + 4) as isize;
+    debug!("dyn trees: dyn {}, stat {}", s.opt_len, s.static_len);
+
+    max_blindex
+}
+
+/* ===========================================================================
+ * Send the header for a block using dynamic Huffman trees: the counts, the
+ * lengths of the bit length codes, the literal tree and the distance tree.
+ * IN assertion: lcodes >= 257, dcodes >= 1, blcodes >= 4.
+ */
+fn send_all_trees(s: &mut Deflater, lcodes: usize, dcodes: usize, blcodes: usize) {
+    debug_assert!(lcodes >= 257 && dcodes >= 1 && blcodes >= 4, "not enough codes");
+    debug_assert!(lcodes <= L_CODES && dcodes <= D_CODES && blcodes <= BL_CODES, "too many codes");
+    send_bits(s, (lcodes - 257) as u32, 5); /* not +255 as stated in appnote.txt */
+    send_bits(s, (dcodes - 1) as u32, 5);
+    send_bits(s, (blcodes - 4) as u32, 4); /* not -3 as stated in appnote.txt */
+    for rank in 0..blcodes {
+        let len = s.bl_tree[BL_ORDER[rank] as usize].dl /*Len*/;
+        send_bits(s, len as u32, 3);
+    }
+    debug!("bl tree: sent {}", s.bits_sent);
+
+    send_tree(s, TreeKind::Literal, lcodes - 1); /* literal tree */
+    debug!("lit tree: sent {}", s.bits_sent);
+
+    send_tree(s, TreeKind::Distance, dcodes - 1); /* distance tree */
+    debug!("dist tree: sent {}", s.bits_sent);
+}
+
+/* ===========================================================================
+ * Send a stored block
+ *
+ *      buf - index within s.window of the input block
+ *      stored_len - length of input block
+ *      last - true if this is the last block for a file
+ */
+pub fn _tr_stored_block(s: &mut Deflater, buf: usize, stored_len: usize, last: bool) {
+    send_bits(s, (STORED_BLOCK << 1) + (last as u32), 3);    /* send block type */
+    s.compressed_len = (s.compressed_len + 3 + 7) & !7;
+    s.compressed_len += ((stored_len + 4) << 3) as u64;
+    copy_block(s, buf, stored_len, true); /* with header */
+}
+
+/* ===========================================================================
+ * Flush the bits in the bit buffer to pending output (leaves at most 7 bits)
+ */
+pub fn _tr_flush_bits(s: &mut Deflater) {
+    bi_flush(s);
+}
+
+/* ===========================================================================
+ * Send one empty static block to give enough lookahead for inflate.
+ * This takes 10 bits, of which 7 may remain in the bit buffer.
+ */
+pub fn _tr_align(s: &mut Deflater) {
+    send_bits(s, STATIC_TREES << 1, 3);
+    send_static_code(s, END_BLOCK, &STATIC_L_DESC);
+    s.compressed_len += 10; /* 3 for block type, 7 for EOB */
+    bi_flush(s);
+}
+
+/* ===========================================================================
+ * Determine the best encoding for the current block: dynamic trees, static
+ * trees or store, and output the encoded block to the zip file.
+ *
+ *      buf - index within s.window of the input block, or None if too old
+ *      stored_len - length of input block
+ *      last - true if this is the last block for a file
+ */
+pub fn _tr_flush_block(s: &mut Deflater, buf: Option<usize>, stored_len: usize, last: bool) {
+    let mut opt_lenb: usize;        /* opt_len in bytes */
+    let static_lenb: usize;         /* static_len in bytes */
+    let mut max_blindex: usize = 0; /* index of last bit length code of non zero freq */
+
+    /* Build the Huffman trees unless a stored block is forced */
+    if s.level > 0 {
+        /* Check if the file is binary or text */
+        if s.strm.data_type == Z_UNKNOWN {
+            s.strm.data_type = detect_data_type(s);
+        }
+
+        /* Construct the literal and distance trees */
+        build_tree(s, TreeKind::Literal);
+        debug!("lit data: dyn {}, stat {}", s.opt_len, s.static_len);
+
+        build_tree(s, TreeKind::Distance);
+        debug!("dist data: dyn {}, stat {}", s.opt_len, s.static_len);
+        /* At this point, opt_len and static_len are the total bit lengths of
+         * the compressed block data, excluding the tree representations.
+         */
+
+        /* Build the bit length tree for the above two trees, and get the index
+         * in BL_ORDER of the last bit length code to send.
+         */
+        max_blindex = build_bl_tree(s);
+
+        /* Determine the best encoding. Compute the block lengths in bytes. */
+        opt_lenb = ((s.opt_len + 3 + 7) >> 3) as usize;
+        static_lenb = ((s.static_len + 3 + 7) >> 3) as usize;
+
+        debug!("opt {}({}) stat {}({}) stored {} lit {}",
+            opt_lenb, s.opt_len, static_lenb, s.static_len, stored_len, s.last_lit);
+
+        if static_lenb <= opt_lenb {
+            opt_lenb = static_lenb;
+        }
+    }
+    else {
+        /* With Z_HUFFMAN_ONLY or Z_RLE, the block may have outgrown the window,
+         * so that it can no longer be stored. zlib 1.2.8 then sends it with the
+         * static trees; build the trees anyway, so that static_len is known.
+         */
+        if buf.is_none() {
+            build_tree(s, TreeKind::Literal);
+            build_tree(s, TreeKind::Distance);
+        }
+        opt_lenb = stored_len + 5; /* force a stored block */
+        static_lenb = opt_lenb;
+    }
+
+    match buf {
+        Some(buf) if stored_len + 4 <= opt_lenb => {
+            /* 4: two words for the lengths */
+
+            /* The test buf != None is only necessary if LIT_BUFSIZE > WSIZE.
+             * Otherwise we can't have processed more than WSIZE input bytes since
+             * the last block flush, because compression would have been
+             * successful. If LIT_BUFSIZE <= WSIZE, it is never too late to
+             * transform a block into a stored block.
+             */
+            _tr_stored_block(s, buf, stored_len, last);
+        }
+        _ => {
+            if s.strategy == Z_FIXED || static_lenb == opt_lenb {
+                send_bits(s, (STATIC_TREES << 1) + (last as u32), 3);
+                compress_block(s, true);
+                s.compressed_len += 3 + s.static_len as u64;
+            }
+            else {
+                send_bits(s, (DYN_TREES << 1) + (last as u32), 3);
+                let lcodes = s.l_desc.max_code + 1;
+                let dcodes = s.d_desc.max_code + 1;
+                send_all_trees(s, lcodes, dcodes, max_blindex + 1);
+                compress_block(s, false);
+                s.compressed_len += 3 + s.opt_len as u64;
+            }
+        }
+    }
+    debug_assert!(s.compressed_len == s.bits_sent, "bad compressed size");
+    init_block(s);
+
+    if last {
+        bi_windup(s);
+        s.compressed_len += 7;  /* align on byte boundary */
+    }
+    debug!("comprlen {}({})", s.compressed_len >> 3, s.compressed_len - 7 * (last as u64));
+}
+
+/* ===========================================================================
+ * Save the match info and tally the frequency counts. Return true if
+ * the current block must be flushed.
+ */
+
+// was _tr_tally_lit; c is the unmatched char
+#[inline]
+pub fn _tr_tally_lit(s: &mut Deflater, c: u8) -> bool {
+    s.d_buf[s.last_lit] = 0;
+    s.l_buf[s.last_lit] = c;
+    s.last_lit += 1;
+    s.dyn_ltree[c as usize].fc /*Freq*/ += 1;
+    s.last_lit == s.lit_bufsize - 1
+    /* We avoid equality with lit_bufsize because of wraparound at 64K
+     * on 16 bit machines and because stored blocks are restricted to
+     * 64K-1 bytes.
+     */
+}
+
+// was _tr_tally_dist; dist is the distance of the matched string, and len
+// is the match length - MIN_MATCH
+#[inline]
+pub fn _tr_tally_dist(s: &mut Deflater, dist: usize, len: usize) -> bool {
+    s.d_buf[s.last_lit] = dist as u16;
+    s.l_buf[s.last_lit] = len as u8;
+    s.last_lit += 1;
+    s.matches += 1;
+    let dist = dist - 1;    /* dist = match distance - 1 */
+    s.dyn_ltree[LENGTH_CODE[len] as usize + LITERALS + 1].fc /*Freq*/ += 1;
+    s.dyn_dtree[d_code(dist)].fc /*Freq*/ += 1;
+    s.last_lit == s.lit_bufsize - 1
+}
+
+/* ===========================================================================
+ * Send the block data compressed using the given Huffman trees.  If
+ * static_trees is true, then the static trees are used, otherwise the
+ * dynamic trees that were just built are used.
+ */
+fn compress_block(s: &mut Deflater, static_trees: bool) {
+    let ltree = if static_trees { Vec::new() } else { mem::take(&mut s.dyn_ltree) };
+    let dtree = if static_trees { Vec::new() } else { mem::take(&mut s.dyn_dtree) };
+
+    macro_rules! send_lcode {
+        ($c:expr) => {
+            if static_trees { send_static_code(s, $c, &STATIC_L_DESC); } else { send_code(s, $c, ltree.as_slice()); }
+        }
+    }
+    macro_rules! send_dcode {
+        ($c:expr) => {
+            if static_trees { send_static_code(s, $c, &STATIC_D_DESC); } else { send_code(s, $c, dtree.as_slice()); }
+        }
+    }
+
+    let mut lx: usize = 0;    /* running index in l_buf */
+
+    while lx < s.last_lit {
+        let mut dist = s.d_buf[lx] as usize;    /* distance of matched string */
+        let mut lc = s.l_buf[lx] as usize;      /* match length or unmatched char (if dist == 0) */
+        lx += 1;
+        if dist == 0 {
+            send_lcode!(lc); /* send a literal byte */
+        }
+        else {
+            /* Here, lc is the match length - MIN_MATCH */
+            let code = LENGTH_CODE[lc] as usize;
+            send_lcode!(code + LITERALS + 1); /* send the length code */
+            let extra = EXTRA_LBITS[code] as usize;
+            if extra != 0 {
+                lc -= BASE_LENGTH[code] as usize;
+                send_bits(s, lc as u32, extra);       /* send the extra length bits */
+            }
+            dist -= 1; /* dist is now the match distance - 1 */
+            let code = d_code(dist);
+            debug_assert!(code < D_CODES, "bad d_code");
+
+            send_dcode!(code);       /* send the distance code */
+            let extra = EXTRA_DBITS[code] as usize;
+            if extra != 0 {
+                dist -= BASE_DIST[code] as usize;
+                send_bits(s, dist as u32, extra);   /* send the extra distance bits */
+            }
+        } /* literal or match pair ? */
+    }
+
+    send_lcode!(END_BLOCK);
+
+    if !static_trees {
+        s.dyn_ltree = ltree;
+        s.dyn_dtree = dtree;
+    }
+}
+
+/* ===========================================================================
+ * Check if the data type is TEXT or BINARY, using the following algorithm:
+ * - TEXT if the two conditions below are satisfied:
+ *    a) There are no non-portable control characters belonging to the
+ *       "black list" (0..6, 14..25, 28..31).
+ *    b) There is at least one printable character belonging to the
+ *       "white list" (9 {TAB}, 10 {LF}, 13 {CR}, 32..255).
+ * - BINARY otherwise.
+ * - The following partially-portable control characters form a
+ *   "gray list" that is ignored in this detection algorithm:
+ *   (7 {BEL}, 8 {BS}, 11 {VT}, 12 {FF}, 26 {SUB}, 27 {ESC}).
+ * IN assertion: the fields Freq of dyn_ltree are set.
+ */
+fn detect_data_type(s: &Deflater) -> u32 {
+    /* black_mask is the bit mask of black-listed bytes
+     * set bits 0..6, 14..25, and 28..31
+     * 0xf3ffc07f = binary 11110011111111111100000001111111
+     */
+    let mut black_mask: u32 = 0xf3ffc07f;
+
+    /* Check for non-textual ("black-listed") bytes. */
+    for n in 0..32 {
+        if (black_mask & 1) != 0 && s.dyn_ltree[n].fc /*Freq*/ != 0 {
+            return Z_BINARY;
+        }
+        black_mask >>= 1;
+    }
+
+    /* Check for textual ("white-listed") bytes. */
+    if s.dyn_ltree[9].fc != 0 || s.dyn_ltree[10].fc != 0 || s.dyn_ltree[13].fc != 0 {
+        return Z_TEXT;
+    }
+    for n in 32..LITERALS {
+        if s.dyn_ltree[n].fc != 0 {
+            return Z_TEXT;
+        }
+    }
+
+    /* There are no "black-listed" or "white-listed" bytes:
+     * this stream either is empty or has tolerated ("gray-listed") bytes only.
+     */
+    Z_BINARY
+}
+
+/* ===========================================================================
+ * Reverse the first len bits of a code, using straightforward code (a faster
+ * method would use a table)
+ * IN assertion: 1 <= len <= 15
+ */
+fn bi_reverse(code: u32, len: usize) -> u32 {
+    let mut code = code;
+    let mut len = len;
+    let mut res: u32 = 0;
+    loop {
+        res |= code & 1;
+        code >>= 1;
+        res <<= 1;
+        len -= 1;
+        if len == 0 {
+            break;
+        }
+    }
+    res >> 1
+}
+
+/* ===========================================================================
+ * Flush the bit buffer, keeping at most 7 bits in it.
+ */
+fn bi_flush(s: &mut Deflater) {
+    if s.bi_valid == 16 {
+        let bi_buf = s.bi_buf;
+        put_short(s, bi_buf);
+        s.bi_buf = 0;
+        s.bi_valid = 0;
+    }
+    else if s.bi_valid >= 8 {
+        let bi_buf = s.bi_buf;
+        put_byte(s, bi_buf as u8);
+        s.bi_buf >>= 8;
+        s.bi_valid -= 8;
+    }
+}
+
+/* ===========================================================================
+ * Flush the bit buffer and align the output on a byte boundary
+ */
+fn bi_windup(s: &mut Deflater) {
+    let bi_buf = s.bi_buf;
+    if s.bi_valid > 8 {
+        put_short(s, bi_buf);
+    }
+    else if s.bi_valid > 0 {
+        put_byte(s, bi_buf as u8);
+    }
+    s.bi_buf = 0;
+    s.bi_valid = 0;
+    s.bits_sent = (s.bits_sent + 7) & !7;
+}
+
+/* ===========================================================================
+ * Copy a stored block, storing first the length and its
+ * one's complement if requested.
+ *
+ *      buf - index within s.window of the input data
+ *      len - its length
+ *      header - true if block header must be written
+ */
+fn copy_block(s: &mut Deflater, buf: usize, len: usize, header: bool) {
+    bi_windup(s);        /* align on byte boundary */
+
+    if header {
+        put_short(s, len as u16);
+        put_short(s, !(len as u16));
+        s.bits_sent += 2 * 16;
+    }
+    s.bits_sent += (len << 3) as u64;
+
+    for i in 0..len {
+        let b = s.window[buf + i];
+        put_byte(s, b);
+    }
+}
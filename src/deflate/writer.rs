@@ -0,0 +1,142 @@
+use std::cmp::max;
+use std::io;
+use std::io::{Write, ErrorKind};
+
+use crate::io_error;
+use crate::Flush;
+use crate::WINDOW_BITS_DEFAULT;
+use crate::deflate::{Deflater,DeflateResult};
+
+/// Provides an implementation of `Write` for deflating (compressing) DEFLATE / zlib / GZIP
+/// streams.  Data written to the `DeflateWriter` is compressed and written to `dst`.
+///
+/// Call `finish()` when all of the data has been written.  This writes the end of the stream
+/// (and the trailer, for zlib and GZIP streams) and returns the inner writer.  If the
+/// `DeflateWriter` is dropped without calling `finish()`, the stream will be incomplete.
+pub struct DeflateWriter<W> {
+    dst: W,
+    state: Deflater,
+
+    outbuf: Vec<u8>,
+}
+
+impl<W:Write> DeflateWriter<W> {
+    /// Creates a new DeflateWriter which writes a zlib stream to `dst`.
+    pub fn new_zlib(
+        level: i32,
+        outbufsize: usize,
+        dst: W) -> DeflateWriter<W> {
+        debug!("DeflateWriter::new_zlib()");
+        DeflateWriter::new_with_deflater(outbufsize, Deflater::new_zlib(level), dst)
+    }
+
+    /// Creates a new DeflateWriter which writes a GZIP stream to `dst`.
+    pub fn new_gzip(
+        level: i32,
+        outbufsize: usize,
+        dst: W) -> DeflateWriter<W> {
+        debug!("DeflateWriter::new_gzip()");
+        DeflateWriter::new_with_deflater(outbufsize, Deflater::new_gzip(level), dst)
+    }
+
+    /// Creates a new DeflateWriter which writes a raw DEFLATE stream to `dst`.
+    pub fn new_raw(
+        level: i32,
+        outbufsize: usize,
+        dst: W) -> DeflateWriter<W> {
+        debug!("DeflateWriter::new_raw()");
+        DeflateWriter::new_with_deflater(outbufsize,
+            Deflater::new_raw(level, WINDOW_BITS_DEFAULT),
+            dst)
+    }
+
+    pub fn new_with_deflater(
+        outbufsize: usize,
+        deflater: Deflater,
+        dst: W) -> DeflateWriter<W> {
+        let outbufsize = max(outbufsize, 0x1000);
+        DeflateWriter {
+            dst: dst,
+            state: deflater,
+            outbuf: vec![0u8; outbufsize],
+        }
+    }
+
+    pub fn inner(&mut self) -> &mut W {
+        &mut self.dst
+    }
+
+    /// Finishes the compressed stream, writes any remaining output to the inner writer,
+    /// and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        loop {
+            match self.state.deflate(Flush::Finish, &[], self.outbuf.as_mut_slice()) {
+                DeflateResult::Encoded(_, out_bytes) => {
+                    debug!("finish: encoded {} bytes", out_bytes);
+                    self.dst.write_all(&self.outbuf[..out_bytes])?;
+                }
+                DeflateResult::Finished(_, out_bytes) => {
+                    debug!("finish: finished, last {} bytes", out_bytes);
+                    self.dst.write_all(&self.outbuf[..out_bytes])?;
+                    break;
+                }
+                result => {
+                    warn!("finish: deflate returned {:?}", result);
+                    return Err(io_error(ErrorKind::Other, "deflate failed", Some(format!("{:?}", result))));
+                }
+            }
+        }
+        self.dst.flush()?;
+        Ok(self.dst)
+    }
+}
+
+impl<W:Write> Write for DeflateWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inpos: usize = 0;
+
+        while inpos < buf.len() {
+            debug!("inpos={} buf.len={}", inpos, buf.len());
+            match self.state.deflate(Flush::None, &buf[inpos..], self.outbuf.as_mut_slice()) {
+                DeflateResult::Encoded(in_bytes, out_bytes) => {
+                    debug!("encoded: in_bytes={} out_bytes={}", in_bytes, out_bytes);
+                    inpos += in_bytes;
+                    if out_bytes != 0 {
+                        self.dst.write_all(&self.outbuf[..out_bytes])?;
+                    }
+                }
+                result => {
+                    warn!("write: deflate returned {:?}", result);
+                    return Err(io_error(ErrorKind::Other, "deflate failed", Some(format!("{:?}", result))));
+                }
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Compresses and writes all data that has been written so far, using a sync flush,
+    /// and then flushes the inner writer.  Flushing too often degrades compression.
+    fn flush(&mut self) -> io::Result<()> {
+        loop {
+            match self.state.deflate(Flush::SyncFlush, &[], self.outbuf.as_mut_slice()) {
+                DeflateResult::Encoded(_, out_bytes) => {
+                    debug!("flush: encoded {} bytes", out_bytes);
+                    self.dst.write_all(&self.outbuf[..out_bytes])?;
+                    if out_bytes < self.outbuf.len() {
+                        break;
+                    }
+                }
+                DeflateResult::BufError => {
+                    // nothing new to flush
+                    break;
+                }
+                result => {
+                    warn!("flush: deflate returned {:?}", result);
+                    return Err(io_error(ErrorKind::Other, "deflate failed", Some(format!("{:?}", result))));
+                }
+            }
+        }
+        self.dst.flush()
+    }
+}
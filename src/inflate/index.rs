@@ -0,0 +1,239 @@
+// Random access to GZIP streams, in the manner of zran.c from the zlib examples.
+
+use std::io;
+use std::io::{Read, Write, Seek, SeekFrom, ErrorKind};
+
+use crate::{io_error, fill_vec, read_le_u32, read_le_u64};
+use crate::Flush;
+use crate::ZERR;
+use crate::WINDOW_BITS_MAX;
+use crate::inflate::{Inflater, InflateResult, InflateReader};
+use super::reader::{inflate_error, unexpected_end_error, need_dictionary_error};
+
+// Identifies a serialized GzipIndex, and the version of the format.
+const INDEX_MAGIC: &[u8] = b"GZINDEX1";
+
+/// A point in a GZIP stream where decoding can start, without decoding the data before it.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Checkpoint {
+    pub output_offset: u64,     // offset in the uncompressed data
+    pub input_offset: u64,      // offset in the GZIP stream of the first byte after the checkpoint
+    pub bits: usize,            // if nonzero, the checkpoint starts this many bits before input_offset
+    pub window: Vec<u8>,        // the 32 KiB of uncompressed data before output_offset (or less, near the start)
+}
+
+/// An index of a GZIP stream, which allows reading the uncompressed data starting at any
+/// offset, without decoding the stream from the start.
+///
+/// `build()` decodes the whole stream once, and saves a checkpoint at the start of a DEFLATE
+/// block every `span` bytes of output.  Each checkpoint holds the last 32 KiB of output, so
+/// the index takes about 32 KiB of space per checkpoint.  `seek()` finds the last checkpoint
+/// before an offset and starts decoding there.  Use `write_to()` and `read_from()` to save
+/// the index, so that it only has to be built once.
+///
+/// Only the first member of a multi-member GZIP stream is indexed.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct GzipIndex {
+    span: u64,
+    total_out: u64,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl GzipIndex {
+    /// Decodes the GZIP stream in `src`, and builds an index with a checkpoint about every
+    /// `span` bytes of uncompressed data.  A span of 1 MiB is a reasonable choice.
+    pub fn build<R:Read>(src: &mut R, span: u64) -> io::Result<GzipIndex> {
+        let mut state = Inflater::new_gzip();
+        let mut inbuf: Vec<u8> = Vec::with_capacity(0x4000);
+        let mut outbuf: Vec<u8> = vec![0u8; 0x8000];
+        let mut next_in: usize = 0;
+        let mut src_eof = false;
+        let mut total_in: u64 = 0;
+        let mut total_out: u64 = 0;
+        let mut checkpoints: Vec<Checkpoint> = Vec::new();
+
+        loop {
+            if next_in == inbuf.len() && !src_eof {
+                next_in = 0;
+                if fill_vec(src, &mut inbuf)? == 0 {
+                    src_eof = true;
+                }
+            }
+
+            let result = state.inflate(Some(Flush::Block), &inbuf[next_in..], outbuf.as_mut_slice());
+            match result {
+                InflateResult::Decoded(in_bytes, out_bytes) => {
+                    next_in += in_bytes;
+                    total_in += in_bytes as u64;
+                    total_out += out_bytes as u64;
+                }
+                InflateResult::Boundary(in_bytes, out_bytes) => {
+                    next_in += in_bytes;
+                    total_in += in_bytes as u64;
+                    total_out += out_bytes as u64;
+
+                    // The first checkpoint is at the start of the first block.
+                    let last_output = checkpoints.last().map(|checkpoint| checkpoint.output_offset);
+                    let due = match last_output {
+                        Some(last_output) => total_out - last_output >= span,
+                        None => true
+                    };
+                    let position = state.block_position();
+                    if due && position.at_block_boundary && !position.last_block {
+                        debug!("checkpoint: output_offset={} input_offset={} bits={}", total_out, total_in, position.bits);
+                        checkpoints.push(Checkpoint {
+                            output_offset: total_out,
+                            input_offset: total_in,
+                            bits: position.bits,
+                            window: state.window_contents(),
+                        });
+                    }
+                }
+                InflateResult::Eof(_) => break,
+                InflateResult::NeedInput => {
+                    if src_eof {
+                        return Err(unexpected_end_error(total_in));
+                    }
+                }
+                InflateResult::Error(err, offset) => {
+                    return Err(inflate_error(err, offset));
+                }
+                InflateResult::NeedDictionary(dictid) => {
+                    return Err(need_dictionary_error(dictid));
+                }
+            }
+        }
+
+        debug!("built index: {} checkpoints, total_out={}", checkpoints.len(), total_out);
+        Ok(GzipIndex {
+            span: span,
+            total_out: total_out,
+            checkpoints: checkpoints,
+        })
+    }
+
+    /// Returns the span that the index was built with.
+    pub fn span(&self) -> u64 {
+        self.span
+    }
+
+    /// Returns the length of the uncompressed data.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Returns the checkpoints, in order of offset.
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        self.checkpoints.as_slice()
+    }
+
+    /// Returns a reader for the uncompressed data, starting at `offset`.  `src` must contain
+    /// the GZIP stream that the index was built from.  The reader decodes from the last
+    /// checkpoint at or before `offset`, and discards the data before `offset`.
+    ///
+    /// The trailer of the GZIP stream is not checked, and errors report input offsets relative
+    /// to the checkpoint rather than to the start of `src`.
+    pub fn seek<R:Read+Seek>(&self, mut src: R, offset: u64) -> io::Result<InflateReader<R>> {
+        if offset > self.total_out {
+            return Err(io_error(ErrorKind::InvalidInput, "offset is past the end of the uncompressed data",
+                Some(format!("offset {}, length {}", offset, self.total_out))));
+        }
+        let checkpoint = match self.checkpoints.iter().rev().find(|c| c.output_offset <= offset) {
+            Some(checkpoint) => checkpoint,
+            None => {
+                return Err(io_error(ErrorKind::InvalidInput, "gzip index has no checkpoints", None));
+            }
+        };
+        debug!("seek: offset={}, using checkpoint at output_offset={}", offset, checkpoint.output_offset);
+
+        // If the checkpoint starts within a byte, feed the rest of that byte to the Inflater.
+        let mut state = Inflater::new_raw(WINDOW_BITS_MAX);
+        if checkpoint.bits != 0 {
+            src.seek(SeekFrom::Start(checkpoint.input_offset - 1))?;
+            let mut byte = [0u8; 1];
+            src.read_exact(&mut byte)?;
+            let byte = byte[0];
+            state.prime(checkpoint.bits as isize, (byte >> (8 - checkpoint.bits)) as u32);
+        }
+        else {
+            src.seek(SeekFrom::Start(checkpoint.input_offset))?;
+        }
+        let result = state.set_dictionary(&checkpoint.window);
+        if result != ZERR::Ok {
+            return Err(io_error(ErrorKind::InvalidData, "invalid gzip index checkpoint",
+                Some(format!("checkpoint at output offset {}: set_dictionary() returned {:?}",
+                    checkpoint.output_offset, result))));
+        }
+
+        let mut reader = InflateReader::new_with_inflater(0x4000, state, src);
+        let mut skip = offset - checkpoint.output_offset;
+        let mut discard: Vec<u8> = vec![0u8; 0x8000];
+        while skip != 0 {
+            let len = if skip < discard.len() as u64 { skip as usize } else { discard.len() };
+            reader.read_exact(&mut discard[..len])?;
+            skip -= len as u64;
+        }
+        Ok(reader)
+    }
+
+    /// Writes the index to `dst`, in a form that `read_from()` can read.
+    pub fn write_to<W:Write>(&self, dst: &mut W) -> io::Result<()> {
+        dst.write_all(INDEX_MAGIC)?;
+        dst.write_all(&self.span.to_le_bytes())?;
+        dst.write_all(&self.total_out.to_le_bytes())?;
+        dst.write_all(&(self.checkpoints.len() as u64).to_le_bytes())?;
+        for checkpoint in self.checkpoints.iter() {
+            dst.write_all(&checkpoint.output_offset.to_le_bytes())?;
+            dst.write_all(&checkpoint.input_offset.to_le_bytes())?;
+            dst.write_all(&[checkpoint.bits as u8])?;
+            dst.write_all(&(checkpoint.window.len() as u32).to_le_bytes())?;
+            dst.write_all(&checkpoint.window)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an index that was written by `write_to()`.
+    pub fn read_from<R:Read>(src: &mut R) -> io::Result<GzipIndex> {
+        let mut magic = [0u8; 8];
+        src.read_exact(&mut magic)?;
+        if magic != INDEX_MAGIC {
+            return Err(io_error(ErrorKind::InvalidData, "not a gzip index", None));
+        }
+        let span = read_le_u64(src)?;
+        let total_out = read_le_u64(src)?;
+        let count = read_le_u64(src)?;
+        let mut checkpoints: Vec<Checkpoint> = Vec::new();
+        for _ in 0..count {
+            let output_offset = read_le_u64(src)?;
+            let input_offset = read_le_u64(src)?;
+            let mut bits = [0u8; 1];
+            src.read_exact(&mut bits)?;
+            let bits = bits[0] as usize;
+            let window_len = read_le_u32(src)? as usize;
+            // Checkpoints are in order, and a checkpoint that starts within a byte needs
+            // that byte.
+            let in_order = match checkpoints.last() {
+                Some(previous) => output_offset > previous.output_offset,
+                None => true
+            };
+            if bits > 7 || window_len > (1 << WINDOW_BITS_MAX) || !in_order || output_offset > total_out
+                || (bits != 0 && input_offset == 0) {
+                return Err(io_error(ErrorKind::InvalidData, "invalid gzip index checkpoint",
+                    Some(format!("checkpoint at output offset {}", output_offset))));
+            }
+            let mut window = vec![0u8; window_len];
+            src.read_exact(&mut window)?;
+            checkpoints.push(Checkpoint {
+                output_offset: output_offset,
+                input_offset: input_offset,
+                bits: bits,
+                window: window,
+            });
+        }
+        Ok(GzipIndex {
+            span: span,
+            total_out: total_out,
+            checkpoints: checkpoints,
+        })
+    }
+}
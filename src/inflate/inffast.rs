@@ -6,7 +6,7 @@
 use super::inftrees::Code;
 use super::Inflater;
 use super::InflateMode;
-use std::slice::bytes::copy_memory;
+use super::InflateError;
 
 pub struct BufPos<'a> {
     pub buf: &'a [u8],
@@ -20,7 +20,7 @@ impl<'a> BufPos<'a> {
         // let b = self.buf[self.pos];
 
         /*        
-        let b = if cfg!(unsafe_fast) {
+        let b = if cfg!(feature = "unsafe_fast") {
             unsafe { *self.buf.get_unchecked(self.pos) }
         }
         else {
@@ -42,7 +42,7 @@ struct BufPosMut<'a> {
 impl<'a> BufPosMut<'a> {
     #[inline]
     pub fn write(&mut self, b: u8) {
-        if cfg!(unsafe_fast) {
+        if cfg!(feature = "unsafe_fast") {
             unsafe {
                 *self.buf.get_unchecked_mut(self.pos) = b;
             }
@@ -54,7 +54,7 @@ impl<'a> BufPosMut<'a> {
     }
 
     pub fn write_slice(&mut self, src: &[u8]) {
-        copy_memory(self.buf.slice_from_mut(self.pos), src);
+        self.buf[self.pos..self.pos + src.len()].copy_from_slice(src);
         self.pos += src.len();
     }
 }
@@ -160,19 +160,19 @@ fn copy_within_output_buffer(buf: &mut [u8], dstpos: usize, srcpos: usize, len:
         debug_assert!(srcpos + len <= dstpos);
 
         let (src_split, dst_split) = buf.split_at_mut(dstpos);
-        let src_buf = src_split.slice(srcpos, srcpos + len);
-        copy_memory(dst_split, src_buf);
+        let src_buf = &src_split[srcpos..srcpos + len];
+        dst_split[..len].copy_from_slice(src_buf);
     }
     else {
         // overlapping copy -- do it the hard way
-        for i in range(0, len) {
+        for i in 0..len {
             buf[dstpos + i] = read_byte(buf, srcpos + i);
         }
     }
 
     /*
     // correct, simple, kinda slow
-    for i in range(0, len) {
+    for i in 0..len {
         buf[dstpos + i] = buf[srcpos + i];
     }
     */
@@ -180,14 +180,14 @@ fn copy_within_output_buffer(buf: &mut [u8], dstpos: usize, srcpos: usize, len:
     /*
     // correct, simple, unsafe, and no faster than copy_memory()
     unsafe {
-        for i in range(0, len) {
+        for i in 0..len {
             *buf.unsafe_mut(dstpos + i) = *buf.get_unchecked(srcpos + i);
         }
     }
     */
 }
 
-#[derive(Show,PartialEq)]
+#[derive(Debug,PartialEq)]
 enum InflateFastState {
     Start,
     DoDist,
@@ -331,8 +331,7 @@ pub fn inflate_fast(
                     break;
                 }
                 else {
-                    state.strm.msg = Some("invalid literal/length code");
-                    state.mode = InflateMode::BAD;
+                    state.bad_input_at(InflateError::InvalidLiteralLengthCode, input.pos, input.bits);
                     break;
                 }
             }
@@ -357,8 +356,7 @@ pub fn inflate_fast(
     // #ifdef INFLATE_STRICT
                     if dist > dmax {
                         debug!("invalid distance, too far back.  dist {} > dmax {}", dist, dmax);
-                        state.strm.msg = Some("invalid distance too far back");
-                        state.mode = InflateMode::BAD;
+                        state.bad_input_at(InflateError::InvalidDistanceTooFarBack, input.pos, input.bits);
                         break;
                     }
     // #endif
@@ -382,8 +380,7 @@ pub fn inflate_fast(
                         // Tracevv!("maxout = dist - maxout = {}", maxout);
                         if maxout > whave {
                             if state.sane {
-                                state.strm.msg = Some("invalid distance too far back");
-                                state.mode = InflateMode::BAD;
+                                state.bad_input_at(InflateError::InvalidDistanceTooFarBack, input.pos, input.bits);
                                 break;
                             }
     /*#ifdef INFLATE_ALLOW_INVALID_DISTANCE_TOOFAR_ARRR
@@ -425,7 +422,7 @@ pub fn inflate_fast(
                                 // debug!("copying some from window, out.pos = {}, window pos = {}, length = {}", out.pos, wsize - maxout, maxout);
                                 len -= maxout;
 
-                                out.write_slice(window.slice(wsize - maxout, wsize)); // transfer size is maxout
+                                out.write_slice(&window[wsize - maxout..wsize]); // transfer size is maxout
 
                                 // copy the rest from the output buffer
                                 // debug!("copying within output buffer, out.pos (dst) = {}, dist = {}, out.src = {}, len = {}", out.pos, dist, out.pos - dist, len);
@@ -435,7 +432,7 @@ pub fn inflate_fast(
                             else {
                                 let wpos = wsize - maxout;
                                 // debug!("copying all from window, out.pos = {}, window.len() = {}, window pos = {}, len = {}", out.pos, window.len(), wpos, len);
-                                out.write_slice(window.slice(wpos, wpos + len));
+                                out.write_slice(&window[wpos..wpos + len]);
                             }
                         }
                         else if wnext < maxout {
@@ -458,9 +455,7 @@ pub fn inflate_fast(
                                     debug!("some from start of window");
                                     maxout = wnext;
                                     len -= maxout;
-                                    copy_memory(
-                                        out.buf.slice_mut(out.pos, out.pos + maxout),
-                                        from.buf.slice(from.pos, from.pos + maxout));
+                                    out.buf[out.pos..out.pos + maxout].copy_from_slice(&from.buf[from.pos..from.pos + maxout]);
                                     out.pos += maxout;
 
                                     /* rest from output */
@@ -469,14 +464,14 @@ pub fn inflate_fast(
                                 }
                                 else {
                                     // copy from 'from' to output
-                                    copy_memory(out.buf.slice_mut(out.pos, out.pos + len), window.slice(from.pos, from.pos + len));
+                                    out.buf[out.pos..out.pos + len].copy_from_slice(&window[from.pos..from.pos + len]);
                                     out.pos += len;
                                 }
                             }
                             else {
                                 // copy from window ('from') to output
                                 // debug!("copy from window to output");
-                                copy_memory(out.buf.slice_mut(out.pos, out.pos + len), window.slice(from.pos, from.pos + len));
+                                out.buf[out.pos..out.pos + len].copy_from_slice(&window[from.pos..from.pos + len]);
                                 out.pos += len;
                             }
                         }
@@ -537,8 +532,7 @@ pub fn inflate_fast(
                     continue;
                 }
                 else {
-                    state.strm.msg = Some("invalid distance code");
-                    state.mode = InflateMode::BAD;
+                    state.bad_input_at(InflateError::InvalidDistanceCode, input.pos, input.bits);
                     break;
                 }
             }
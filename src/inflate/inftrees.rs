@@ -1,5 +1,3 @@
-use std::iter::range_inclusive;
-
 /* inftrees.h -- header to use inftrees.c
  * Copyright (C) 1995-2005, 2010 Mark Adler
  * For conditions of distribution and use, see copyright notice in zlib.h
@@ -23,7 +21,7 @@ use std::iter::range_inclusive;
 // of the bit buffer.  val is the actual byte to output in the case
 // of a literal, the base length or distance, or the offset from
 // the current table to the next table.  Each entry is four bytes.
-#[derive(Copy,Default)]
+#[derive(Copy,Clone,Default)]
 pub struct Code {
     // operation, extra bits, table bits
     // op values as set by inflate_table():
@@ -151,12 +149,12 @@ pub fn inflate_table(
 
     // accumulate lengths for codes (assumes lens[] all in 0..MAXBITS)
     let mut count = [0u16; MAXBITS+1]; // number of codes of each length
-    for sym in range(0, codes) {
+    for sym in 0..codes {
         count[lens[sym] as usize] += 1;
     }
 
     // debug!("counts:");
-    // for i in range_inclusive(0, MAXBITS) {
+    // for i in 0..=MAXBITS {
     //     debug!("    count[{}] = {}", i, count[i]);
     // }
 
@@ -200,7 +198,7 @@ pub fn inflate_table(
     // check for an over-subscribed or incomplete set of lengths
     {
         let mut left: i32 = 1; // number of prefix codes available
-        for len in range_inclusive(1, MAXBITS) {
+        for len in 1..=MAXBITS {
             left <<= 1;
             left -= count[len] as i32;
             if left < 0 {
@@ -217,12 +215,12 @@ pub fn inflate_table(
     // generate offsets into symbol table for each length for sorting
     let mut offs = [0u16; MAXBITS+1];     // offsets in table for each length
     offs[1] = 0;
-    for len in range(1, MAXBITS) {
+    for len in 1..MAXBITS {
         offs[len + 1] = offs[len] + count[len];
     }
 
     // sort symbols by length, by symbol order within each length
-    for sym in range(0u16, codes as u16) {
+    for sym in 0u16..codes as u16 {
         let symlen = lens[sym as usize] as usize;
         if symlen != 0 {
             let symoff = offs[symlen] as usize;
@@ -268,9 +266,9 @@ pub fn inflate_table(
         extra_bias,         // offset into 'extra' to use, can be negative
         end)                // use base and extra for symbol > end
         = match ctype {
-        CODES => (&EMPTY_U16[], 0, &EMPTY_U16[], 0, 19),    // base/extra not used
-        LENS => (&LBASE[], -257, &LEXT[], -257, 256),
-        _ /* DISTS */ => (&DBASE[], 0, &DEXT[], 0, -1)
+        CODES => (&EMPTY_U16[..], 0, &EMPTY_U16[..], 0, 19),    // base/extra not used
+        LENS => (&LBASE[..], -257, &LEXT[..], -257, 256),
+        _ /* DISTS */ => (&DBASE[..], 0, &DEXT[..], 0, -1)
     };
 
     // debug!("base.len = {}, extra.len = {}", base.len(), extra.len());
@@ -292,7 +290,7 @@ pub fn inflate_table(
     let mut next: usize = *table_pos;    // next available space in 'table'; current table to fill in
     let mut curr: usize = root;          // number of index bits for current table; current table index bits
     let mut drop: usize = 0;             // code bits to drop for sub-table; current bits to drop from code for index
-    let mut low: usize = !0us;           // low bits for current root entry; trigger new sub-table when len > root
+    let mut low: usize = !0usize;           // low bits for current root entry; trigger new sub-table when len > root
 
     // process all codes and make table entries
     // debug!("processing codes");
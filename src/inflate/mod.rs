@@ -4,39 +4,46 @@
 
 // http://www.gzip.org/zlib/rfc-gzip.html
 
-use std::slice::bytes::copy_memory;
-use std::iter::repeat;
 
-use crc32::crc32;
-use adler32::adler32;
+use crate::crc::crc32;
+use crate::adler32::adler32;
 use self::inffast::inflate_fast;
 use self::inffast::BufPos;
 use self::inftrees::{Code, ENOUGH, CODES, LENS, DISTS, inflate_table};
 use std::default::Default;
-use GZipHeader;
-use ZStream;
-use swap32;
-use Flush;
-use Z_DEFLATED;
-use WINDOW_BITS_DEFAULT;
-use WINDOW_BITS_MIN;
-use WINDOW_BITS_MAX;
-
-pub use self::reader::InflateReader;
+use crate::GZipHeader;
+use crate::ZStream;
+use crate::swap32;
+use crate::Flush;
+use crate::Z_DEFLATED;
+use crate::ZERR;
+use crate::WINDOW_BITS_DEFAULT;
+use crate::WINDOW_BITS_MIN;
+use crate::WINDOW_BITS_MAX;
+
+pub use self::reader::{InflateReader, BufInflateReader};
+pub use self::writer::InflateWriter;
+pub use self::index::{GzipIndex, Checkpoint};
 
 const DEFAULT_DMAX: usize = 32768;
 
 mod inffast;
 mod inftrees;
 mod reader;
+mod writer;
 mod inffixed;
+mod index;
 
 macro_rules! BADINPUT {
-    ($loc:expr, $msg:expr) => {
+    ($loc:expr, $err:ident) => {
+        {
+            $loc.state.bad_input_at(InflateError::$err, $loc.next, $loc.bits);
+            return;
+        }
+    };
+    ($loc:expr, $err:ident, $($arg:expr),+) => {
         {
-            warn!("bad input, total_in={}: {}", $loc.state.strm.total_in, $msg);
-            $loc.state.strm.msg = Some($msg);
-            $loc.state.mode = InflateMode::BAD;
+            $loc.state.bad_input_at(InflateError::$err($($arg),+), $loc.next, $loc.bits);
             return;
         }
     }
@@ -73,13 +80,123 @@ macro_rules! NEEDBITS {
 }
 
 /// Describes the results of calling `inflate()`.
-#[derive(Copy)]
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
 pub enum InflateResult
 {
-    Eof(u32),               // input data stream has reached its end; value is crc32 of stream
+    Eof(StreamEnd),         // input data stream has reached its end
     NeedInput,              // could decode more, but need more input buffer space
     Decoded(usize, usize),    // decoded N bytes of input, wrote N bytes of output
-    InvalidData,            // input data is malformed, decoding has halted
+    Error(InflateError, u64),   // input data is malformed, decoding has halted.  the value is the offset
+                            // in the input stream of the byte where the error was found.
+    NeedDictionary(u32),    // stream requires a preset dictionary; value is the Adler-32 of the dictionary.
+                            // call set_dictionary(), then call inflate() again.
+    Boundary(usize, usize), // decoded N bytes of input, wrote N bytes of output, and stopped at a block
+                            // boundary.  only returned for Flush::Block and Flush::Trees.
+}
+
+/// Describes why `inflate()` could not decode its input.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum InflateError
+{
+    // zlib and GZIP headers
+    IncorrectHeaderCheck,
+    UnknownCompressionMethod,
+    InvalidWindowSize,
+    UnknownHeaderFlags,
+    HeaderCrcMismatch,
+
+    // block headers and code tables
+    InvalidBlockType,
+    InvalidStoredBlockLengths,
+    TooManyLengthOrDistanceSymbols,
+    InvalidCodeLengthsSet,
+    InvalidBitLengthRepeat,
+    MissingEndOfBlock,
+    InvalidLiteralLengthsSet,
+    InvalidDistancesSet,
+
+    // compressed data
+    InvalidLiteralLengthCode,
+    InvalidDistanceCode,
+    InvalidDistanceTooFarBack,
+
+    // zlib and GZIP trailers.  the values are the expected value, from the trailer, and the
+    // actual value, computed from the decoded data.
+    IncorrectDataCheck(u32, u32),       // Adler-32 (zlib) or CRC-32 (GZIP) of the data
+    IncorrectLengthCheck(u32, u32),     // length of the data, modulo 2^32 (GZIP only)
+
+    // sync() has not found a full flush point yet
+    NotSynchronized,
+}
+
+impl InflateError {
+    /// Returns the message that zlib uses for this error.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            InflateError::IncorrectHeaderCheck => "incorrect header check",
+            InflateError::UnknownCompressionMethod => "unknown compression method",
+            InflateError::InvalidWindowSize => "invalid window size",
+            InflateError::UnknownHeaderFlags => "unknown header flags set",
+            InflateError::HeaderCrcMismatch => "header crc mismatch",
+            InflateError::InvalidBlockType => "invalid block type",
+            InflateError::InvalidStoredBlockLengths => "invalid stored block lengths",
+            InflateError::TooManyLengthOrDistanceSymbols => "too many length or distance symbols",
+            InflateError::InvalidCodeLengthsSet => "invalid code lengths set",
+            InflateError::InvalidBitLengthRepeat => "invalid bit length repeat",
+            InflateError::MissingEndOfBlock => "invalid code -- missing end-of-block",
+            InflateError::InvalidLiteralLengthsSet => "invalid literal/lengths set",
+            InflateError::InvalidDistancesSet => "invalid distances set",
+            InflateError::InvalidLiteralLengthCode => "invalid literal/length code",
+            InflateError::InvalidDistanceCode => "invalid distance code",
+            InflateError::InvalidDistanceTooFarBack => "invalid distance too far back",
+            InflateError::IncorrectDataCheck(..) => "incorrect data check",
+            InflateError::IncorrectLengthCheck(..) => "incorrect length check",
+            InflateError::NotSynchronized => "no full flush point found yet; call sync() with more input",
+        }
+    }
+}
+
+/// Describes a stream that has been completely decoded.  Returned by `inflate()` in
+/// `InflateResult::Eof`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct StreamEnd
+{
+    /// The Adler-32 (zlib) or CRC-32 (GZIP) of the decoded data.  Always 0 for raw DEFLATE
+    /// streams, which have no check value.
+    pub check: u32,
+
+    /// The number of bytes of decoded data.
+    pub total_out: u64,
+
+    /// True if `check` matched the value in the stream trailer and, for GZIP streams, the
+    /// length matched too.  False for raw DEFLATE streams, which have no trailer.  If the
+    /// trailer does not match, `inflate()` returns `IncorrectDataCheck` or
+    /// `IncorrectLengthCheck` instead of `Eof`.
+    pub verified: bool,
+}
+
+/// Describes the results of calling `sync()`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum SyncResult
+{
+    Found(usize),           // skipped N bytes of input, up to and including a full flush point.
+                            // call inflate() with the rest of the input.
+    NotFound(usize),        // skipped all N bytes of input without finding a full flush point.
+                            // call sync() again with more input.
+}
+
+/// Describes where the decoder is relative to the DEFLATE blocks of the stream.  See
+/// `Inflater::block_position()`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct BlockPosition
+{
+    pub at_block_boundary: bool,    // true if the decoder is waiting for the next block header
+    pub after_block_header: bool,   // true if the decoder has just read the header of a block,
+                                    // and has not decoded any of its data (see Flush::Trees)
+    pub last_block: bool,           // true if the last block of the stream has been reached
+    pub bits: usize,                // number of bits held in the bit buffer (unconsumed input);
+                                    // less than 8 at a block boundary, but can be more after
+                                    // a block header
 }
 
 // /* define NO_GZIP when compiling if you want to disable gzip header and
@@ -92,7 +209,7 @@ pub enum InflateResult
 // */
 
 /* Possible inflate modes between inflate() calls */
-#[derive(Show,Copy,PartialEq,Eq)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
 enum InflateMode {
     HEAD,       // i: waiting for magic header
     FLAGS,      // i: waiting for method and flags (gzip)
@@ -165,17 +282,25 @@ macro_rules! goto_mode {
 
 /// Decompresses ("inflates") a stream of data.  Supports both `GZIP` and raw `DEFLATE` streams.
 /// Use `Inflater::new()` to create a stream.
+///
+/// Cloning an `Inflater` copies the complete state of the decoder (was inflateCopy), so the
+/// copy can continue decoding from the same point in the stream, independently of the
+/// original.  The code tables are indexed by position in `codes`, rather than by pointer, so
+/// no adjustment is needed for the copy.
+#[derive(Clone)]
 pub struct Inflater // was inflate_state
 {
     mode: InflateMode,          // current inflate mode
     last: bool,                 // true if processing last block
     wrap: u32,                  // bit 0 true for zlib, bit 1 true for gzip
+    raw_fallback: bool,         // true to decode as raw deflate if there is no zlib or gzip header
     havedict: bool,             // true if dictionary provided
     flags: u32,                 // gzip header method and flags (0 if zlib)
     dmax: usize,                 // zlib header max distance (INFLATE_STRICT)
     check: u32,                 // protected copy of check value
     total: usize,                // protected copy of output count
     head: Option<GZipHeader>,   // where to save gzip header information
+    error: Option<(InflateError, u64)>, // why decoding failed, and the input offset (mode == BAD)
 
     // sliding window
     wbits: usize,                // log base 2 of requested window size
@@ -231,12 +356,50 @@ impl Inflater {
         Inflater::internal_new(WINDOW_BITS_DEFAULT, 2)
     }
 
-    /// Creates a new Inflater for decoding a raw DEFLATE stream.  This should not
-    /// be used for decoding GZIP streams.
-    pub fn new_inflate(window_bits: usize) -> Inflater {
+    /// Creates a new Inflater for decoding a zlib stream (RFC 1950).
+    ///
+    /// A zlib stream starts with a two-byte header, which may be followed by the
+    /// Adler-32 of a preset dictionary, and ends with the Adler-32 of the data.
+    pub fn new_zlib() -> Inflater {
+        Inflater::internal_new(WINDOW_BITS_DEFAULT, 1)
+    }
+
+    /// Creates a new Inflater for decoding either a zlib stream or a GZIP stream.  The
+    /// header at the start of the stream determines which.  (was windowBits + 32)
+    pub fn new_auto() -> Inflater {
+        Inflater::internal_new(WINDOW_BITS_DEFAULT, 3)
+    }
+
+    /// Creates a new Inflater for decoding a zlib stream, a GZIP stream, or a raw DEFLATE
+    /// stream.  If the stream does not start with a zlib or GZIP header, it is decoded as raw
+    /// DEFLATE.  Some HTTP servers send raw DEFLATE for "Content-Encoding: deflate".
+    ///
+    /// A raw DEFLATE stream can never look like a GZIP header, but about 1 in 1000 can look
+    /// like a zlib header, and those streams will fail to decode.  Use `new_auto()` if raw
+    /// DEFLATE streams are not expected.
+    pub fn new_auto_with_raw_fallback() -> Inflater {
+        let mut inflater = Inflater::internal_new(WINDOW_BITS_DEFAULT, 3);
+        inflater.raw_fallback = true;
+        inflater
+    }
+
+    /// Creates a new Inflater for decoding a raw DEFLATE stream, with no header or trailer,
+    /// such as the entries of a ZIP file.  `window_bits` must be at least the window size
+    /// used to compress the stream; for ZIP entries this is always 15.  (was negative
+    /// windowBits)
+    ///
+    /// Since there is no trailer, the stream ends with its last block.  Input after that is
+    /// not consumed, so the caller can find where the stream ended by adding up the input
+    /// byte counts returned by `inflate()`.
+    pub fn new_raw(window_bits: usize) -> Inflater {
         Inflater::internal_new(window_bits, 0)
     }
 
+    /// Same as `new_raw()`.
+    pub fn new_inflate(window_bits: usize) -> Inflater {
+        Inflater::new_raw(window_bits)
+    }
+
     fn internal_new(window_bits: usize, wrap: u32) -> Inflater {
         assert!(window_bits >= WINDOW_BITS_MIN && window_bits <= WINDOW_BITS_MAX);
 
@@ -246,23 +409,21 @@ impl Inflater {
             mode: InflateMode::HEAD,
             last: false,
             wrap: wrap,                 // bit 0 true for zlib, bit 1 true for gzip
+            raw_fallback: false,        // true to decode as raw deflate if there is no zlib or gzip header
             havedict: false,            // true if dictionary provided
             flags: 0,                   // gzip header method and flags (0 if zlib)
             dmax: DEFAULT_DMAX,         // zlib header max distance (INFLATE_STRICT)
             check: 0,                   // protected copy of check value
             total: 0,                   // protected copy of output count
             head: None,                 // where to save gzip header information
+            error: None,                // why decoding failed, if mode == BAD
 
             // sliding window
             wbits: window_bits,         // log base 2 of requested window size
             wsize: wsize,               // window size or zero if not using window
             whave: 0,                   // valid bytes in the window
             wnext: 0,                   // window write index
-            window: {
-                let mut w = Vec::with_capacity(wsize);         // allocated sliding window, if needed
-                w.extend(repeat(0u8).take(wsize));
-                w
-            },
+            window: vec![0u8; wsize],   // allocated sliding window, if needed
 
             // bit accumulator
             hold: 0,                    // input bit accumulator
@@ -290,8 +451,8 @@ impl Inflater {
             lens: [0u16; 320],          // temporary storage for code lengths
             work: [0u16; 288],          // work area for code table building
             codes: [Default::default(); ENOUGH],    // space for code tables
-            sane: false,                // if false, allow invalid distance too far
-            back: 0,                    // bits back of last unprocessed length/lit
+            sane: true,                 // if false, allow invalid distance too far
+            back: !0,                   // bits back of last unprocessed length/lit
             was: 0,                     // initial length of match
             strm: ZStream::new(),
 
@@ -305,7 +466,12 @@ impl Inflater {
         self.strm.total_in = 0;
         self.strm.total_out = 0;
         self.total = 0;
+        self.check = 0;
         self.strm.msg = None;
+        if self.raw_fallback {
+            // the last stream may have been decoded as raw deflate
+            self.wrap = 3;
+        }
         if self.wrap != 0 {
             // to support ill-conceived Java test suite
             self.strm.adler = self.wrap as u32 & 1;
@@ -315,6 +481,7 @@ impl Inflater {
         self.havedict = false;
         self.dmax = DEFAULT_DMAX;
         self.head = None;
+        self.error = None;
         self.hold = 0;
         self.bits = 0;
 
@@ -323,7 +490,7 @@ impl Inflater {
         self.next = 0;         // index into self.codes
 
         self.sane = true;
-        self.back = -1;
+        self.back = !0;
         // debug!("inflate: reset");
 
         self.counter_mainloop = 0;
@@ -354,6 +521,200 @@ impl Inflater {
         self.bits += bits as usize;
     }
 
+    /// Requests that the GZIP header be saved as it is decoded.  (was inflateGetHeader)
+    ///
+    /// Call this before the header has been decoded, i.e. before the first call to `inflate()`
+    /// or after `reset()`, which discards any saved header.  Once `inflate()` has decoded the
+    /// whole header, it is available from `header()`.  Returns `ZERR::StreamError` if this
+    /// Inflater does not decode GZIP streams.
+    pub fn capture_header(&mut self) -> ZERR {
+        if (self.wrap & 2) == 0 {
+            return ZERR::StreamError;
+        }
+        self.head = Some(GZipHeader::new());
+        ZERR::Ok
+    }
+
+    /// Returns the GZIP header, if `capture_header()` was called and the header has been
+    /// completely decoded.
+    pub fn header(&self) -> Option<&GZipHeader> {
+        match self.head {
+            Some(ref h) if h.done => Some(h),
+            _ => None
+        }
+    }
+
+    /// Provides the preset dictionary for the stream.  (was inflateSetDictionary)
+    ///
+    /// For a zlib stream, call this after `inflate()` returns `InflateResult::NeedDictionary`.
+    /// The Adler-32 of `dictionary` must match the dictionary ID in the stream; if it does not,
+    /// this returns `ZERR::DataError` and the dictionary is not used.  For a raw DEFLATE stream,
+    /// call this before the first call to `inflate()`.  Returns `ZERR::StreamError` if the stream
+    /// is not expecting a dictionary.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> ZERR {
+        if self.wrap != 0 && self.mode != InflateMode::DICT {
+            return ZERR::StreamError;
+        }
+
+        // check for correct dictionary identifier
+        if self.mode == InflateMode::DICT {
+            let dictid = adler32(1, dictionary);
+            if dictid != self.check {
+                warn!("set_dictionary: wrong dictionary, expected dictid 0x{:08x}, got 0x{:08x}", self.check, dictid);
+                return ZERR::DataError;
+            }
+        }
+
+        // copy dictionary to window using updatewindow(), which will amend the
+        // existing dictionary if appropriate
+        updatewindow(self, dictionary);
+        self.havedict = true;
+        debug!("inflate:   dictionary set");
+        ZERR::Ok
+    }
+
+    // Returns the contents of the sliding window, oldest byte first.  This is the last 32K
+    // (or less) of output.
+    fn window_contents(&self) -> Vec<u8> {
+        let mut contents: Vec<u8> = Vec::with_capacity(self.whave);
+        contents.extend_from_slice(&self.window[self.wnext..self.whave]);
+        contents.extend_from_slice(&self.window[..self.wnext]);
+        contents
+    }
+
+    // Records why decoding failed, and stops decoding.
+    fn bad_input(&mut self, err: InflateError, offset: u64) {
+        warn!("bad input, offset={}: {}", offset, err.description());
+        self.strm.msg = Some(err.description());
+        self.error = Some((err, offset));
+        self.mode = InflateMode::BAD;
+    }
+
+    // Like bad_input(), for an error found 'next' bytes into the current input, with 'bits'
+    // bits in the accumulator.  The offset is that of the byte holding the first bit that has
+    // not been consumed.
+    fn bad_input_at(&mut self, err: InflateError, next: usize, bits: usize) {
+        let offset = self.strm.total_in + next as u64 - ((bits + 7) / 8) as u64;
+        self.bad_input(err, offset);
+    }
+
+    // Describes the stream, once mode == DONE.  The trailer, if any, has been verified.
+    fn stream_end(&self) -> StreamEnd {
+        StreamEnd {
+            check: self.check,
+            total_out: self.total as u64,
+            verified: self.wrap != 0,
+        }
+    }
+
+    /// Skips invalid compressed data until a full flush point is found.  (was inflateSync)
+    ///
+    /// A full flush point is the empty stored block written by a full flush, which ends with
+    /// the bytes 00 00 FF FF.  If `inflate()` returns `InflateResult::Error`, or the
+    /// start of the stream has been lost, call `sync()` with the remaining input.  If it
+    /// returns `SyncResult::Found(n)`, then `inflate()` can resume decoding at the next block,
+    /// starting with `input[n..]`.  If it returns `SyncResult::NotFound(n)`, then all of the
+    /// input was skipped; call `sync()` again with more input.
+    ///
+    /// The window is discarded, so data following a sync flush (rather than a full flush) may
+    /// refer to data that is no longer available.  Since the data before the flush point was
+    /// lost, the check value in the trailer of a zlib or GZIP stream will not match.
+    pub fn sync(&mut self, input: &[u8]) -> SyncResult {
+        // if first time, start search in bit buffer
+        if self.mode != InflateMode::SYNC {
+            self.mode = InflateMode::SYNC;
+            self.hold <<= self.bits & 7;
+            self.bits -= self.bits & 7;
+            let mut buf = [0u8; 4];
+            let mut len: usize = 0;
+            while self.bits >= 8 {
+                buf[len] = self.hold as u8;
+                len += 1;
+                self.hold >>= 8;
+                self.bits -= 8;
+            }
+            self.have = 0;
+            syncsearch(&mut self.have, &buf[..len]);
+        }
+
+        // search available input
+        let len = syncsearch(&mut self.have, input);
+        self.strm.total_in += len as u64;
+
+        // return no joy or set up to restart inflate() on a new block
+        if self.have != 4 {
+            debug!("sync: skipped {} bytes, flush point not found", len);
+            return SyncResult::NotFound(len);
+        }
+        let total_in = self.strm.total_in;
+        let total_out = self.strm.total_out;
+        self.reset();
+        self.strm.total_in = total_in;
+        self.strm.total_out = total_out;
+        self.mode = InflateMode::TYPE;
+        debug!("sync: found flush point, skipped {} bytes", len);
+        SyncResult::Found(len)
+    }
+
+    /// Returns true if the decoder is at the end of a block generated by a sync or full
+    /// flush, and is waiting for the length bytes of the empty stored block.  (was
+    /// inflateSyncPoint)  This is used by the PPP protocol, which removes those length bytes.
+    pub fn sync_point(&self) -> bool {
+        self.mode == InflateMode::STORED && self.bits == 0
+    }
+
+    /// Returns the input bytes that have been counted as consumed, but are still held unused
+    /// in the bit accumulator.  Once the stream has ended, these are the first bytes after it.
+    ///
+    /// `inflate()` reads input a byte at a time into the bit accumulator, and a raw DEFLATE
+    /// stream can end before all of those bytes have been used.  (A zlib or GZIP stream ends
+    /// with its trailer, which uses all of them.)  A caller that reads data after the stream
+    /// must start with these bytes.
+    pub fn unused_input(&self) -> Vec<u8> {
+        let mut hold = self.hold >> (self.bits & 7);
+        let mut unused: Vec<u8> = Vec::with_capacity(self.bits / 8);
+        for _ in 0..self.bits / 8 {
+            unused.push(hold as u8);
+            hold >>= 8;
+        }
+        unused
+    }
+
+    /// Returns the position of the decoder within the stream.  (was inflateMark)
+    ///
+    /// The upper 16 bits (as a signed value) are the number of bits back from the current
+    /// input position to the start of the length/literal code being decoded, or -1 if the
+    /// decoder is not in the middle of decoding a code.  The lower 16 bits are the number of
+    /// bytes of a stored block or match that remain to be copied to the output.  If the upper
+    /// value is -1 and the lower value is 0, then the decoder is at a point where it can be
+    /// resumed with only the window and the bits pending in the last input byte.
+    pub fn mark(&self) -> i64 {
+        ((self.back as isize as i64) << 16) + match self.mode {
+            InflateMode::COPY => self.length as i64,
+            InflateMode::MATCH => (self.was - self.length) as i64,
+            _ => 0
+        }
+    }
+
+    /// Describes where the decoder is relative to the DEFLATE blocks of the stream.  This is
+    /// the same information that zlib returns in `data_type`.  Together with the input position
+    /// and the window, it is enough to resume decoding at a block boundary.
+    pub fn block_position(&self) -> BlockPosition {
+        BlockPosition {
+            at_block_boundary: self.mode == InflateMode::TYPE,
+            after_block_header: self.mode == InflateMode::LEN_ || self.mode == InflateMode::COPY_,
+            last_block: self.last,
+            bits: self.bits,
+        }
+    }
+
+    /// Decodes data from `input_buffer` and writes it to `output_buffer`.
+    ///
+    /// `flush` is normally `None`.  With `Some(Flush::Block)`, inflate() stops at the start of
+    /// each DEFLATE block (after the zlib or GZIP header, for the first block of those streams)
+    /// and after the end of the last block, and returns `InflateResult::Boundary`.  With
+    /// `Some(Flush::Trees)`, it also stops after each block header, before any of the block's
+    /// data is decoded.  Use `block_position()` to tell these apart.
     pub fn inflate(
         &mut self,
         flush: Option<Flush>,
@@ -364,10 +725,17 @@ impl Inflater {
 
         match self.mode {
             InflateMode::BAD => {
-                return InflateResult::InvalidData
+                let (err, offset) = self.error.expect("mode is BAD, but no error was recorded");
+                return InflateResult::Error(err, offset)
             }
             InflateMode::DONE => {
-                return InflateResult::Eof(self.check)
+                return InflateResult::Eof(self.stream_end())
+            }
+            InflateMode::DICT if !self.havedict => {
+                return InflateResult::NeedDictionary(self.check)
+            }
+            InflateMode::SYNC => {
+                return InflateResult::Error(InflateError::NotSynchronized, self.strm.total_in)
             }
             _ => ()
         }
@@ -385,6 +753,7 @@ impl Inflater {
             bits: 0,
             next: 0,
             put: 0,
+            out: 0,
             flush: flush,
             is_goto: false,
         };
@@ -409,7 +778,7 @@ impl Inflater {
             if loc.state.mode as u32 >= InflateMode::CHECK as u32 {
                 put = 0; // don't ask
             }
-            updatewindow(&mut loc, put, put);
+            updatewindow(loc.state, &loc.output_buffer[..put]);
         }
 
         debug!("avail_in={} avail_out={}", loc.avail_in(), loc.avail_out());
@@ -417,12 +786,13 @@ impl Inflater {
         let in_inflated = loc.next;
         let out_inflated = loc.put;
 
+        // The CHECK state may have already counted some of the output.
         loc.state.strm.total_in += in_inflated as u64;
-        loc.state.strm.total_out += out_inflated as u64;
-        loc.state.total += out_inflated;
+        loc.state.strm.total_out += (out_inflated - loc.out) as u64;
+        loc.state.total += out_inflated - loc.out;
 
-        if loc.state.wrap != 0 && out_inflated != 0 {
-            let updated_check = update(loc.state.flags, loc.state.check, loc.output_buffer.slice_to(out_inflated));
+        if loc.state.wrap != 0 && out_inflated != loc.out {
+            let updated_check = update(loc.state.flags, loc.state.check, &loc.output_buffer[loc.out..out_inflated]);
             loc.state.strm.adler = updated_check;
             loc.state.check = updated_check;
         }
@@ -436,11 +806,27 @@ impl Inflater {
     //    }
     //
 
-        if in_inflated != 0 || out_inflated != 0 {
+        // With Flush::Block or Flush::Trees, report where inflate() stopped.  This can happen
+        // without consuming any input, if the block header was already in the bit buffer.
+        if (loc.flush == Flush::Block || loc.flush == Flush::Trees) && (loc.state.mode == InflateMode::TYPE
+            || loc.state.mode == InflateMode::LEN_ || loc.state.mode == InflateMode::COPY_) {
+            debug!("stopped at block boundary, mode = {:?}", loc.state.mode);
+            InflateResult::Boundary(in_inflated, out_inflated)
+        }
+        else if loc.state.mode == InflateMode::BAD && out_inflated == 0 {
+            // Report the error now, unless there is output that the caller needs to see first.
+            let (err, offset) = loc.state.error.expect("mode is BAD, but no error was recorded");
+            InflateResult::Error(err, offset)
+        }
+        else if in_inflated != 0 || out_inflated != 0 {
             InflateResult::Decoded(in_inflated, out_inflated)
         }
         else if loc.state.mode == InflateMode::DONE {
-            InflateResult::Eof(loc.state.check)
+            InflateResult::Eof(loc.state.stream_end())
+        }
+        else if loc.state.mode == InflateMode::DICT && !loc.state.havedict {
+            debug!("need dictionary, dictid = 0x{:08x}", loc.state.check);
+            InflateResult::NeedDictionary(loc.state.check)
         }
         else {
             warn!("need input, mode = {:?}", loc.state.mode);
@@ -468,10 +854,10 @@ impl Inflater {
         load_locals(loc);
 
         // ret = Z_OK;
-        'mainloop: loop {
+        loop {
             loc.state.counter_mainloop += 1;
 
-            if !cfg!(ndebug) {
+            if cfg!(debug_assertions) {
                 if loc.is_goto {
                     loc.is_goto = false;
                 }
@@ -501,6 +887,14 @@ impl Inflater {
 
                 debug!("hold = 0x{:08x}, wrap = {}", loc.hold, loc.state.wrap);
 
+                if loc.state.raw_fallback && !is_zlib_header(loc.hold, loc.state.wbits) {
+                    // The header bits are left in the bit buffer, where they are the start
+                    // of the first deflate block.
+                    debug!("no zlib or gzip header, decoding as raw deflate");
+                    loc.state.wrap = 0;
+                    goto_mode!(loc, TYPEDO);
+                }
+
                 loc.state.flags = 0;           /* expect zlib header */
 
                 /*
@@ -519,10 +913,10 @@ impl Inflater {
     // #endif
                     ((((bits(loc, 8) as u32) << 8) + (loc.hold >> 8)) % 31) != 0 {
                     warn!("incorrect header check.  bits(8) = 0x{:2x}", bits(loc, 8));
-                    BADINPUT!(loc, "incorrect header check");
+                    BADINPUT!(loc, IncorrectHeaderCheck);
                 }
                 if bits(loc, 4) != Z_DEFLATED as u32 {
-                    BADINPUT!(loc, "unknown compression method");
+                    BADINPUT!(loc, UnknownCompressionMethod);
                 }
                 dropbits(loc, 4);
                 len = (bits(loc, 4) + 8) as usize;
@@ -530,12 +924,12 @@ impl Inflater {
                     loc.state.wbits = len;
                 }
                 else if len > loc.state.wbits {
-                    BADINPUT!(loc, "invalid window size");
+                    BADINPUT!(loc, InvalidWindowSize);
                 }
                 loc.state.dmax = 1 << len;
                 // debug!("max distance (dmax) = {} 0x{:x}", loc.state.dmax, loc.state.dmax);
                 // debug!("inflate:   zlib header ok");
-                let adler_value = adler32(0, &[]);
+                let adler_value = adler32(1, &[]);
                 loc.state.strm.adler = adler_value;
                 loc.state.check = adler_value;
                 loc.state.mode = if (loc.hold & 0x200) != 0 { InflateMode::DICTID } else { InflateMode::TYPE };
@@ -553,10 +947,10 @@ impl Inflater {
 
                 loc.state.flags = loc.hold;
                 if method != Z_DEFLATED as u32 {
-                    BADINPUT!(loc, "unknown compression method");
+                    BADINPUT!(loc, UnknownCompressionMethod);
                 }
                 if (loc.state.flags & 0xe000) != 0 {
-                    BADINPUT!(loc, "unknown header flags set");
+                    BADINPUT!(loc, UnknownHeaderFlags);
                 }
 
                 match loc.state.head {
@@ -578,12 +972,15 @@ impl Inflater {
 
             InflateMode::TIME => {
                 NEEDBITS!(loc, 32);
-                let time :u32 = loc.state.hold;
+                let time :u32 = loc.hold;
                 // debug!("TIME: t: {}", time);
 
-                /*if (state.head != Z_NULL)
-                    state.head.time = time;
-                */
+                match loc.state.head {
+                    Some(ref mut h) => {
+                        h.time = time;
+                    }
+                    None => ()
+                }
 
                 if (loc.state.flags & 0x0200) != 0 {
                     loc.state.check = crc4(loc.state.check, time);
@@ -594,7 +991,7 @@ impl Inflater {
 
             InflateMode::OS => {
                 NEEDBITS!(loc, 16);
-                let ostype = loc.state.hold;
+                let ostype = loc.hold;
                 let xflags = ostype & 0xff;
                 let os = ostype >> 8;
                 // debug!("OS: os 0x{:x} xflags 0x{:x}", os, xflags);
@@ -615,7 +1012,7 @@ impl Inflater {
             InflateMode::EXLEN => {
                 if (loc.state.flags & 0x0400) != 0 {
                     NEEDBITS!(loc, 16);
-                    let extra_len = loc.state.hold & 0xffff;
+                    let extra_len = loc.hold & 0xffff;
                     loc.state.length = extra_len as usize;
 
                     // debug!("EXTRALEN: extra_len = {}", extra_len);
@@ -623,6 +1020,7 @@ impl Inflater {
                     match loc.state.head {
                         Some(ref mut h) => {
                             h.extra_len = extra_len as usize;
+                            h.extra = Some(Vec::with_capacity(extra_len as usize));
                         }
                         _ => ()
                     }
@@ -637,6 +1035,7 @@ impl Inflater {
                     match loc.state.head {
                         Some(ref mut h) => {
                             h.extra_len = 0;
+                            h.extra = None;
                         }
                         None => ()
                     }
@@ -651,16 +1050,14 @@ impl Inflater {
                         copy = loc.have();
                     }
                     if copy != 0 {
-                        /* TODO
-                        if (state.head != Z_NULL && state.head.extra != Z_NULL) {
-                            len = state.head.extra_len - state.length;
-                            copy_memory(state.head.extra + len, next,
-                                    if len + copy > state.head.extra_max
-                                    { state.head.extra_max - len } else { copy });
+                        match loc.state.head {
+                            Some(GZipHeader { extra: Some(ref mut extra), .. }) => {
+                                extra.extend_from_slice(&loc.input_buffer[loc.next..loc.next + copy]);
+                            }
+                            _ => ()
                         }
-                        */
                         if (loc.state.flags & 0x0200) != 0 {
-                            loc.state.check = crc32(loc.state.check, loc.input_buffer.slice(loc.next, loc.next + copy));
+                            loc.state.check = crc32(loc.state.check, &loc.input_buffer[loc.next..loc.next + copy]);
                         }
                         loc.next += copy;
                         loc.state.length -= copy;
@@ -686,27 +1083,36 @@ impl Inflater {
                     loop {
                         len = loc.input_buffer[loc.next + copy] as usize;
                         copy += 1;
-
-                        /* TODO
-                        if (state.head != Z_NULL && state.head.name != Z_NULL && state.length < state.head.name_max) {
-                            state.head.name[state.length++] = len;
-                        } */
-
                         if !(len != 0 && copy < loc.have()) {
                              break;
                         }
                     }
+                    match loc.state.head {
+                        Some(ref mut h) => {
+                            // The name may arrive over several calls; don't store the terminating zero.
+                            let end = if len == 0 { copy - 1 } else { copy };
+                            let name = &loc.input_buffer[loc.next..loc.next + end];
+                            match h.name {
+                                Some(ref mut v) => v.extend_from_slice(name),
+                                None => h.name = Some(name.to_vec())
+                            }
+                        }
+                        None => ()
+                    }
                     if (loc.state.flags & 0x0200) != 0 {
-                        loc.state.check = crc32(loc.state.check, loc.input_buffer.slice(loc.next, loc.next + copy));
+                        loc.state.check = crc32(loc.state.check, &loc.input_buffer[loc.next..loc.next + copy]);
                     }
                     loc.next += copy;
                     if len != 0 { break; }
                 }
                 else {
                     // debug!("NAME: header does not contain a NAME record");
-                    /*TODO if (state.head != Z_NULL) {
-                        state.head.name = Z_NULL;
-                    }*/
+                    match loc.state.head {
+                        Some(ref mut h) => {
+                            h.name = None;
+                        }
+                        None => ()
+                    }
                 }
                 loc.state.length = 0;
                 goto_mode!(loc, COMMENT);
@@ -724,22 +1130,23 @@ impl Inflater {
                     loop {
                         len = loc.input_buffer[loc.next + copy];
                         copy += 1;
-
-                        /* TODO
-                        if (state.head != Z_NULL &&
-                                state.head.comment != Z_NULL &&
-                                state.length < state.head.comm_max) {
-                            state.head.comment[state.length] = len;
-                            state.length += 1;
-                        }
-                        */
-
                         if !(len != 0 && copy < loc.have()) {
                             break;
                         }
                     }
+                    match loc.state.head {
+                        Some(ref mut h) => {
+                            let end = if len == 0 { copy - 1 } else { copy };
+                            let comment = &loc.input_buffer[loc.next..loc.next + end];
+                            match h.comment {
+                                Some(ref mut v) => v.extend_from_slice(comment),
+                                None => h.comment = Some(comment.to_vec())
+                            }
+                        }
+                        None => ()
+                    }
                     if (loc.state.flags & 0x0200) != 0 {
-                        loc.state.check = crc32(loc.state.check, loc.input_buffer.slice(loc.next, loc.next + copy));
+                        loc.state.check = crc32(loc.state.check, &loc.input_buffer[loc.next..loc.next + copy]);
                     }
                     loc.next += copy;
                     if len != 0 {
@@ -749,9 +1156,12 @@ impl Inflater {
                 }
                 else {
                     // debug!("COMMENT: header does not contain a COMMENT record");
-                    // TODO
-                    // if (state.head != Z_NULL)
-                    //     state.head.comment = Z_NULL;
+                    match loc.state.head {
+                        Some(ref mut h) => {
+                            h.comment = None;
+                        }
+                        None => ()
+                    }
                 }
                 goto_mode!(loc, HCRC);
             }
@@ -761,17 +1171,18 @@ impl Inflater {
                     NEEDBITS!(loc, 16);
                     let expected_crc = loc.hold;
                     // debug!("HCRC: header says expected CRC = 0x{:x}", expected_crc);
-                    /* TODO - CRC
                     if expected_crc != (loc.state.check & 0xffff) {
-                        BADINPUT!(loc, "header crc mismatch");
+                        BADINPUT!(loc, HeaderCrcMismatch);
                     }
-                    */
                     initbits(loc);
                 }
-                /* TODO if (state.head != Z_NULL) {
-                    loc.state.head.hcrc = (int)((state.flags >> 9) & 1);
-                    loc.state.head.done = 1;
-                }*/
+                match loc.state.head {
+                    Some(ref mut h) => {
+                        h.hcrc = (loc.state.flags & 0x0200) != 0;
+                        h.done = true;
+                    }
+                    None => ()
+                }
                 let initial_crc = crc32(0, &[]);
                 loc.state.strm.adler = initial_crc;
                 loc.state.check = initial_crc;
@@ -791,10 +1202,9 @@ impl Inflater {
             InflateMode::DICT => {
                 if !loc.state.havedict {
                     debug!("do not have dictionary, returning Z_NEED_DICT");
-                    restore_locals(loc);
-                    unimplemented!(); // return Z_NEED_DICT;
+                    break;
                 }
-                let check = adler32(0, &[]);
+                let check = adler32(1, &[]);
                 loc.state.strm.adler = check;
                 loc.state.check = check;
                 goto_mode!(loc, TYPE);
@@ -855,7 +1265,7 @@ impl Inflater {
                             loc.state.mode = InflateMode::TABLE;
                         }
                         3 => {
-                            BADINPUT!(loc, "invalid block type");
+                            BADINPUT!(loc, InvalidBlockType);
                         }
                         _ => { unreachable!(); }
                     }
@@ -871,7 +1281,7 @@ impl Inflater {
                 let invlen = (loc.hold >> 16) ^ 0xffff;
                 if len != invlen {
                     warn!("invalid stored block lengths;  hold=0x{:08x}  len=0x{:04x}  invlen=0x{:04x}", loc.hold, len, invlen);
-                    BADINPUT!(loc, "invalid stored block lengths");
+                    BADINPUT!(loc, InvalidStoredBlockLengths);
                 }
                 debug!("inflate:       stored length {}", len);
                 loc.state.length = len as usize;
@@ -896,7 +1306,7 @@ impl Inflater {
                         // debug!("cannot copy data right now (no buffer space) -- exiting");
                         break;
                     }
-                    copy_memory(loc.output_buffer.slice_mut(loc.put, loc.put + copy), loc.input_buffer.slice(loc.next, loc.next + copy));
+                    loc.output_buffer[loc.put..loc.put + copy].copy_from_slice(&loc.input_buffer[loc.next..loc.next + copy]);
                     loc.next += copy;
                     loc.put += copy;
                     loc.state.length -= copy;
@@ -916,7 +1326,7 @@ impl Inflater {
                 // debug!("TABLE: nlen {} ndist {} ncode {}", loc.state.nlen, loc.state.ndist, loc.state.ncode);
     // #ifndef PKZIP_BUG_WORKAROUND
                 if loc.state.nlen > 286 || loc.state.ndist > 30 {
-                    BADINPUT!(loc, "too many length or distance symbols");
+                    BADINPUT!(loc, TooManyLengthOrDistanceSymbols);
                 }
     // #endif
                 debug!("inflate:       table sizes ok");
@@ -950,7 +1360,7 @@ impl Inflater {
                 ret = inflate_ret as usize;
                 loc.state.lenbits = inflate_bits;
                 if ret != 0 {
-                    BADINPUT!(loc, "invalid code lengths set");
+                    BADINPUT!(loc, InvalidCodeLengthsSet);
                 }
                 debug!("inflate:       code lengths ok");
                 loc.state.have = 0;
@@ -972,7 +1382,7 @@ impl Inflater {
                             NEEDBITS!(loc, here.bits as usize + 2);
                             dropbits(loc, here.bits as usize);
                             if loc.state.have == 0 {
-                                BADINPUT!(loc, "invalid bit length repeat");
+                                BADINPUT!(loc, InvalidBitLengthRepeat);
                             }
                             (loc.state.lens[loc.state.have as usize - 1], 3 + bits_and_drop(loc, 2) as usize)
                         }
@@ -987,9 +1397,9 @@ impl Inflater {
                             (0, 11 + bits_and_drop(loc, 7) as usize)
                         };
                         if loc.state.have + copy > loc.state.nlen + loc.state.ndist {
-                            BADINPUT!(loc, "invalid bit length repeat");
+                            BADINPUT!(loc, InvalidBitLengthRepeat);
                         }
-                        for _ in range(0, copy) {
+                        for _ in 0..copy {
                             loc.state.lens[loc.state.have] = len as u16;
                             loc.state.have += 1;
                         }
@@ -998,7 +1408,7 @@ impl Inflater {
 
                 // check for end-of-block code (better have one)
                 if loc.state.lens[256] == 0 {
-                    BADINPUT!(loc, "(CODELENS) invalid code -- missing end-of-block");
+                    BADINPUT!(loc, MissingEndOfBlock);
                 }
 
                 // build code tables -- note: do not change the lenbits or distbits
@@ -1014,7 +1424,7 @@ impl Inflater {
                 ret = inflate_result as usize;
                 loc.state.lenbits = inflate_bits;
                 if ret != 0 {
-                    BADINPUT!(loc, "invalid literal/lengths set");
+                    BADINPUT!(loc, InvalidLiteralLengthsSet);
                 }
                 loc.state.distcode = loc.state.next;
                 loc.state.distbits = 6;
@@ -1022,11 +1432,11 @@ impl Inflater {
                 // debug!("loc.state.lens = {}, loc.state.nlen = {}, loc.state.ndist = {}", loc.state.lens.len(), loc.state.nlen, loc.state.ndist);
 
                 let (inflate_ret, inflate_bits) = {
-                    let codes_lens = loc.state.lens.slice(loc.state.nlen, loc.state.nlen + loc.state.ndist);
+                    let codes_lens = &loc.state.lens[loc.state.nlen..loc.state.nlen + loc.state.ndist];
                     inflate_table(DISTS, codes_lens, loc.state.ndist,
                                 &mut loc.state.codes, &mut loc.state.next, loc.state.distbits, loc.state.work.as_mut_slice()) };
                 if inflate_ret != 0 {
-                    BADINPUT!(loc, "invalid distances set");
+                    BADINPUT!(loc, InvalidDistancesSet);
                 }
                 loc.state.distbits = inflate_bits;
                 debug!("inflate:       codes ok");
@@ -1057,7 +1467,7 @@ impl Inflater {
                     load_locals(loc);
                     debug!("left={}", loc.left());
                     if loc.state.mode == InflateMode::TYPE {
-                        loc.state.back = -1;
+                        loc.state.back = !0;
                     }
                 }
                 else {
@@ -1098,12 +1508,12 @@ impl Inflater {
                     }
                     if (here.op & 32) != 0 {
                         debug!("inflate:         end of block");
-                        loc.state.back = -1;
+                        loc.state.back = !0;
                         loc.state.mode = InflateMode::TYPE;
                         continue;
                     }
                     if (here.op & 64) != 0 {
-                        BADINPUT!(loc, "invalid literal/length code");
+                        BADINPUT!(loc, InvalidLiteralLengthCode);
                     }
                     loc.state.extra = (here.op & 15) as usize;
                     goto_mode!(loc, LENEXT);
@@ -1148,7 +1558,7 @@ impl Inflater {
                 dropbits(loc, here.bits as usize);
                 loc.state.back += here.bits as usize;
                 if (here.op & 64) != 0 {
-                    BADINPUT!(loc, "invalid distance code");
+                    BADINPUT!(loc, InvalidDistanceCode);
                 }
                 loc.state.offset = here.val as usize;
                 loc.state.extra = (here.op & 15) as usize;
@@ -1165,7 +1575,7 @@ impl Inflater {
                 }
     // #ifdef INFLATE_STRICT
                 if loc.state.offset > loc.state.dmax {
-                    BADINPUT!(loc, "invalid distance too far back");
+                    BADINPUT!(loc, InvalidDistanceTooFarBack);
                 }
     // #endif
                 debug!("inflate:         distance {}", loc.state.offset);
@@ -1185,7 +1595,7 @@ impl Inflater {
                     debug!("copy from window, copy={}", copy);
                     if copy > loc.state.whave {
                         if loc.state.sane {
-                            BADINPUT!(loc, "invalid distance too far back");
+                            BADINPUT!(loc, InvalidDistanceTooFarBack);
                         }
     // #ifdef INFLATE_ALLOW_INVALID_DISTANCE_TOOFAR_ARRR
                         debug!("inflate.c too far");
@@ -1273,22 +1683,23 @@ impl Inflater {
                 // let mut from: usize; // index into loc.input_buffer
                 if loc.state.wrap != 0 {
                     NEEDBITS!(loc, 32);
-                    loc.state.strm.total_out += loc.put as u64;
-                    loc.state.total += loc.put;
-                    if loc.put != 0 {
-                        let check = update(loc.state.flags, loc.state.check, loc.output_buffer.slice_to(loc.put));
+                    loc.state.strm.total_out += (loc.put - loc.out) as u64;
+                    loc.state.total += loc.put - loc.out;
+                    if loc.put != loc.out {
+                        let check = update(loc.state.flags, loc.state.check, &loc.output_buffer[loc.out..loc.put]);
                         loc.state.strm.adler = check;
                         loc.state.check = check;
                     }
+                    loc.out = loc.put;
     // #ifdef GUNZIP
                     let ch = if loc.state.flags != 0 { loc.hold } else { swap32(loc.hold) };
                     if ch != loc.state.check {
-                        // not implemented yet
-                        // BADINPUT!(loc, "incorrect data check");
+                        let check = loc.state.check;
+                        BADINPUT!(loc, IncorrectDataCheck, ch, check);
                     }
     // #else
     //              if ((ZSWAP32(hold)) != state.check) {
-    //                  BADINPUT!(loc, "incorrect data check");
+    //                  BADINPUT!(loc, IncorrectDataCheck);
     //              }
     // #endif
                     initbits(loc);
@@ -1301,12 +1712,12 @@ impl Inflater {
             InflateMode::LENGTH => {
                 if loc.state.wrap != 0 && loc.state.flags != 0 {
                     NEEDBITS!(loc, 32);
-                    /* -- need to fix
-                    if loc.hold != (loc.state.total & 0xffffffff) as u32 {
-                        warn!("LENGTH: expected 0x{:08x}, instead got 0x{:08x}", loc.hold, (loc.state.total & 0xffffffff) as u32);
-                        BADINPUT!(loc, "incorrect length check");
+                    let total = (loc.state.total & 0xffffffff) as u32;
+                    if loc.hold != total {
+                        warn!("LENGTH: expected 0x{:08x}, instead got 0x{:08x}", loc.hold, total);
+                        let expected = loc.hold;
+                        BADINPUT!(loc, IncorrectLengthCheck, expected, total);
                     }
-                    */
                     initbits(loc);
                     debug!("inflate:   length matches trailer");
                 }
@@ -1322,16 +1733,9 @@ impl Inflater {
             }
 
             InflateMode::BAD => {
+                // inflate_fast() found invalid data
                 debug!("BAD state -- input data is invalid");
-                match loc.state.strm.msg {
-                    Some(ref errmsg) => {
-                        panic!("InflateMode::BAD: total_in = {}, message: {}", loc.state.strm.total_in, errmsg);
-                    }
-                    _ => {}
-                }
-                panic!();
-                // ret = Z_DATA_ERROR;
-                // return inf_leave(loc);
+                break;
             }
 
             /*
@@ -1392,7 +1796,7 @@ impl Inflater {
     // #   include "inffixed.h"
     // #endif /* BUILDFIXED */
         // ::std::slice::bytes::copy_memory(self.codes.as_mut_slice(), fixed.as_slice());
-        for i in range(0, 544) {
+        for i in 0..544 {
             self.codes[i] = fixed[i];
         }
         self.lencode = lenfix;
@@ -1402,11 +1806,11 @@ impl Inflater {
 */
         // Copy fixed tables
 
-        for i in range(0, inffixed::LENFIX.len()) {
+        for i in 0..inffixed::LENFIX.len() {
             self.codes[i] = inffixed::LENFIX[i];
         }
 
-        for i in range(0, inffixed::DISTFIX.len()) {
+        for i in 0..inffixed::DISTFIX.len() {
             self.codes[i + inffixed::LENFIX.len()] = inffixed::DISTFIX[i];
         }
 
@@ -1430,71 +1834,79 @@ impl Inflater {
 // output will fall in the output data, making match copies simpler and faster.
 // The advantage may be dependent on the size of the processor's data caches.
 //
-//      end - the index within loc.output_buffer where the source data ENDS
-//      copy - the length of the data that was just written to loc.output_buffer,
+//      src - the data that was just written to the output buffer (or the dictionary),
 //          and so which is now available to copy into the window
 //
 #[inline]
-fn updatewindow(loc: &mut InflateLocals, end: usize, copy: usize) {
-    debug!("updatewindow: copy={}", copy);
+fn updatewindow(state: &mut Inflater, src: &[u8]) {
+    debug!("updatewindow: copy={}", src.len());
 
-    let mut copy = copy;
+    let end = src.len();
+    let mut copy = src.len();
     let mut dist: usize;
 
     /* if it hasn't been done already, allocate space for the window */
 
-    // loc.state.window.clear();
-    // loc.state.window.grow(1 << loc.state.wbits, 0);
+    // state.window.clear();
+    // state.window.grow(1 << state.wbits, 0);
 
     /* if window not in use yet, initialize */
-    if loc.state.wsize == 0 {
-        // debug!("wsize=0, initializing window, wbits={}", loc.state.wbits);
-        loc.state.wsize = 1 << loc.state.wbits;
-        loc.state.wnext = 0;
-        loc.state.whave = 0;
+    if state.wsize == 0 {
+        // debug!("wsize=0, initializing window, wbits={}", state.wbits);
+        state.wsize = 1 << state.wbits;
+        state.wnext = 0;
+        state.whave = 0;
     }
 
     /* copy state.wsize or less output bytes into the circular window */
-    if copy >= loc.state.wsize {
-        // debug!("copy >= wsize, copy = {}, wsize = {}", copy, loc.state.wsize);
+    if copy >= state.wsize {
+        // debug!("copy >= wsize, copy = {}, wsize = {}", copy, state.wsize);
         debug!("filling entire window");
-        copy_memory(loc.state.window.as_mut_slice(), loc.output_buffer.slice(end - loc.state.wsize, end));
-        loc.state.wnext = 0;
-        loc.state.whave = loc.state.wsize;
+        state.window[..state.wsize].copy_from_slice(&src[end - state.wsize..end]);
+        state.wnext = 0;
+        state.whave = state.wsize;
     }
     else {
-        // debug!("copy < wsize, copy = {}, wsize = {}", copy, loc.state.wsize);
+        // debug!("copy < wsize, copy = {}, wsize = {}", copy, state.wsize);
         debug!("partial window fill");
-        dist = loc.state.wsize - loc.state.wnext;
+        dist = state.wsize - state.wnext;
         if dist > copy {
             dist = copy;
         }
-        debug!("copying from output_buffer to window[{}] length: {}", loc.state.wnext, dist);
-        copy_memory(
-            loc.state.window.slice_mut(loc.state.wnext, loc.state.wnext + dist),
-            loc.output_buffer.slice(end - copy, end - copy + dist));
+        debug!("copying from output_buffer to window[{}] length: {}", state.wnext, dist);
+        state.window[state.wnext..state.wnext + dist].copy_from_slice(&src[end - copy..end - copy + dist]);
         copy -= dist;
         if copy != 0 {
             debug!("copying second chunk, to window start, length: {}", copy);
-            copy_memory(loc.state.window.as_mut_slice(), loc.output_buffer.slice(end - copy, end));
-            loc.state.wnext = copy;
-            loc.state.whave = loc.state.wsize;
+            state.window[..copy].copy_from_slice(&src[end - copy..end]);
+            state.wnext = copy;
+            state.whave = state.wsize;
         }
         else {
             debug!("no second chunk, advancing by dist={}", dist);
-            loc.state.wnext += dist;
-            if loc.state.wnext == loc.state.wsize {
+            state.wnext += dist;
+            if state.wnext == state.wsize {
                 debug!("wnext=wsize, so resetting wnext to 0");
-                loc.state.wnext = 0;
+                state.wnext = 0;
             }
-            if loc.state.whave < loc.state.wsize {
+            if state.whave < state.wsize {
                 debug!("whave < wsize, so advancing whave");
-                loc.state.whave += dist;
+                state.whave += dist;
             }
         }
     }
 
-    debug!("whave={} wnext={}", loc.state.whave, loc.state.wnext);
+    debug!("whave={} wnext={}", state.whave, state.wnext);
+}
+
+// Returns true if the first two bytes of a stream, in the low 16 bits of 'hold', are a zlib
+// header that inflate() would accept.
+fn is_zlib_header(hold: u32, wbits: usize) -> bool {
+    let cmf = hold & 0xff;
+    let flg = (hold >> 8) & 0xff;
+    ((cmf << 8) + flg) % 31 == 0
+        && (cmf & 0xf) == Z_DEFLATED as u32
+        && ((cmf >> 4) + 8) as usize <= wbits
 }
 
 /* Macros for inflate(): */
@@ -1698,6 +2110,7 @@ struct InflateLocals<'a>
     bits: usize,         // bits in bit buffer
     next: usize,         // next input; is an index into input_buffer
     put: usize,          // next output; is an index into output_buffer
+    out: usize,          // output already added to the totals and check value
 
     flush: Flush,
 
@@ -1754,200 +2167,36 @@ uInt *dictLength;
     return Z_OK;
 }
 
-int ZEXPORT inflateSetDictionary(strm, dictionary, dictLength)
-z_streamp strm;
-const Bytef *dictionary;
-uInt dictLength;
-{
-    struct Inflater FAR *state;
-    unsigned long dictid;
-    int ret;
-
-    /* check state */
-    if (strm == Z_NULL || strm.state == Z_NULL) return Z_STREAM_ERROR;
-    state = (struct Inflater FAR *)strm.state;
-    if (state.wrap != 0 && state.mode != DICT)
-        return Z_STREAM_ERROR;
-
-    /* check for correct dictionary identifier */
-    if (state.mode == DICT) {
-        dictid = adler32(0L, Z_NULL, 0);
-        dictid = adler32(dictid, dictionary, dictLength);
-        if (dictid != state.check)
-            return Z_DATA_ERROR;
-    }
-
-    /* copy dictionary to window using updatewindow(), which will amend the
-       existing dictionary if appropriate */
-    ret = updatewindow(strm, dictionary + dictLength, dictLength);
-    if (ret) {
-        state.mode = MEM;
-        return Z_MEM_ERROR;
-    }
-    state.havedict = 1;
-    debug!("inflate:   dictionary set");
-    return Z_OK;
-}
-
-int ZEXPORT inflateGetHeader(strm, head)
-z_streamp strm;
-gz_headerp head;
-{
-    struct Inflater FAR *state;
-
-    /* check state */
-    if (strm == Z_NULL || strm.state == Z_NULL) return Z_STREAM_ERROR;
-    state = (struct Inflater FAR *)strm.state;
-    if ((state.wrap & 2) == 0) return Z_STREAM_ERROR;
-
-    /* save header structure */
-    state.head = head;
-    head.done = 0;
-    return Z_OK;
-}
+*/
 
-/*
-   Search buf[0..len-1] for the pattern: 0, 0, 0xff, 0xff.  Return when found
-   or when out of input.  When called, *have is the number of pattern bytes
-   found in order so far, in 0..3.  On return *have is updated to the new
-   state.  If on return *have equals four, then the pattern was found and the
-   return value is how many bytes were read including the last byte of the
-   pattern.  If *have is less than four, then the pattern has not been found
-   yet and the return value is len.  In the latter case, syncsearch() can be
-   called again with more data and the *have state.  *have is initialized to
-   zero for the first call.
- */
-local unsigned syncsearch(have, buf, len)
-unsigned FAR *have;
-const unsigned char FAR *buf;
-unsigned len;
-{
-    unsigned got;
-    unsigned next;
-
-    got = *have;
-    next = 0;
-    while (next < len && got < 4) {
-        if ((int)(buf[next]) == (got < 2 ? 0 : 0xff))
-            got++;
-        else if (buf[next])
+// Search buf for the pattern: 0, 0, 0xff, 0xff.  Return when found or when out of
+// input.  When called, *have is the number of pattern bytes found in order so far,
+// in 0..3.  On return *have is updated to the new state.  If on return *have equals
+// four, then the pattern was found and the return value is how many bytes were read
+// including the last byte of the pattern.  If *have is less than four, then the
+// pattern has not been found yet and the return value is buf.len().  In the latter
+// case, syncsearch() can be called again with more data and the *have state.  *have
+// is initialized to zero for the first call.
+fn syncsearch(have: &mut usize, buf: &[u8]) -> usize {
+    let mut got = *have;
+    let mut next: usize = 0;
+    while next < buf.len() && got < 4 {
+        if buf[next] == (if got < 2 { 0 } else { 0xff }) {
+            got += 1;
+        }
+        else if buf[next] != 0 {
             got = 0;
-        else
+        }
+        else {
             got = 4 - got;
-        next++;
-    }
-    *have = got;
-    return next;
-}
-
-int ZEXPORT inflateSync(strm)
-z_streamp strm;
-{
-    unsigned len;               /* number of bytes to look at or looked at */
-    unsigned long in, out;      /* temporary to save total_in and total_out */
-    unsigned char buf[4];       /* to restore bit buffer to byte string */
-    struct Inflater FAR *state;
-
-    /* check parameters */
-    if (strm == Z_NULL || strm.state == Z_NULL) return Z_STREAM_ERROR;
-    state = (struct Inflater FAR *)strm.state;
-    if (strm.avail_in == 0 && state.bits < 8) return Z_BUF_ERROR;
-
-    /* if first time, start search in bit buffer */
-    if (state.mode != SYNC) {
-        state.mode = SYNC;
-        state.hold <<= state.bits & 7;
-        state.bits -= state.bits & 7;
-        len = 0;
-        while (state.bits >= 8) {
-            buf[len++] = (unsigned char)(state.hold);
-            state.hold >>= 8;
-            state.bits -= 8;
         }
-        state.have = 0;
-        syncsearch(&(state.have), buf, len);
+        next += 1;
     }
-
-    /* search available input */
-    len = syncsearch(&(state.have), strm.next_in, strm.avail_in);
-    strm.avail_in -= len;
-    strm.next_in += len;
-    strm.total_in += len;
-
-    /* return no joy or set up to restart inflate() on a new block */
-    if (state.have != 4) return Z_DATA_ERROR;
-    in = strm.total_in;  out = strm.total_out;
-    inflateReset(strm);
-    strm.total_in = in;  strm.total_out = out;
-    state.mode = TYPE;
-    return Z_OK;
+    *have = got;
+    next
 }
 
 /*
-   Returns true if inflate is currently at the end of a block generated by
-   Z_SYNC_FLUSH or Z_FULL_FLUSH. This function is used by one PPP
-   implementation to provide an additional safety check. PPP uses
-   Z_SYNC_FLUSH but removes the length bytes of the resulting empty stored
-   block. When decompressing, PPP checks that at the end of input packet,
-   inflate is waiting for these length bytes.
- */
-int ZEXPORT inflateSyncPoint(strm)
-z_streamp strm;
-{
-    struct Inflater FAR *state;
-
-    if (strm == Z_NULL || strm.state == Z_NULL) return Z_STREAM_ERROR;
-    state = (struct Inflater FAR *)strm.state;
-    return state.mode == STORED && state.bits == 0;
-}
-
-int ZEXPORT inflateCopy(dest, source)
-z_streamp dest;
-z_streamp source;
-{
-    struct Inflater FAR *state;
-    struct Inflater FAR *copy;
-    unsigned char FAR *window;
-    unsigned wsize;
-
-    /* check input */
-    if (dest == Z_NULL || source == Z_NULL || source.state == Z_NULL ||
-        source.zalloc == (alloc_func)0 || source.zfree == (free_func)0)
-        return Z_STREAM_ERROR;
-    state = (struct Inflater FAR *)source.state;
-
-    /* allocate space */
-    copy = (struct Inflater FAR *)
-           ZALLOC(source, 1, sizeof(struct Inflater));
-    if (copy == Z_NULL) return Z_MEM_ERROR;
-    window = Z_NULL;
-    if (state.window != Z_NULL) {
-        window = (unsigned char FAR *)
-                 ZALLOC(source, 1U << state.wbits, sizeof(unsigned char));
-        if (window == Z_NULL) {
-            ZFREE(source, copy);
-            return Z_MEM_ERROR;
-        }
-    }
-
-    /* copy state */
-    copy_memory((voidpf)dest, (voidpf)source, sizeof(z_stream));
-    copy_memory((voidpf)copy, (voidpf)state, sizeof(struct Inflater));
-    if (state.lencode >= state.codes &&
-        state.lencode <= state.codes + ENOUGH - 1) {
-        copy.lencode = copy.codes + (state.lencode - state.codes);
-        copy.distcode = copy.codes + (state.distcode - state.codes);
-    }
-    copy.next = copy.codes + (state.next - state.codes);
-    if (window != Z_NULL) {
-        wsize = 1U << state.wbits;
-        copy_memory(window, state.window, wsize);
-    }
-    copy.window = window;
-    dest.state = (struct internal_state FAR *)copy;
-    return Z_OK;
-}
-
 int ZEXPORT inflateUndermine(strm, subvert)
 z_streamp strm;
 int subvert;
@@ -1964,17 +2213,5 @@ int subvert;
     return Z_DATA_ERROR;
 #endif
 }
-
-long ZEXPORT inflateMark(strm)
-z_streamp strm;
-{
-    struct Inflater FAR *state;
-
-    if (strm == Z_NULL || strm.state == Z_NULL) return -1L << 16;
-    state = (struct Inflater FAR *)strm.state;
-    return ((long)(state.back) << 16) +
-        (state.mode == COPY ? state.length :
-            (state.mode == MATCH ? state.was - state.length : 0));
-}
 */
 
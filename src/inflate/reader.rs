@@ -1,11 +1,18 @@
 use std::cmp::max;
 use std::io;
-use std::io::{Reader, IoResult};
+use std::io::{Read, BufRead, ErrorKind};
 
-use WINDOW_BITS_DEFAULT;
-use inflate::{Inflater,InflateResult};
+use crate::{io_error, fill_vec};
+use crate::GZipHeader;
+use crate::WINDOW_BITS_DEFAULT;
+use crate::ZERR;
+use crate::inflate::{Inflater,InflateResult,InflateError};
 
-/// Provides an implementation of `Reader` for inflating (decompression) INFLATE / GZIP streams.
+/// Provides an implementation of `Read` for inflating (decompression) INFLATE / GZIP streams.
+///
+/// If `src` ends before the end of the compressed stream, `read()` returns the data decoded
+/// so far, and then fails with `ErrorKind::UnexpectedEof`, so that a truncated file is not
+/// mistaken for a short one.
 pub struct InflateReader<R> {
     src: R,
     state: Inflater,
@@ -15,15 +22,27 @@ pub struct InflateReader<R> {
 
     /// Set true when 'src' reports EOF.
     src_eof: bool,
+
+    /// Number of bytes of 'src' that have been decoded, and the number that preceded the
+    /// current GZIP member.  Used to report errors relative to the start of 'src'.
+    total_in: u64,
+    member_start: u64,
+
+    /// See set_multi_member() and set_ignore_zero_padding().
+    multi_member: bool,
+    ignore_zero_padding: bool,
 }
 
-impl<R:Reader> InflateReader<R> {
+impl<R:Read> InflateReader<R> {
     /// Creates a new InflateReader which uses `src` as its input stream.
+    /// The GZIP header is saved, and is available from `header()`.
     pub fn new_gzip(
         inbufsize: usize,
         src: R) -> InflateReader<R> {
         debug!("InflateReader::new_gzip()");
-        InflateReader::new_with_inflater(inbufsize, Inflater::new_gzip(), src)
+        let mut inflater = Inflater::new_gzip();
+        inflater.capture_header();
+        InflateReader::new_with_inflater(inbufsize, inflater, src)
     }
 
     pub fn new_inflate(
@@ -45,47 +64,125 @@ impl<R:Reader> InflateReader<R> {
             inbuf: Vec::with_capacity(inbufsize),
             next_in: 0,
             src_eof: false,
-            state: inflater
-        }        
+            state: inflater,
+            total_in: 0,
+            member_start: 0,
+            multi_member: false,
+            ignore_zero_padding: false,
+        }
+    }
+
+    /// Enables or disables decoding of multi-member GZIP streams, such as those made by
+    /// concatenating GZIP files, or by pigz or bgzip.  When enabled, the reader continues with
+    /// the next member after each GZIP trailer, until the end of `src`.  When disabled (the
+    /// default), the reader stops at the end of the first member and ignores any data after it.
+    pub fn set_multi_member(&mut self, multi_member: bool) {
+        self.multi_member = multi_member;
+    }
+
+    /// Enables or disables skipping zero bytes after the last GZIP member, as GNU gzip does.
+    /// Archives written to tape are often padded with zeros to the block size.  Once zero
+    /// padding is found, the rest of `src` must be zeros.  Only used in multi-member mode.
+    pub fn set_ignore_zero_padding(&mut self, ignore_zero_padding: bool) {
+        self.ignore_zero_padding = ignore_zero_padding;
+    }
+
+    /// Provides the preset dictionary for the stream.  See `Inflater::set_dictionary()`.
+    /// If `read()` fails because the stream requires a dictionary, call this and then
+    /// call `read()` again.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> ZERR {
+        self.state.set_dictionary(dictionary)
+    }
+
+    /// Returns the GZIP header, once it has been read.  See `Inflater::header()`.
+    pub fn header(&self) -> Option<&GZipHeader> {
+        self.state.header()
     }
 
     pub fn inner(&mut self) -> &mut R {
         &mut self.src // self.src.deref_mut()
     }
 
-    fn fill_buffer(&mut self) -> IoResult<()> {
-        self.inbuf.clear();
+    /// Returns the inner reader, the input that was read from it but not decoded, and the
+    /// length of the compressed data that was decoded.
+    ///
+    /// Once `read()` has returned 0, the unused input is the data that follows the compressed
+    /// stream, up to the current position of `src`, and the length is the exact length of the
+    /// compressed stream (or, in multi-member mode, of all of the members and any padding).
+    /// This allows formats that put more data after a zlib or DEFLATE stream, such as Git
+    /// packfiles and PNG, to continue parsing at the byte after the stream.
+    pub fn into_inner_with_remainder(self) -> (R, Vec<u8>, u64) {
+        let mut remainder = self.state.unused_input();
+        let total_in = self.total_in - remainder.len() as u64;
+        remainder.extend_from_slice(&self.inbuf[self.next_in..]);
+        (self.src, remainder, total_in)
+    }
+
+    // Called when the Inflater reaches the end of a GZIP member, in multi-member mode.  Skips
+    // zero padding (if enabled), and if more input follows, resets the Inflater to decode the
+    // next member.  Returns false at the end of the input.
+    fn start_next_member(&mut self) -> io::Result<bool> {
+        let mut in_padding = false;
+        loop {
+            if self.next_in == self.inbuf.len() {
+                if self.src_eof {
+                    return Ok(false);
+                }
+                self.fill_buffer()?;
+                continue;
+            }
+            if !self.ignore_zero_padding {
+                break;
+            }
+            if !in_padding && self.inbuf[self.next_in] != 0 {
+                break;
+            }
+            in_padding = true;
+            while self.next_in < self.inbuf.len() && self.inbuf[self.next_in] == 0 {
+                self.next_in += 1;
+                self.total_in += 1;
+            }
+            if self.next_in < self.inbuf.len() {
+                warn!("found non-zero data after zero padding, at input offset {}", self.total_in);
+                return Err(io_error(ErrorKind::InvalidData, "trailing data after zero padding",
+                    Some(format!("at input offset {}", self.total_in))));
+            }
+        }
+
+        debug!("starting next GZIP member, at input offset {}", self.total_in);
+        let capture_header = self.state.head.is_some();
+        self.state.reset();
+        if capture_header {
+            self.state.capture_header();
+        }
+        self.member_start = self.total_in;
+        Ok(true)
+    }
+
+    fn fill_buffer(&mut self) -> io::Result<()> {
         self.next_in = 0;
-        let result = self.src.push(self.inbuf.capacity(), &mut self.inbuf);
-        match result {
+        match fill_vec(&mut self.src, &mut self.inbuf) {
             Ok(count) => {
+                if count == 0 {
+                    self.src_eof = true;
+                }
                 Ok(())
             }
-            // Err(err) if err.kind == io::EndOfFile {
-            //     self.src_eof = true;
-            // }
             Err(err) => {
-                if err.kind == io::EndOfFile {
-                    self.src_eof = true;
-                    Ok(())
-                }
-                else {
-                    self.src_eof = true;
-                    Err(err)
-                }
+                self.src_eof = true;
+                Err(err)
             }
         }
     }
 }
 
-impl<R:Reader> Reader for InflateReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+impl<R:Read> Read for InflateReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let mut outpos: usize = 0;
 
-        if buf.len() == 0 {
-            // not really
-            debug!("input buffer is zero-length!");
-            return Err(io::standard_error(io::EndOfFile));
+        if buf.is_empty() {
+            debug!("output buffer is zero-length!");
+            return Ok(0);
         }
 
         while outpos < buf.len() {
@@ -100,43 +197,241 @@ impl<R:Reader> Reader for InflateReader<R> {
                 }
             }
 
-            let inbuf = self.inbuf.slice_from(self.next_in);
-            let buflen = buf.len();
-            debug!("InflateReader: calling inflate, in_len={} out_len={}", inbuf.len(), buflen - outpos);
-            match self.state.inflate(None, inbuf, buf.slice_from_mut(outpos)) {
-                InflateResult::Decoded(in_bytes, out_bytes) => {
+            let result = {
+                let inbuf = &self.inbuf[self.next_in..];
+                let buflen = buf.len();
+                debug!("InflateReader: calling inflate, in_len={} out_len={}", inbuf.len(), buflen - outpos);
+                self.state.inflate(None, inbuf, &mut buf[outpos..])
+            };
+            match result {
+                InflateResult::Decoded(in_bytes, out_bytes) | InflateResult::Boundary(in_bytes, out_bytes) => {
                     debug!("decoded: in_bytes={} out_bytes={}", in_bytes, out_bytes);
                     self.next_in += in_bytes;
+                    self.total_in += in_bytes as u64;
                     outpos += out_bytes;
                 }
                 InflateResult::Eof(_) => {
-                    if outpos == 0 {
-                        debug!("inflater says EOF, no data transferred, returning EOF error");
-                        return Err(io::standard_error(io::EndOfFile));
-                    }
-                    else {
-                        debug!("inflater says EOF, some data transferred, returning that count");
-                        return Ok(outpos)
+                    // Return the data from this member before looking for the next one.
+                    if self.multi_member && outpos == 0 && self.start_next_member()? {
+                        continue;
                     }
+                    debug!("inflater says EOF, returning {}", outpos);
+                    return Ok(outpos);
                 }
-                InflateResult::InvalidData => {
-                    warn!("InflateResult::InvalidData");
-                    return Err(io::standard_error(io::InvalidInput))
+                InflateResult::Error(err, offset) => {
+                    return Err(inflate_error(err, self.member_start + offset));
                 }
                 InflateResult::NeedInput => {
-                    warn!("InflateResult::NeedInput");
+                    if !self.src_eof {
+                        continue;
+                    }
+                    // Return the data decoded so far; the next call reports the error.
+                    if outpos != 0 {
+                        break;
+                    }
+                    return Err(unexpected_end_error(self.total_in));
+                }
+                InflateResult::NeedDictionary(dictid) => {
+                    return Err(need_dictionary_error(dictid));
+                }
+            }
+        }
+
+        debug!("end of loop, returning {}", outpos);
+        Ok(outpos)
+    }
+}
+
+/// Provides an implementation of `Read` for inflating INFLATE / GZIP streams from a `BufRead`.
+///
+/// Unlike `InflateReader`, this has no input buffer of its own.  It decodes directly from
+/// the slices returned by `src.fill_buf()`, and calls `src.consume()` for exactly the bytes
+/// that the Inflater used.  When the stream ends, `src` is positioned just after it, so any
+/// data that follows the compressed stream can be read from `src`.  As with `InflateReader`,
+/// reaching the end of `src` before the end of the stream is an error.
+pub struct BufInflateReader<R> {
+    src: R,
+    state: Inflater,
+
+    /// Number of bytes of 'src' that have been decoded, and the number that preceded the
+    /// current GZIP member.  Used to report errors relative to the start of 'src'.
+    total_in: u64,
+    member_start: u64,
+
+    /// See set_multi_member() and set_ignore_zero_padding().
+    multi_member: bool,
+    ignore_zero_padding: bool,
+}
+
+impl<R:BufRead> BufInflateReader<R> {
+    /// Creates a new BufInflateReader which uses `src` as its input stream.
+    /// The GZIP header is saved, and is available from `header()`.
+    pub fn new_gzip(src: R) -> BufInflateReader<R> {
+        debug!("BufInflateReader::new_gzip()");
+        let mut inflater = Inflater::new_gzip();
+        inflater.capture_header();
+        BufInflateReader::new_with_inflater(inflater, src)
+    }
+
+    pub fn new_inflate(src: R) -> BufInflateReader<R> {
+        debug!("BufInflateReader::new_inflate()");
+        BufInflateReader::new_with_inflater(Inflater::new_inflate(WINDOW_BITS_DEFAULT), src)
+    }
+
+    pub fn new_with_inflater(inflater: Inflater, src: R) -> BufInflateReader<R> {
+        BufInflateReader {
+            src: src,
+            state: inflater,
+            total_in: 0,
+            member_start: 0,
+            multi_member: false,
+            ignore_zero_padding: false,
+        }
+    }
+
+    /// See `InflateReader::set_multi_member()`.
+    pub fn set_multi_member(&mut self, multi_member: bool) {
+        self.multi_member = multi_member;
+    }
+
+    /// See `InflateReader::set_ignore_zero_padding()`.
+    pub fn set_ignore_zero_padding(&mut self, ignore_zero_padding: bool) {
+        self.ignore_zero_padding = ignore_zero_padding;
+    }
+
+    /// Provides the preset dictionary for the stream.  See `Inflater::set_dictionary()`.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> ZERR {
+        self.state.set_dictionary(dictionary)
+    }
+
+    /// Returns the GZIP header, once it has been read.  See `Inflater::header()`.
+    pub fn header(&self) -> Option<&GZipHeader> {
+        self.state.header()
+    }
+
+    /// Returns the number of bytes of compressed data that have been consumed from `src`.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    pub fn inner(&mut self) -> &mut R {
+        &mut self.src
+    }
+
+    /// Returns the inner reader.  Once `read()` has returned 0, it is positioned just after
+    /// the compressed stream.
+    pub fn into_inner(self) -> R {
+        self.src
+    }
+
+    // Called when the Inflater reaches the end of a GZIP member, in multi-member mode.  Skips
+    // zero padding (if enabled), and if more input follows, resets the Inflater to decode the
+    // next member.  Returns false at the end of the input.
+    fn start_next_member(&mut self) -> io::Result<bool> {
+        let mut in_padding = false;
+        loop {
+            let (zeros, more) = {
+                let inbuf = self.src.fill_buf()?;
+                if inbuf.is_empty() {
+                    return Ok(false);
+                }
+                if !self.ignore_zero_padding || (!in_padding && inbuf[0] != 0) {
                     break;
                 }
+                let zeros = inbuf.iter().take_while(|&&b| b == 0).count();
+                (zeros, zeros < inbuf.len())
+            };
+            in_padding = true;
+            self.src.consume(zeros);
+            self.total_in += zeros as u64;
+            if more {
+                warn!("found non-zero data after zero padding, at input offset {}", self.total_in);
+                return Err(io_error(ErrorKind::InvalidData, "trailing data after zero padding",
+                    Some(format!("at input offset {}", self.total_in))));
             }
         }
 
-        if outpos == 0 {
-            debug!("end of loop, no data transferred, returning EOF");
-            Err(io::standard_error(io::EndOfFile))
+        debug!("starting next GZIP member, at input offset {}", self.total_in);
+        let capture_header = self.state.head.is_some();
+        self.state.reset();
+        if capture_header {
+            self.state.capture_header();
+        }
+        self.member_start = self.total_in;
+        Ok(true)
+    }
+}
+
+impl<R:BufRead> Read for BufInflateReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut outpos: usize = 0;
+
+        if buf.is_empty() {
+            return Ok(0);
         }
-        else {
-            debug!("end of loop, some data transfered, returning {}", outpos);
-            Ok(outpos)
+
+        while outpos < buf.len() {
+            let result = {
+                let inbuf = self.src.fill_buf()?;
+                debug!("BufInflateReader: calling inflate, in_len={} out_len={}", inbuf.len(), buf.len() - outpos);
+                self.state.inflate(None, inbuf, &mut buf[outpos..])
+            };
+            match result {
+                InflateResult::Decoded(in_bytes, out_bytes) | InflateResult::Boundary(in_bytes, out_bytes) => {
+                    self.src.consume(in_bytes);
+                    self.total_in += in_bytes as u64;
+                    outpos += out_bytes;
+                }
+                InflateResult::Eof(_) => {
+                    // Return the data from this member before looking for the next one.
+                    if self.multi_member && outpos == 0 && self.start_next_member()? {
+                        continue;
+                    }
+                    return Ok(outpos);
+                }
+                InflateResult::Error(err, offset) => {
+                    return Err(inflate_error(err, self.member_start + offset));
+                }
+                InflateResult::NeedInput => {
+                    // The Inflater uses all of the input that it is given, so 'src' is at EOF.
+                    // Return the data decoded so far; the next call reports the error.
+                    if outpos != 0 {
+                        break;
+                    }
+                    return Err(unexpected_end_error(self.total_in));
+                }
+                InflateResult::NeedDictionary(dictid) => {
+                    return Err(need_dictionary_error(dictid));
+                }
+            }
         }
+
+        Ok(outpos)
     }
 }
+
+// Builds the error for InflateResult::Error.  'offset' is relative to the start of the input.
+// Also used by InflateWriter.
+pub fn inflate_error(err: InflateError, offset: u64) -> io::Error {
+    warn!("InflateResult::Error({:?}, {})", err, offset);
+    let detail = match err {
+        InflateError::IncorrectDataCheck(expected, actual) =>
+            format!("at input offset {}: expected 0x{:08x}, computed 0x{:08x}", offset, expected, actual),
+        InflateError::IncorrectLengthCheck(expected, actual) =>
+            format!("at input offset {}: expected {} bytes, decoded {} bytes", offset, expected, actual),
+        _ => format!("at input offset {}", offset)
+    };
+    io_error(ErrorKind::InvalidData, err.description(), Some(detail))
+}
+
+// Builds the error for reaching the end of the input before the end of the compressed stream.
+// Also used by InflateWriter.
+pub fn unexpected_end_error(offset: u64) -> io::Error {
+    warn!("unexpected end of compressed stream, at input offset {}", offset);
+    io_error(ErrorKind::UnexpectedEof, "unexpected end of compressed stream", Some(format!("at input offset {}", offset)))
+}
+
+pub fn need_dictionary_error(dictid: u32) -> io::Error {
+    warn!("InflateResult::NeedDictionary(0x{:08x})", dictid);
+    io_error(ErrorKind::InvalidInput, "stream requires a preset dictionary; call set_dictionary()", None)
+}
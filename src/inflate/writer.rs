@@ -0,0 +1,157 @@
+use std::cmp::max;
+use std::io;
+use std::io::{Write, ErrorKind};
+
+use crate::io_error;
+use crate::GZipHeader;
+use crate::WINDOW_BITS_DEFAULT;
+use crate::ZERR;
+use crate::inflate::{Inflater,InflateResult};
+use super::reader::{inflate_error, unexpected_end_error, need_dictionary_error};
+
+/// Provides an implementation of `Write` for inflating (decompressing) DEFLATE / zlib / GZIP
+/// streams.  Compressed data written to the `InflateWriter` is decoded, and the decoded data
+/// is written to `dst`.  This is useful when compressed data arrives in pieces, such as from
+/// a socket handler, rather than from a `Read`.
+///
+/// Call `finish()` when all of the compressed data has been written.  It fails if the
+/// stream is incomplete.  Once the end of the stream has been decoded, `write()` accepts no
+/// more data; it returns the number of bytes that were part of the stream, or an error if
+/// there were none.
+pub struct InflateWriter<W> {
+    dst: W,
+    state: Inflater,
+
+    outbuf: Vec<u8>,
+
+    /// Number of bytes of compressed data that have been decoded.
+    total_in: u64,
+}
+
+impl<W:Write> InflateWriter<W> {
+    /// Creates a new InflateWriter which decodes a GZIP stream and writes the data to `dst`.
+    /// The GZIP header is saved, and is available from `header()`.
+    pub fn new_gzip(
+        outbufsize: usize,
+        dst: W) -> InflateWriter<W> {
+        debug!("InflateWriter::new_gzip()");
+        let mut inflater = Inflater::new_gzip();
+        inflater.capture_header();
+        InflateWriter::new_with_inflater(outbufsize, inflater, dst)
+    }
+
+    /// Creates a new InflateWriter which decodes a zlib stream and writes the data to `dst`.
+    pub fn new_zlib(
+        outbufsize: usize,
+        dst: W) -> InflateWriter<W> {
+        debug!("InflateWriter::new_zlib()");
+        InflateWriter::new_with_inflater(outbufsize, Inflater::new_zlib(), dst)
+    }
+
+    /// Creates a new InflateWriter which decodes a raw DEFLATE stream and writes the data
+    /// to `dst`.
+    pub fn new_raw(
+        outbufsize: usize,
+        dst: W) -> InflateWriter<W> {
+        debug!("InflateWriter::new_raw()");
+        InflateWriter::new_with_inflater(outbufsize,
+            Inflater::new_raw(WINDOW_BITS_DEFAULT),
+            dst)
+    }
+
+    pub fn new_with_inflater(
+        outbufsize: usize,
+        inflater: Inflater,
+        dst: W) -> InflateWriter<W> {
+        let outbufsize = max(outbufsize, 0x1000);
+        InflateWriter {
+            dst: dst,
+            state: inflater,
+            outbuf: vec![0u8; outbufsize],
+            total_in: 0,
+        }
+    }
+
+    /// Provides the preset dictionary for the stream.  See `Inflater::set_dictionary()`.
+    /// If `write()` fails because the stream requires a dictionary, call this and then
+    /// write the data again, starting with the buffer that was passed to the failed `write()`.
+    /// (A `write()` that uses the zlib header returns the number of bytes it used, and the
+    /// next `write()` fails, so the data after the header is never lost.)
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> ZERR {
+        self.state.set_dictionary(dictionary)
+    }
+
+    /// Returns the GZIP header, once it has been decoded.  See `Inflater::header()`.
+    pub fn header(&self) -> Option<&GZipHeader> {
+        self.state.header()
+    }
+
+    /// Returns the number of bytes of compressed data that have been decoded.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    pub fn inner(&mut self) -> &mut W {
+        &mut self.dst
+    }
+
+    /// Writes any remaining output to the inner writer, and returns the inner writer.
+    /// Fails if the end of the compressed stream has not been written.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.decode(&[])? {
+            return Err(unexpected_end_error(self.total_in));
+        }
+        self.dst.flush()?;
+        Ok(self.dst)
+    }
+
+    // Decodes as much of 'input' as the Inflater will take, writing the output to 'dst'.
+    // Returns true if the end of the stream has been reached.  'total_in' tells how much of
+    // 'input' was used, which is less than all of it at the end of the stream, or if the
+    // stream requires a dictionary after some of 'input' was used.
+    fn decode(&mut self, input: &[u8]) -> io::Result<bool> {
+        let start = self.total_in;
+        loop {
+            let inpos = (self.total_in - start) as usize;
+            match self.state.inflate(None, &input[inpos..], self.outbuf.as_mut_slice()) {
+                InflateResult::Decoded(in_bytes, out_bytes) | InflateResult::Boundary(in_bytes, out_bytes) => {
+                    debug!("decoded: in_bytes={} out_bytes={}", in_bytes, out_bytes);
+                    self.total_in += in_bytes as u64;
+                    if out_bytes != 0 {
+                        self.dst.write_all(&self.outbuf[..out_bytes])?;
+                    }
+                }
+                InflateResult::Eof(_) => return Ok(true),
+                InflateResult::NeedInput => return Ok(false),
+                InflateResult::Error(err, offset) => return Err(inflate_error(err, offset)),
+                InflateResult::NeedDictionary(dictid) => {
+                    // Report this in the next call, so that the caller can tell where to resume.
+                    if self.total_in != start {
+                        return Ok(false);
+                    }
+                    return Err(need_dictionary_error(dictid));
+                }
+            }
+        }
+    }
+}
+
+impl<W:Write> Write for InflateWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = self.total_in;
+        let done = self.decode(buf)?;
+        let used = (self.total_in - start) as usize;
+        if done && used == 0 && !buf.is_empty() {
+            warn!("write: {} bytes of data after the end of the compressed stream", buf.len());
+            return Err(io_error(ErrorKind::InvalidInput, "data after the end of the compressed stream",
+                Some(format!("at input offset {}", self.total_in))));
+        }
+        Ok(used)
+    }
+
+    /// Flushes the inner writer.  All of the data that can be decoded from the compressed
+    /// data written so far has already been written to it.
+    fn flush(&mut self) -> io::Result<()> {
+        self.dst.flush()
+    }
+}
@@ -1,27 +1,46 @@
-#![feature(plugin)]
 #![allow(dead_code)]
 #![allow(unused_variables)]
-#![allow(unstable)]
 
-#[plugin]
+// Lints for idioms that the port keeps from the C zlib, such as names like CODES and LENS,
+// explicit index loops, and C-style arithmetic and casts.
+#![allow(clippy::upper_case_acronyms)]
+#![allow(clippy::needless_range_loop)]
+#![allow(clippy::needless_return)]
+#![allow(clippy::unnecessary_cast)]
+#![allow(clippy::manual_range_contains)]
+#![allow(clippy::manual_div_ceil)]
+#![allow(clippy::precedence)]
+#![allow(clippy::collapsible_if)]
+#![allow(clippy::single_match)]
+#![allow(clippy::len_zero)]
+#![allow(clippy::manual_is_multiple_of)]
+#![allow(clippy::redundant_field_names)]
+
 #[macro_use]
 extern crate log;
 
-extern crate crc32;
+use std::io;
+use std::io::{Read, ErrorKind};
+
+pub use adler32::{adler32, adler32_combine};
+pub use crc::crc32_combine;
 
-pub macro_rules! Tracevv {
+macro_rules! Tracevv {
     ($($arg:tt)*) => {
-        if cfg!(not(ndebug)) {
+        if cfg!(debug_assertions) {
             // println!($($arg)*)
         }
     }
 }
 
 mod adler32;
+mod crc;
 pub mod inflate;
 mod statictrees;
 mod treedefs;
-mod deflate;
+pub mod deflate;
+pub mod zip;
+pub mod bgzf;
 
 // From deflate.h
 
@@ -36,12 +55,13 @@ pub const WINDOW_BITS_MIN: usize = 8;
 pub const WINDOW_BITS_MAX: usize = 15;
 pub const WINDOW_BITS_DEFAULT: usize = WINDOW_BITS_MAX;
 
-#[derive(Copy,Show,Eq,PartialEq)]
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
 pub enum WrapKind {
     Zlib,
     Gzip
 }
 
+#[derive(Clone)]
 struct ZStream {
     pub total_in: u64,              // total number of input bytes read so far
     pub total_out: u64,             // total number of bytes output so far
@@ -64,23 +84,26 @@ impl ZStream {
 
 /// gzip header information passed to and from zlib routines.  See RFC 1952
 /// for more details on the meanings of these fields.
+///
+/// The `name` and `comment` fields hold the bytes stored in the header, without the
+/// terminating zero.  RFC 1952 specifies ISO 8859-1 for both, but many encoders simply
+/// store whatever bytes the file system used, so they are not converted to strings.
+#[derive(Clone,Debug,PartialEq,Eq)]
 pub struct GZipHeader {
     pub text: bool,                     // true if compressed data believed to be text
     pub time: u32,                      // modification time
     pub xflags: u32,                    // extra flags (not used when writing a gzip file)
     pub os: u32,                        // operating system
     pub extra_len: usize,                // length of the 'extra' data, in bytes
-    pub extra: Option<Box<Vec<u8>>>,    // extra field data, if any
-//    pub name_len: usize,                 // length of the 'name' data, in bytes (not chars!)
-    pub name: Option<Box<String>>,      // filename, if any
-//    pub comm_len: usize,                 // length of the 'comment' data, in bytes (not chars!)
-    pub comment: Option<Box<String>>,   // comment string, if any
-    pub hcrc: bool,                     // true if there was or will be a header crc
+    pub extra: Option<Vec<u8>>,         // extra field data, if any
+    pub name: Option<Vec<u8>>,          // filename, if any
+    pub comment: Option<Vec<u8>>,       // comment, if any
+    pub hcrc: bool,                     // true if there was or will be a header crc; when reading, it has been verified
     pub done: bool,                     // true when done reading gzip header (not used when writing a gzip file)
 }
 
 impl GZipHeader {
-    fn new() -> GZipHeader {
+    pub fn new() -> GZipHeader {
         GZipHeader {
             text: false,
             time: 0,
@@ -96,10 +119,16 @@ impl GZipHeader {
     }
 }
 
+impl Default for GZipHeader {
+    fn default() -> GZipHeader {
+        GZipHeader::new()
+    }
+}
+
 /* constants */
 
 /* Allowed flush values; see deflate() and inflate() below for details */
-#[derive(Copy,Show,PartialEq,Eq)]
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
 pub enum Flush {
     None = 0,
     PartialFlush = 1,
@@ -120,7 +149,7 @@ pub const Z_BLOCK        : u32 = 5;
 pub const Z_TREES        : u32 = 6;
 */
 
-#[derive(Copy,PartialEq,Eq)]
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
 pub enum ZERR {
     Ok              = 0,        // Z_OK            = 0,
     StreamEnd       = 1,        // Z_STREAM_END    = 1,
@@ -170,3 +199,47 @@ fn swap32(n: u32) -> u32 {
     | ((n << 8) & 0xff0000)
     | (n << 24)
 }
+
+// Builds an io::Error from a short description of the error and an optional detail, such as
+// the offset where it was found.  The message is "desc: detail".
+fn io_error(kind: ErrorKind, desc: &str, detail: Option<String>) -> io::Error {
+    match detail {
+        Some(detail) => io::Error::new(kind, format!("{}: {}", desc, detail)),
+        None => io::Error::new(kind, desc),
+    }
+}
+
+// Replaces the contents of 'buf' with up to buf.capacity() bytes read from 'src'.  Returns
+// the number of bytes read, which is 0 only at the end of 'src'.
+fn fill_vec<R: Read + ?Sized>(src: &mut R, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let capacity = buf.capacity();
+    buf.clear();
+    buf.resize(capacity, 0);
+    loop {
+        match src.read(buf) {
+            Ok(n) => {
+                buf.truncate(n);
+                return Ok(n);
+            }
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => {
+                buf.clear();
+                return Err(err);
+            }
+        }
+    }
+}
+
+// Reads little-endian integers from 'src', as the old Reader::read_le_u32() and read_le_u64()
+// did.
+fn read_le_u32<R: Read + ?Sized>(src: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    src.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_le_u64<R: Read + ?Sized>(src: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    src.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
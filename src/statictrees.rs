@@ -103,33 +103,33 @@ pub static DIST_CODE :[u8; 512] = [
   15,  15,  15,  15,  15,  15,  15,  15,  15,  15,
   15,  15,  15,  15,  15,  15,  15,  15,  15,  15,
   15,  15,  15,  15,  15,  15,  15,  15,  15,  15,
-  15,  15,  15,  15,  15,  15,  29,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0,   0,   0,   0,   0,   0,   0,   0,   0,
-   0,   0
+  15,  15,  15,  15,  15,  15,   0,   0,  16,  17,
+  18,  18,  19,  19,  20,  20,  20,  20,  21,  21,
+  21,  21,  22,  22,  22,  22,  22,  22,  22,  22,
+  23,  23,  23,  23,  23,  23,  23,  23,  24,  24,
+  24,  24,  24,  24,  24,  24,  24,  24,  24,  24,
+  24,  24,  24,  24,  25,  25,  25,  25,  25,  25,
+  25,  25,  25,  25,  25,  25,  25,  25,  25,  25,
+  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,
+  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,
+  26,  26,  26,  26,  26,  26,  26,  26,  26,  26,
+  26,  26,  27,  27,  27,  27,  27,  27,  27,  27,
+  27,  27,  27,  27,  27,  27,  27,  27,  27,  27,
+  27,  27,  27,  27,  27,  27,  27,  27,  27,  27,
+  27,  27,  27,  27,  28,  28,  28,  28,  28,  28,
+  28,  28,  28,  28,  28,  28,  28,  28,  28,  28,
+  28,  28,  28,  28,  28,  28,  28,  28,  28,  28,
+  28,  28,  28,  28,  28,  28,  28,  28,  28,  28,
+  28,  28,  28,  28,  28,  28,  28,  28,  28,  28,
+  28,  28,  28,  28,  28,  28,  28,  28,  28,  28,
+  28,  28,  28,  28,  28,  28,  28,  28,  29,  29,
+  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,
+  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,
+  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,
+  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,
+  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,
+  29,  29,  29,  29,  29,  29,  29,  29,  29,  29,
+  29,  29
 ];
 
 pub static LENGTH_CODE :[u8; 256] = [
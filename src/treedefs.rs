@@ -55,6 +55,7 @@ pub static BL_ORDER: [u8; BL_CODES]
  */
 
 /* Data structure describing a single value and its code string. */
+#[derive(Copy,Clone)]
 pub struct CtData // was ct_data
 {
     // union {
@@ -83,7 +83,7 @@ pub fn tr_static_init() -> StaticTrees
     for code in range(16, D_CODES) {
         base_dist[code] = dist << 7;
         for _ in range(0, 1u32 << (EXTRA_DBITS[code] as uint - 7)) {
-            dist_code[256] = code as u8;
+            dist_code[256 + dist as uint] = code as u8;
             dist += 1;
         }
     }
@@ -0,0 +1,164 @@
+// Support for ZIP archives.  See the PKWARE "APPNOTE.TXT" for the format.
+
+use std::io;
+use std::io::{Read, ErrorKind};
+
+use crate::io_error;
+
+pub use self::reader::{ZipArchive, ZipEntryReader};
+pub use self::writer::ZipWriter;
+
+mod reader;
+mod writer;
+
+/// Compression method for entries that are stored without compression.
+pub const STORED: u16 = 0;
+
+/// Compression method for entries that are compressed with DEFLATE.
+pub const DEFLATED: u16 = 8;
+
+// Record signatures
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIR_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIR_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+
+// Fixed sizes of records, not counting the variable-length fields
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_OF_CENTRAL_DIR_SIZE: usize = 22;
+const ZIP64_END_OF_CENTRAL_DIR_SIZE: usize = 56;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+
+// Tag of the extra field that holds the 64-bit sizes and offsets
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+// Values above this must be stored in ZIP64 fields
+const ZIP64_LIMIT: u64 = 0xffffffff;
+
+// Version needed to extract: 2.0 for DEFLATE and data descriptors, 4.5 for ZIP64
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+// General purpose flags
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+
+/// A date and time, at the two-second resolution that ZIP archives store.  There is no time
+/// zone; most tools store local time.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct DosDateTime {
+    pub year: u16,                  // 1980 to 2107
+    pub month: u8,                  // 1 to 12
+    pub day: u8,                    // 1 to 31
+    pub hour: u8,                   // 0 to 23
+    pub minute: u8,                 // 0 to 59
+    pub second: u8,                 // 0 to 58, always even
+}
+
+impl DosDateTime {
+    /// Decodes the date and time fields of a ZIP header.
+    pub fn from_dos(date: u16, time: u16) -> DosDateTime {
+        DosDateTime {
+            year: 1980 + (date >> 9),
+            month: ((date >> 5) & 0xf) as u8,
+            day: (date & 0x1f) as u8,
+            hour: (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3f) as u8,
+            second: ((time & 0x1f) * 2) as u8,
+        }
+    }
+
+    /// Encodes the date and time for a ZIP header, as (date, time).  The format holds the
+    /// years 1980 to 2107; earlier times are written as the start of 1980, and later times as
+    /// the end of 2107.
+    pub fn to_dos(&self) -> (u16, u16) {
+        if self.year < 1980 {
+            return ((1 << 5) | 1, 0);
+        }
+        if self.year > 2107 {
+            return ((127 << 9) | (12 << 5) | 31, (23 << 11) | (59 << 5) | 29);
+        }
+        let date = ((self.year - 1980) << 9) | ((self.month as u16) << 5) | (self.day as u16);
+        let time = ((self.hour as u16) << 11) | ((self.minute as u16) << 5) | (self.second as u16 / 2);
+        (date, time)
+    }
+}
+
+/// Describes an entry in a ZIP archive, from the central directory.
+///
+/// The `name` and `comment` fields hold the bytes stored in the archive.  If `is_utf8()` is
+/// true they are UTF-8; otherwise they are usually ASCII, or the code page of the system that
+/// wrote the archive.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ZipEntry {
+    pub name: Vec<u8>,                  // path of the entry, with '/' separators
+    pub comment: Vec<u8>,               // entry comment, if any
+    pub method: u16,                    // compression method; STORED or DEFLATED
+    pub flags: u16,                     // general purpose bit flags
+    pub modified: DosDateTime,          // last modification time
+    pub crc32: u32,                     // CRC-32 of the uncompressed data
+    pub compressed_size: u64,           // size of the entry's data in the archive
+    pub uncompressed_size: u64,         // size of the entry's data after decompression
+    pub external_attributes: u32,       // host file attributes (for Unix, the mode is in the high 16 bits)
+    pub header_offset: u64,             // offset of the local header in the archive
+}
+
+impl ZipEntry {
+    /// Returns true if the entry is a directory.  Directory names end with '/'.
+    pub fn is_dir(&self) -> bool {
+        self.name.last() == Some(&b'/')
+    }
+
+    /// Returns true if the name and comment are UTF-8.
+    pub fn is_utf8(&self) -> bool {
+        (self.flags & FLAG_UTF8) != 0
+    }
+
+    /// Returns true if the entry's data is encrypted.  Encrypted entries cannot be read.
+    pub fn is_encrypted(&self) -> bool {
+        (self.flags & FLAG_ENCRYPTED) != 0
+    }
+}
+
+fn invalid_archive(desc: &str, detail: Option<String>) -> io::Error {
+    warn!("{} {:?}", desc, detail);
+    io_error(ErrorKind::InvalidData, desc, detail)
+}
+
+// Reads exactly 'len' bytes from 'src'.
+fn read_vec<R: Read + ?Sized>(src: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    src.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn get_u16(buf: &[u8], pos: usize) -> u16 {
+    (buf[pos] as u16) | ((buf[pos + 1] as u16) << 8)
+}
+
+fn get_u32(buf: &[u8], pos: usize) -> u32 {
+    (get_u16(buf, pos) as u32) | ((get_u16(buf, pos + 2) as u32) << 16)
+}
+
+fn get_u64(buf: &[u8], pos: usize) -> u64 {
+    (get_u32(buf, pos) as u64) | ((get_u32(buf, pos + 4) as u64) << 32)
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.push(value as u8);
+    buf.push((value >> 8) as u8);
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    put_u16(buf, value as u16);
+    put_u16(buf, (value >> 16) as u16);
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    put_u32(buf, value as u32);
+    put_u32(buf, (value >> 32) as u32);
+}
@@ -0,0 +1,334 @@
+use std::cmp::min;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, ErrorKind};
+
+use crate::io_error;
+use crate::crc::crc32;
+use crate::WINDOW_BITS_MAX;
+use crate::inflate::{Inflater, InflateReader};
+use super::{ZipEntry, DosDateTime, STORED, DEFLATED};
+use super::{LOCAL_HEADER_SIGNATURE, CENTRAL_HEADER_SIGNATURE, END_OF_CENTRAL_DIR_SIGNATURE};
+use super::{ZIP64_END_OF_CENTRAL_DIR_SIGNATURE, ZIP64_LOCATOR_SIGNATURE};
+use super::{LOCAL_HEADER_SIZE, CENTRAL_HEADER_SIZE, END_OF_CENTRAL_DIR_SIZE};
+use super::{ZIP64_END_OF_CENTRAL_DIR_SIZE, ZIP64_LOCATOR_SIZE, ZIP64_EXTRA_ID};
+use super::{invalid_archive, read_vec, get_u16, get_u32, get_u64};
+
+/// Reads a ZIP archive from `src`.
+///
+/// `new()` reads the central directory, which lists the entries.  Use `entries()` to get the
+/// list, and `read_entry()` to read the data of an entry.  ZIP64 archives are supported, but
+/// archives split across several files ("disks") are not.
+pub struct ZipArchive<R> {
+    src: R,
+    entries: Vec<ZipEntry>,
+    comment: Vec<u8>,
+}
+
+// The fields of the end of central directory record that we need, after applying the
+// ZIP64 end of central directory record, if there is one.
+struct CentralDirectory {
+    entries: u64,
+    size: u64,
+    offset: u64,
+    comment: Vec<u8>,
+}
+
+impl<R:Read+Seek> ZipArchive<R> {
+    /// Reads the central directory of the archive in `src`.
+    pub fn new(mut src: R) -> io::Result<ZipArchive<R>> {
+        let cd = read_end_of_central_dir(&mut src)?;
+        debug!("central directory: entries={} size={} offset={}", cd.entries, cd.size, cd.offset);
+
+        src.seek(SeekFrom::Start(cd.offset))?;
+        let buf = read_vec(&mut src, cd.size as usize)?;
+        let mut entries: Vec<ZipEntry> = Vec::new();
+        let mut pos: usize = 0;
+        for _ in 0..cd.entries {
+            let (entry, len) = parse_central_header(&buf[pos..])?;
+            debug!("entry: {:?}", entry);
+            entries.push(entry);
+            pos += len;
+        }
+
+        Ok(ZipArchive {
+            src: src,
+            entries: entries,
+            comment: cd.comment,
+        })
+    }
+
+    /// Returns the entries of the archive, in the order of the central directory.
+    pub fn entries(&self) -> &[ZipEntry] {
+        self.entries.as_slice()
+    }
+
+    /// Returns the index of the entry with the given name, if there is one.
+    pub fn find(&self, name: &[u8]) -> Option<usize> {
+        self.entries.iter().position(|e| e.name.as_slice() == name)
+    }
+
+    /// Returns the archive comment.
+    pub fn comment(&self) -> &[u8] {
+        self.comment.as_slice()
+    }
+
+    /// Returns a reader for the data of an entry.  The data is decompressed, and its
+    /// CRC-32 and length are checked against the central directory once it has all been
+    /// read.  Only `STORED` and `DEFLATED` entries can be read.
+    pub fn read_entry<'a>(&'a mut self, index: usize) -> io::Result<ZipEntryReader<'a, R>> {
+        let entry = match self.entries.get(index) {
+            Some(entry) => entry.clone(),
+            None => {
+                return Err(io_error(ErrorKind::InvalidInput, "zip entry index is out of range",
+                    Some(format!("index {}, but the archive has {} entries", index, self.entries.len()))));
+            }
+        };
+        if entry.is_encrypted() {
+            return Err(invalid_archive("encrypted zip entries are not supported",
+                Some(format!("entry '{}'", String::from_utf8_lossy(entry.name.as_slice())))));
+        }
+        if entry.method != STORED && entry.method != DEFLATED {
+            return Err(invalid_archive("unsupported zip compression method",
+                Some(format!("entry '{}' uses method {}", String::from_utf8_lossy(entry.name.as_slice()), entry.method))));
+        }
+
+        // The sizes of the name and extra fields in the local header may differ from those
+        // in the central directory, so read them here to find the start of the data.
+        self.src.seek(SeekFrom::Start(entry.header_offset))?;
+        let header = read_vec(&mut self.src, LOCAL_HEADER_SIZE)?;
+        if get_u32(header.as_slice(), 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid_archive("invalid zip local header signature",
+                Some(format!("at offset {}", entry.header_offset))));
+        }
+        let skip = get_u16(header.as_slice(), 26) as i64 + get_u16(header.as_slice(), 28) as i64;
+        self.src.seek(SeekFrom::Current(skip))?;
+
+        let data = EntryData {
+            src: &mut self.src,
+            remaining: entry.compressed_size,
+        };
+        let inner = if entry.method == DEFLATED {
+            EntryReader::Deflated(Box::new(InflateReader::new_with_inflater(0x1000, Inflater::new_raw(WINDOW_BITS_MAX), data)))
+        }
+        else {
+            EntryReader::Stored(data)
+        };
+
+        Ok(ZipEntryReader {
+            inner: inner,
+            entry: entry,
+            crc: 0,
+            total_out: 0,
+        })
+    }
+
+    /// Returns the reader that contains the archive.
+    pub fn into_inner(self) -> R {
+        self.src
+    }
+}
+
+/// Reads the data of an entry of a `ZipArchive`.  See `ZipArchive::read_entry()`.
+pub struct ZipEntryReader<'a, R:'a> {
+    inner: EntryReader<'a, R>,
+    entry: ZipEntry,
+
+    crc: u32,           // CRC-32 of the data returned so far
+    total_out: u64,     // number of bytes returned so far
+}
+
+enum EntryReader<'a, R:'a> {
+    Stored(EntryData<'a, R>),
+    Deflated(Box<InflateReader<EntryData<'a, R>>>),
+}
+
+// Reads the bytes of an entry's data from the archive.
+struct EntryData<'a, R:'a> {
+    src: &'a mut R,
+    remaining: u64,
+}
+
+impl<'a, R:Read> Read for EntryData<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let len = min(buf.len() as u64, self.remaining) as usize;
+        let n = self.src.read(&mut buf[..len])?;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+impl<'a, R:Read> ZipEntryReader<'a, R> {
+    /// Returns the entry being read.
+    pub fn entry(&self) -> &ZipEntry {
+        &self.entry
+    }
+
+    // Called at the end of the data.  Checks the length and CRC-32 against the central
+    // directory.
+    fn check(&self) -> io::Result<()> {
+        if self.total_out != self.entry.uncompressed_size {
+            return Err(invalid_archive("incorrect length check",
+                Some(format!("in zip entry '{}': expected {} bytes, decoded {} bytes",
+                    String::from_utf8_lossy(self.entry.name.as_slice()), self.entry.uncompressed_size, self.total_out))));
+        }
+        if self.crc != self.entry.crc32 {
+            return Err(invalid_archive("incorrect data check",
+                Some(format!("in zip entry '{}': expected 0x{:08x}, computed 0x{:08x}",
+                    String::from_utf8_lossy(self.entry.name.as_slice()), self.entry.crc32, self.crc))));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R:Read> Read for ZipEntryReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = match self.inner {
+            EntryReader::Stored(ref mut r) => r.read(buf)?,
+            EntryReader::Deflated(ref mut r) => r.read(buf)?,
+        };
+        if n == 0 && !buf.is_empty() {
+            self.check()?;
+        }
+        self.crc = crc32(self.crc, &buf[..n]);
+        self.total_out += n as u64;
+        Ok(n)
+    }
+}
+
+// Finds and reads the end of central directory record, which is at the end of the archive,
+// followed only by the archive comment.  If it refers to a ZIP64 end of central directory
+// record, reads that too.
+fn read_end_of_central_dir<R:Read+Seek>(src: &mut R) -> io::Result<CentralDirectory> {
+    let file_len = src.seek(SeekFrom::End(0))?;
+    let search_len = min(file_len, (END_OF_CENTRAL_DIR_SIZE + 0xffff) as u64) as usize;
+    let search_start = file_len - search_len as u64;
+    src.seek(SeekFrom::Start(search_start))?;
+    let buf = read_vec(src, search_len)?;
+
+    if search_len < END_OF_CENTRAL_DIR_SIZE {
+        return Err(invalid_archive("not a zip archive", Some(format!("archive is only {} bytes", file_len))));
+    }
+
+    // Search backwards, since the comment could contain the signature.
+    let mut pos = search_len - END_OF_CENTRAL_DIR_SIZE;
+    loop {
+        if get_u32(buf.as_slice(), pos) == END_OF_CENTRAL_DIR_SIGNATURE
+            && pos + END_OF_CENTRAL_DIR_SIZE + get_u16(buf.as_slice(), pos + 20) as usize == search_len {
+            break;
+        }
+        if pos == 0 {
+            return Err(invalid_archive("not a zip archive", Some("no end of central directory record found".to_string())));
+        }
+        pos -= 1;
+    }
+    let eocd_offset = search_start + pos as u64;
+    debug!("found end of central directory record at offset {}", eocd_offset);
+
+    let eocd = &buf[pos..];
+    let disk = get_u16(eocd, 4);
+    let cd_disk = get_u16(eocd, 6);
+    let mut cd = CentralDirectory {
+        entries: get_u16(eocd, 10) as u64,
+        size: get_u32(eocd, 12) as u64,
+        offset: get_u32(eocd, 16) as u64,
+        comment: eocd[END_OF_CENTRAL_DIR_SIZE..search_len - pos].to_vec(),
+    };
+
+    let is_zip64 = cd.entries == 0xffff || cd.size == 0xffffffff || cd.offset == 0xffffffff;
+    if is_zip64 {
+        // The ZIP64 end of central directory locator is just before the end of central
+        // directory record, and gives the offset of the ZIP64 end of central directory record.
+        if eocd_offset < ZIP64_LOCATOR_SIZE as u64 {
+            return Err(invalid_archive("invalid zip64 end of central directory locator", None));
+        }
+        src.seek(SeekFrom::Start(eocd_offset - ZIP64_LOCATOR_SIZE as u64))?;
+        let locator = read_vec(src, ZIP64_LOCATOR_SIZE)?;
+        if get_u32(locator.as_slice(), 0) != ZIP64_LOCATOR_SIGNATURE {
+            return Err(invalid_archive("invalid zip64 end of central directory locator", None));
+        }
+        let zip64_offset = get_u64(locator.as_slice(), 8);
+        debug!("zip64 end of central directory record at offset {}", zip64_offset);
+
+        src.seek(SeekFrom::Start(zip64_offset))?;
+        let record = read_vec(src, ZIP64_END_OF_CENTRAL_DIR_SIZE)?;
+        let record = record.as_slice();
+        if get_u32(record, 0) != ZIP64_END_OF_CENTRAL_DIR_SIGNATURE {
+            return Err(invalid_archive("invalid zip64 end of central directory record",
+                Some(format!("at offset {}", zip64_offset))));
+        }
+        if get_u32(record, 16) != 0 || get_u32(record, 20) != 0 {
+            return Err(invalid_archive("multi-disk zip archives are not supported", None));
+        }
+        cd.entries = get_u64(record, 32);
+        cd.size = get_u64(record, 40);
+        cd.offset = get_u64(record, 48);
+    }
+    else if disk != 0 || cd_disk != 0 {
+        return Err(invalid_archive("multi-disk zip archives are not supported", None));
+    }
+
+    if cd.offset.checked_add(cd.size).is_none_or(|end| end > eocd_offset) {
+        return Err(invalid_archive("invalid zip central directory",
+            Some(format!("central directory at offset {} with size {} overlaps end of central directory record",
+                cd.offset, cd.size))));
+    }
+    Ok(cd)
+}
+
+// Parses a central directory header at the start of 'buf'.  Returns the entry, and the size
+// of the header.
+fn parse_central_header(buf: &[u8]) -> io::Result<(ZipEntry, usize)> {
+    if buf.len() < CENTRAL_HEADER_SIZE || get_u32(buf, 0) != CENTRAL_HEADER_SIGNATURE {
+        return Err(invalid_archive("invalid zip central directory", Some("bad central file header".to_string())));
+    }
+    let name_len = get_u16(buf, 28) as usize;
+    let extra_len = get_u16(buf, 30) as usize;
+    let comment_len = get_u16(buf, 32) as usize;
+    let len = CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len;
+    if buf.len() < len {
+        return Err(invalid_archive("invalid zip central directory", Some("central file header is truncated".to_string())));
+    }
+    let name_end = CENTRAL_HEADER_SIZE + name_len;
+    let extra_end = name_end + extra_len;
+
+    let mut entry = ZipEntry {
+        name: buf[CENTRAL_HEADER_SIZE..name_end].to_vec(),
+        comment: buf[extra_end..len].to_vec(),
+        method: get_u16(buf, 10),
+        flags: get_u16(buf, 8),
+        modified: DosDateTime::from_dos(get_u16(buf, 14), get_u16(buf, 12)),
+        crc32: get_u32(buf, 16),
+        compressed_size: get_u32(buf, 20) as u64,
+        uncompressed_size: get_u32(buf, 24) as u64,
+        external_attributes: get_u32(buf, 38),
+        header_offset: get_u32(buf, 42) as u64,
+    };
+
+    // The ZIP64 extra field holds the 64-bit values of the fields that are 0xffffffff, in
+    // this order.
+    let extra = &buf[name_end..extra_end];
+    let mut pos: usize = 0;
+    while pos + 4 <= extra.len() {
+        let id = get_u16(extra, pos);
+        let size = get_u16(extra, pos + 2) as usize;
+        let data = &extra[pos + 4..min(pos + 4 + size, extra.len())];
+        if id == ZIP64_EXTRA_ID {
+            let mut field: usize = 0;
+            for value in [&mut entry.uncompressed_size, &mut entry.compressed_size, &mut entry.header_offset].iter_mut() {
+                if **value == 0xffffffff {
+                    if field + 8 > data.len() {
+                        return Err(invalid_archive("invalid zip64 extra field", None));
+                    }
+                    **value = get_u64(data, field);
+                    field += 8;
+                }
+            }
+        }
+        pos += 4 + size;
+    }
+
+    Ok((entry, len))
+}
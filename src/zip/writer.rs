@@ -0,0 +1,345 @@
+use std::cmp::min;
+use std::io;
+use std::io::{Write, ErrorKind};
+
+use crate::io_error;
+use crate::crc::crc32;
+use crate::Flush;
+use crate::WINDOW_BITS_MAX;
+use crate::deflate::{Deflater, DeflateResult};
+use super::{ZipEntry, DosDateTime, STORED, DEFLATED};
+use super::{LOCAL_HEADER_SIGNATURE, CENTRAL_HEADER_SIGNATURE, END_OF_CENTRAL_DIR_SIGNATURE};
+use super::{ZIP64_END_OF_CENTRAL_DIR_SIGNATURE, ZIP64_LOCATOR_SIGNATURE, DATA_DESCRIPTOR_SIGNATURE};
+use super::{ZIP64_END_OF_CENTRAL_DIR_SIZE, ZIP64_EXTRA_ID, ZIP64_LIMIT};
+use super::{VERSION_DEFAULT, VERSION_ZIP64, FLAG_DATA_DESCRIPTOR, FLAG_UTF8};
+use super::{put_u16, put_u32, put_u64};
+
+/// Writes a ZIP archive to `dst`.
+///
+/// There are two ways to add an entry.  `start_entry()` begins an entry whose data is then
+/// written to the `ZipWriter`, through its `Write` implementation.  Since the sizes and CRC-32
+/// of the entry are not known until all of its data has been written, they are written after
+/// the data, in a data descriptor.  `add_entry()` adds an entry whose data is already in
+/// memory, and writes the sizes and CRC-32 in the local header instead.
+///
+/// Call `finish()` after the last entry.  This writes the central directory and returns the
+/// inner writer.  If the `ZipWriter` is dropped without calling `finish()`, the archive cannot
+/// be read.
+///
+/// ZIP64 records are written when they are needed: for entries of 4 GiB or more, and when
+/// the central directory starts 4 GiB or more into the archive, or has 65535 entries or more.
+pub struct ZipWriter<W> {
+    dst: W,
+    offset: u64,                // number of bytes written to 'dst'
+    entries: Vec<ZipEntry>,     // entries that have been written, for the central directory
+    current: Option<ZipEntry>,  // entry started by start_entry(), if any
+    comment: Vec<u8>,
+
+    deflater: Deflater,
+    outbuf: Vec<u8>,
+}
+
+impl<W:Write> ZipWriter<W> {
+    /// Creates a new ZipWriter which writes an archive to `dst`.  `level` is the compression
+    /// level for `DEFLATED` entries, and has the same meaning as in `Deflater::new_zlib()`.
+    pub fn new(level: i32, dst: W) -> ZipWriter<W> {
+        debug!("ZipWriter::new()");
+        ZipWriter {
+            dst: dst,
+            offset: 0,
+            entries: Vec::new(),
+            current: None,
+            comment: Vec::new(),
+            deflater: Deflater::new_raw(level, WINDOW_BITS_MAX),
+            outbuf: vec![0u8; 0x1000],
+        }
+    }
+
+    /// Sets the archive comment, which is written by `finish()`.  The comment can be at most
+    /// 65535 bytes long.
+    pub fn set_comment(&mut self, comment: &[u8]) -> io::Result<()> {
+        if comment.len() > 0xffff {
+            return Err(io_error(ErrorKind::InvalidInput, "zip archive comment is too long",
+                Some(format!("{} bytes", comment.len()))));
+        }
+        self.comment = comment.to_vec();
+        Ok(())
+    }
+
+    /// Starts a new entry.  Data written to the `ZipWriter` is added to this entry, until the
+    /// next call to `start_entry()`, `add_entry()`, or `finish()`.  `method` is `STORED` or
+    /// `DEFLATED`.  The names of directories end with '/', and directories have no data.
+    /// The entry is written with ZIP64 sizes in its local header and data descriptor, since
+    /// its size is not known in advance.
+    pub fn start_entry(&mut self, name: &str, method: u16, modified: DosDateTime) -> io::Result<()> {
+        self.finish_entry()?;
+        let mut entry = new_entry(name, method, modified)?;
+        entry.flags |= FLAG_DATA_DESCRIPTOR;
+        entry.header_offset = self.offset;
+        self.write_out(local_header(&entry).as_slice())?;
+        self.deflater.reset();
+        self.current = Some(entry);
+        Ok(())
+    }
+
+    /// Adds an entry whose data is `data`.  See `start_entry()`.
+    pub fn add_entry(&mut self, name: &str, method: u16, modified: DosDateTime, data: &[u8]) -> io::Result<()> {
+        self.finish_entry()?;
+        let mut entry = new_entry(name, method, modified)?;
+        entry.header_offset = self.offset;
+        entry.crc32 = crc32(0, data);
+        entry.uncompressed_size = data.len() as u64;
+
+        let mut compressed: Vec<u8> = Vec::new();
+        if method == DEFLATED {
+            self.deflater.reset();
+            deflate_to(&mut self.deflater, self.outbuf.as_mut_slice(), data, Flush::Finish, &mut compressed)?;
+        }
+        let body = if method == DEFLATED { compressed.as_slice() } else { data };
+        entry.compressed_size = body.len() as u64;
+
+        self.write_out(local_header(&entry).as_slice())?;
+        self.write_out(body)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Finishes the current entry, writes the central directory, and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_entry()?;
+
+        let cd_offset = self.offset;
+        for i in 0..self.entries.len() {
+            let header = central_header(&self.entries[i]);
+            self.write_out(header.as_slice())?;
+        }
+        let cd_size = self.offset - cd_offset;
+        let count = self.entries.len() as u64;
+        debug!("central directory: entries={} size={} offset={}", count, cd_size, cd_offset);
+
+        let mut end: Vec<u8> = Vec::new();
+        if count >= 0xffff || cd_size >= ZIP64_LIMIT || cd_offset >= ZIP64_LIMIT {
+            let zip64_offset = self.offset;
+            put_u32(&mut end, ZIP64_END_OF_CENTRAL_DIR_SIGNATURE);
+            put_u64(&mut end, (ZIP64_END_OF_CENTRAL_DIR_SIZE - 12) as u64);   // size of the rest of the record
+            put_u16(&mut end, VERSION_ZIP64);                                   // version made by
+            put_u16(&mut end, VERSION_ZIP64);                                   // version needed to extract
+            put_u32(&mut end, 0);                                               // this disk
+            put_u32(&mut end, 0);                                               // disk with the central directory
+            put_u64(&mut end, count);                                           // entries on this disk
+            put_u64(&mut end, count);                                           // total entries
+            put_u64(&mut end, cd_size);
+            put_u64(&mut end, cd_offset);
+
+            put_u32(&mut end, ZIP64_LOCATOR_SIGNATURE);
+            put_u32(&mut end, 0);                                               // disk with the ZIP64 record
+            put_u64(&mut end, zip64_offset);
+            put_u32(&mut end, 1);                                               // total disks
+        }
+        put_u32(&mut end, END_OF_CENTRAL_DIR_SIGNATURE);
+        put_u16(&mut end, 0);                                                   // this disk
+        put_u16(&mut end, 0);                                                   // disk with the central directory
+        put_u16(&mut end, min(count, 0xffff) as u16);                           // entries on this disk
+        put_u16(&mut end, min(count, 0xffff) as u16);                           // total entries
+        put_u32(&mut end, min(cd_size, ZIP64_LIMIT) as u32);
+        put_u32(&mut end, min(cd_offset, ZIP64_LIMIT) as u32);
+        put_u16(&mut end, self.comment.len() as u16);
+        end.extend_from_slice(self.comment.as_slice());
+        self.write_out(end.as_slice())?;
+
+        self.dst.flush()?;
+        Ok(self.dst)
+    }
+
+    pub fn inner(&mut self) -> &mut W {
+        &mut self.dst
+    }
+
+    // Finishes the entry started by start_entry(), if any, and writes its data descriptor.
+    fn finish_entry(&mut self) -> io::Result<()> {
+        let mut entry = match self.current.take() {
+            Some(entry) => entry,
+            None => return Ok(())
+        };
+        if entry.method == DEFLATED {
+            let n = deflate_to(&mut self.deflater, self.outbuf.as_mut_slice(), &[], Flush::Finish, &mut self.dst)?;
+            self.offset += n;
+            entry.compressed_size += n;
+        }
+        debug!("finished entry: {:?}", entry);
+
+        // The local header has a ZIP64 extra field, so the sizes are written with 64 bits.
+        let mut descriptor: Vec<u8> = Vec::new();
+        put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+        put_u32(&mut descriptor, entry.crc32);
+        put_u64(&mut descriptor, entry.compressed_size);
+        put_u64(&mut descriptor, entry.uncompressed_size);
+        self.write_out(descriptor.as_slice())?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn write_out(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.dst.write_all(buf)?;
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+}
+
+impl<W:Write> Write for ZipWriter<W> {
+    /// Adds data to the entry started by `start_entry()`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let entry = match self.current {
+            Some(ref mut entry) => entry,
+            None => {
+                return Err(io_error(ErrorKind::InvalidInput,
+                    "no zip entry has been started; call start_entry()", None));
+            }
+        };
+        entry.crc32 = crc32(entry.crc32, buf);
+        entry.uncompressed_size += buf.len() as u64;
+        let n = if entry.method == DEFLATED {
+            deflate_to(&mut self.deflater, self.outbuf.as_mut_slice(), buf, Flush::None, &mut self.dst)?
+        }
+        else {
+            self.dst.write_all(buf)?;
+            buf.len() as u64
+        };
+        entry.compressed_size += n;
+        self.offset += n;
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer.  Data that the compressor is holding is not flushed, since
+    /// that would make the compression worse.
+    fn flush(&mut self) -> io::Result<()> {
+        self.dst.flush()
+    }
+}
+
+// Compresses 'input' and writes the output to 'dst'.  With Flush::Finish, also ends the
+// stream.  Returns the number of bytes written.
+fn deflate_to<T:Write>(deflater: &mut Deflater, outbuf: &mut [u8], input: &[u8], flush: Flush, dst: &mut T) -> io::Result<u64> {
+    let mut inpos: usize = 0;
+    let mut written: u64 = 0;
+    while inpos < input.len() || flush == Flush::Finish {
+        match deflater.deflate(flush, &input[inpos..], outbuf) {
+            DeflateResult::Encoded(in_bytes, out_bytes) => {
+                inpos += in_bytes;
+                dst.write_all(&outbuf[..out_bytes])?;
+                written += out_bytes as u64;
+            }
+            DeflateResult::Finished(in_bytes, out_bytes) if flush == Flush::Finish => {
+                dst.write_all(&outbuf[..out_bytes])?;
+                written += out_bytes as u64;
+                break;
+            }
+            result => {
+                warn!("deflate returned {:?}", result);
+                return Err(io_error(ErrorKind::Other, "deflate failed", Some(format!("{:?}", result))));
+            }
+        }
+    }
+    Ok(written)
+}
+
+fn new_entry(name: &str, method: u16, modified: DosDateTime) -> io::Result<ZipEntry> {
+    if method != STORED && method != DEFLATED {
+        return Err(io_error(ErrorKind::InvalidInput, "unsupported zip compression method",
+            Some(format!("entry '{}' uses method {}", name, method))));
+    }
+    if name.len() > 0xffff {
+        return Err(io_error(ErrorKind::InvalidInput, "zip entry name is too long", None));
+    }
+    Ok(ZipEntry {
+        name: name.as_bytes().to_vec(),
+        comment: Vec::new(),
+        method: method,
+        flags: if name.bytes().any(|b| b >= 0x80) { FLAG_UTF8 } else { 0 },
+        modified: modified,
+        crc32: 0,
+        compressed_size: 0,
+        uncompressed_size: 0,
+        external_attributes: if name.ends_with("/") { 0x10 } else { 0 },   // MS-DOS directory attribute
+        header_offset: 0,
+    })
+}
+
+fn local_header(entry: &ZipEntry) -> Vec<u8> {
+    // The sizes of an entry written with start_entry() are not known yet, and may need 64 bits.
+    // Its header always has a ZIP64 extra field, with the sizes set to 0, so that its data
+    // descriptor can hold 64-bit sizes.
+    let zip64 = (entry.flags & FLAG_DATA_DESCRIPTOR) != 0
+        || entry.compressed_size >= ZIP64_LIMIT || entry.uncompressed_size >= ZIP64_LIMIT;
+    let (date, time) = entry.modified.to_dos();
+    let mut header: Vec<u8> = Vec::new();
+    put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+    put_u16(&mut header, if zip64 { VERSION_ZIP64 } else { VERSION_DEFAULT });
+    put_u16(&mut header, entry.flags);
+    put_u16(&mut header, entry.method);
+    put_u16(&mut header, time);
+    put_u16(&mut header, date);
+    put_u32(&mut header, entry.crc32);
+    if zip64 {
+        put_u32(&mut header, ZIP64_LIMIT as u32);
+        put_u32(&mut header, ZIP64_LIMIT as u32);
+    }
+    else {
+        put_u32(&mut header, entry.compressed_size as u32);
+        put_u32(&mut header, entry.uncompressed_size as u32);
+    }
+    put_u16(&mut header, entry.name.len() as u16);
+    put_u16(&mut header, if zip64 { 20 } else { 0 });
+    header.extend_from_slice(entry.name.as_slice());
+    if zip64 {
+        put_u16(&mut header, ZIP64_EXTRA_ID);
+        put_u16(&mut header, 16);
+        put_u64(&mut header, entry.uncompressed_size);
+        put_u64(&mut header, entry.compressed_size);
+    }
+    header
+}
+
+fn central_header(entry: &ZipEntry) -> Vec<u8> {
+    // Values that do not fit in 32 bits are written as 0xffffffff, and then in the ZIP64
+    // extra field, in this order.
+    let mut zip64: Vec<u8> = Vec::new();
+    let mut fields = [entry.uncompressed_size, entry.compressed_size, entry.header_offset];
+    for value in fields.iter_mut() {
+        if *value >= ZIP64_LIMIT {
+            put_u64(&mut zip64, *value);
+            *value = ZIP64_LIMIT;
+        }
+    }
+    let mut extra: Vec<u8> = Vec::new();
+    if zip64.len() != 0 {
+        put_u16(&mut extra, ZIP64_EXTRA_ID);
+        put_u16(&mut extra, zip64.len() as u16);
+        extra.extend_from_slice(zip64.as_slice());
+    }
+    let version = if zip64.len() != 0 { VERSION_ZIP64 } else { VERSION_DEFAULT };
+
+    let (date, time) = entry.modified.to_dos();
+    let mut header: Vec<u8> = Vec::new();
+    put_u32(&mut header, CENTRAL_HEADER_SIGNATURE);
+    put_u16(&mut header, version);                      // version made by (MS-DOS)
+    put_u16(&mut header, version);                      // version needed to extract
+    put_u16(&mut header, entry.flags);
+    put_u16(&mut header, entry.method);
+    put_u16(&mut header, time);
+    put_u16(&mut header, date);
+    put_u32(&mut header, entry.crc32);
+    put_u32(&mut header, fields[1] as u32);
+    put_u32(&mut header, fields[0] as u32);
+    put_u16(&mut header, entry.name.len() as u16);
+    put_u16(&mut header, extra.len() as u16);
+    put_u16(&mut header, entry.comment.len() as u16);
+    put_u16(&mut header, 0);                            // disk number start
+    put_u16(&mut header, 0);                            // internal file attributes
+    put_u32(&mut header, entry.external_attributes);
+    put_u32(&mut header, fields[2] as u32);
+    header.extend_from_slice(entry.name.as_slice());
+    header.extend_from_slice(extra.as_slice());
+    header.extend_from_slice(entry.comment.as_slice());
+    header
+}
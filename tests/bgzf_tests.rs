@@ -0,0 +1,199 @@
+extern crate zlib;
+
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use zlib::Z_DEFAULT_COMPRESSION;
+use zlib::bgzf::{BgzfReader, BgzfWriter, GziIndex, EOF_BLOCK, make_virtual_offset, split_virtual_offset};
+use zlib::inflate::InflateReader;
+
+fn read_file(filename: &str) -> Vec<u8> {
+    match fs::read(filename) {
+        Ok(data) => data,
+        Err(err) => {
+            panic!("Failed to read {}.  Make sure you run this from the root of the 'zlib' dir.  {:?}", filename, err);
+        }
+    }
+}
+
+// Reads exactly 'len' bytes, which may span blocks.
+fn read_len<R:Read>(reader: &mut BgzfReader<R>, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).unwrap();
+    buf
+}
+
+fn read_all<R:Read>(src: &mut R) -> io::Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    src.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// hamlet.tar.bgz has three blocks of data, of 65280, 65280, and 64512 bytes, and the EOF
+// block.  hamlet.tar.bgz.gzi is its index, in the format written by "bgzip -i".
+const BLOCK_OFFSETS: [u64; 4] = [0, 27186, 54269, 80185];
+
+#[test]
+fn test_bgzf_read() {
+    let hamlet = read_file("tests/hamlet.tar");
+    let data = read_file("tests/hamlet.tar.bgz");
+    let mut reader = BgzfReader::new(io::Cursor::new(data.as_slice()));
+    assert_eq!(reader.virtual_offset(), 0);
+
+    // Each read stops at the end of a block.
+    let mut buf = [0u8; 0x20000];
+    let mut output: Vec<u8> = Vec::new();
+    let mut block_lens: Vec<usize> = Vec::new();
+    loop {
+        let n = reader.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        output.extend_from_slice(&buf[..n]);
+        block_lens.push(n);
+    }
+    assert!(output == hamlet);
+    assert_eq!(block_lens, vec![65280, 65280, 64512]);
+    assert_eq!(reader.virtual_offset(), make_virtual_offset(data.len() as u64, 0));
+}
+
+#[test]
+fn test_bgzf_virtual_offsets() {
+    assert_eq!(make_virtual_offset(27186, 100), (27186 << 16) | 100);
+    assert_eq!(split_virtual_offset((27186 << 16) | 100), (27186, 100));
+
+    let hamlet = read_file("tests/hamlet.tar");
+    let data = read_file("tests/hamlet.tar.bgz");
+    let mut reader = BgzfReader::new(io::Cursor::new(data.as_slice()));
+
+    // Read through the file, saving the virtual offset every 10000 bytes.
+    let mut offsets: Vec<u64> = Vec::new();
+    for i in 0..hamlet.len() / 10000 {
+        offsets.push(reader.virtual_offset());
+        let chunk = read_len(&mut reader, 10000);
+        assert!(chunk.as_slice() == &hamlet[i * 10000..(i + 1) * 10000]);
+    }
+    assert_eq!(offsets[7], make_virtual_offset(BLOCK_OFFSETS[1], 70000 - 65280));
+
+    // Seek back to each one, in reverse order, and check the data there.
+    for i in (0..offsets.len()).rev() {
+        reader.seek(offsets[i]).unwrap();
+        assert_eq!(reader.virtual_offset(), offsets[i]);
+        let chunk = read_len(&mut reader, 100);
+        assert!(chunk.as_slice() == &hamlet[i * 10000..i * 10000 + 100]);
+    }
+
+    // Seeking to the end of the file is allowed; seeking past the end of a block is not.
+    let mut buf = [0u8; 10];
+    reader.seek(make_virtual_offset(BLOCK_OFFSETS[3], 0)).unwrap();
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    let err = reader.seek(make_virtual_offset(BLOCK_OFFSETS[2], 64513)).expect_err("expected seek() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), format!("virtual offset is past the end of its block: virtual offset 0x{:x}, block length 64512",
+        make_virtual_offset(BLOCK_OFFSETS[2], 64513)));
+}
+
+#[test]
+fn test_bgzf_gzi_index() {
+    let hamlet = read_file("tests/hamlet.tar");
+    let gzi = read_file("tests/hamlet.tar.bgz.gzi");
+    let index = GziIndex::read_from(&mut io::Cursor::new(gzi.as_slice())).unwrap();
+    assert_eq!(index.entries(), [(BLOCK_OFFSETS[1], 65280), (BLOCK_OFFSETS[2], 130560)].as_slice());
+    assert_eq!(index.virtual_offset(0), 0);
+    assert_eq!(index.virtual_offset(65279), make_virtual_offset(0, 65279));
+    assert_eq!(index.virtual_offset(65280), make_virtual_offset(BLOCK_OFFSETS[1], 0));
+    assert_eq!(index.virtual_offset(150000), make_virtual_offset(BLOCK_OFFSETS[2], 150000 - 130560));
+
+    let mut written = Vec::new();
+    index.write_to(&mut written).unwrap();
+    assert!(written == gzi);
+
+    let data = read_file("tests/hamlet.tar.bgz");
+    let mut reader = BgzfReader::new(io::Cursor::new(data.as_slice()));
+    for &offset in [150000usize, 0, 65280, 195000, 65279, 100].iter() {
+        reader.seek_uncompressed(&index, offset as u64).unwrap();
+        let chunk = read_len(&mut reader, 72);
+        assert!(chunk.as_slice() == &hamlet[offset..offset + 72]);
+    }
+
+    // Entries must be in order.
+    let mut bad = gzi.clone();
+    bad[15] = 0xff;
+    let err = GziIndex::read_from(&mut io::Cursor::new(bad.as_slice())).expect_err("expected read_from() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "invalid gzi index: entry 1 is out of order");
+}
+
+#[test]
+fn test_bgzf_write() {
+    let hamlet = read_file("tests/hamlet.tar");
+    let mut writer = BgzfWriter::new(Z_DEFAULT_COMPRESSION, Vec::new());
+    for chunk in hamlet.chunks(1000) {
+        writer.write_all(chunk).unwrap();
+    }
+    assert_eq!(writer.virtual_offset(), make_virtual_offset(BLOCK_OFFSETS[2], 64512));
+    writer.flush().unwrap();
+    let index = writer.index().clone();
+    let data = writer.finish().unwrap();
+
+    // Deflater and zlib agree at the default level, so this matches the fixture exactly.
+    assert!(data == read_file("tests/hamlet.tar.bgz"));
+    assert!(&data[data.len() - EOF_BLOCK.len()..] == EOF_BLOCK.as_slice());
+    let mut gzi = Vec::new();
+    index.write_to(&mut gzi).unwrap();
+    assert!(gzi == read_file("tests/hamlet.tar.bgz.gzi"));
+
+    // A BGZF file is a multi-member GZIP file, so InflateReader can read it too.
+    let mut reader = InflateReader::new_gzip(0x1000, io::Cursor::new(data.as_slice()));
+    reader.set_multi_member(true);
+    assert!(read_all(&mut reader).unwrap() == hamlet);
+}
+
+#[test]
+fn test_bgzf_write_flush() {
+    // flush() ends the block, so each record starts a new block.
+    let mut writer = BgzfWriter::new(Z_DEFAULT_COMPRESSION, Vec::new());
+    let mut offsets: Vec<u64> = Vec::new();
+    for i in 0..5usize {
+        offsets.push(writer.virtual_offset());
+        writer.write_all(format!("record {}\n", i).as_bytes()).unwrap();
+        writer.flush().unwrap();
+    }
+    writer.flush().unwrap();
+    assert_eq!(writer.index().entries().len(), 4);
+    let data = writer.finish().unwrap();
+
+    let mut reader = BgzfReader::new(io::Cursor::new(data.as_slice()));
+    for i in (0..5usize).rev() {
+        let (_, within_block) = split_virtual_offset(offsets[i]);
+        assert_eq!(within_block, 0);
+        reader.seek(offsets[i]).unwrap();
+        assert_eq!(read_len(&mut reader, 9), format!("record {}\n", i).into_bytes());
+    }
+}
+
+#[test]
+fn test_bgzf_errors() {
+    // A GZIP file without the BC subfield
+    let data = read_file("tests/hamlet.tar.gz");
+    let err = read_all(&mut BgzfReader::new(io::Cursor::new(data.as_slice()))).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "not a bgzf block: in block at offset 0");
+
+    // A block size that does not match the block
+    let mut data = read_file("tests/hamlet.tar.bgz");
+    data[BLOCK_OFFSETS[1] as usize + 16] += 1;
+    let err = read_all(&mut BgzfReader::new(io::Cursor::new(data.as_slice()))).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.to_string(), format!("incorrect bgzf block size: in block at offset {}: expected 27084 bytes, found 27083 bytes", BLOCK_OFFSETS[1]));
+
+    // A corrupted CRC-32
+    let mut data = read_file("tests/hamlet.tar.bgz");
+    data[BLOCK_OFFSETS[2] as usize - 8] ^= 1;
+    let err = read_all(&mut BgzfReader::new(io::Cursor::new(data.as_slice()))).expect_err("expected read_to_end() to fail");
+    assert!(err.to_string().starts_with(&format!("incorrect data check: in block at offset {}: expected 0x", BLOCK_OFFSETS[1])));
+
+    // A truncated block
+    let data = read_file("tests/hamlet.tar.bgz");
+    let err = read_all(&mut BgzfReader::new(io::Cursor::new(&data[..40000]))).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.to_string(), format!("unexpected end of input: in block at offset {}", BLOCK_OFFSETS[1]));
+}
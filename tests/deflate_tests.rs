@@ -0,0 +1,546 @@
+extern crate zlib;
+
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use zlib::{Flush, ZERR, WINDOW_BITS_DEFAULT, Z_DEFAULT_COMPRESSION};
+use zlib::{Z_DEFAULT_STRATEGY, Z_FILTERED, Z_HUFFMAN_ONLY, Z_RLE, Z_FIXED};
+use zlib::deflate::{Deflater, DeflateResult, DeflateWriter, ParallelGzipWriter, PARALLEL_BLOCK_SIZE_DEFAULT};
+use zlib::inflate::{Inflater, InflateResult, InflateReader};
+
+fn read_file(filename: &str) -> Vec<u8> {
+    match fs::read(filename) {
+        Ok(data) => data,
+        Err(err) => {
+            panic!("Failed to read {}.  Make sure you run this from the root of the 'zlib' dir.  {:?}", filename, err);
+        }
+    }
+}
+
+fn read_all<R:Read>(src: &mut R) -> io::Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    src.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// Compresses all of 'input', feeding the Deflater at most in_chunk bytes at a time,
+// and giving it an output buffer of out_chunk bytes.
+fn deflate_all(deflater: &mut Deflater, input: &[u8], in_chunk: usize, out_chunk: usize) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut outbuf: Vec<u8> = vec![0u8; out_chunk];
+    let mut input_pos: usize = 0;
+
+    loop {
+        let in_end = if input.len() - input_pos > in_chunk { input_pos + in_chunk } else { input.len() };
+        let flush = if in_end == input.len() { Flush::Finish } else { Flush::None };
+        match deflater.deflate(flush, &input[input_pos..in_end], outbuf.as_mut_slice()) {
+            DeflateResult::Encoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&outbuf[..output_bytes_written]);
+            }
+            DeflateResult::Finished(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&outbuf[..output_bytes_written]);
+                break;
+            }
+            other => {
+                panic!("unexpected result from deflate: {:?}", other);
+            }
+        }
+    }
+
+    assert_eq!(input_pos, input.len());
+    output
+}
+
+fn inflate_all(inflater: &mut Inflater, input: &[u8]) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut outbuf: Vec<u8> = vec![0u8; 0x1000];
+    let mut input_pos: usize = 0;
+
+    loop {
+        match inflater.inflate(None, &input[input_pos..], outbuf.as_mut_slice()) {
+            InflateResult::Eof(_) => {
+                break;
+            }
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&outbuf[..output_bytes_written]);
+            }
+            InflateResult::NeedInput => {
+                panic!("inflate needs more input, but the compressed stream is complete");
+            }
+            InflateResult::Error(err, offset) => {
+                panic!("inflate reported an error: {} at input offset {}", err.description(), offset);
+            }
+            InflateResult::NeedDictionary(_) => {
+                panic!("inflate needs a dictionary, but none was used to compress");
+            }
+            InflateResult::Boundary(_, _) => {
+                panic!("inflate stopped at a block boundary, but no flush was requested");
+            }
+        }
+    }
+
+    output
+}
+
+fn check_same(expected: &[u8], actual: &[u8]) {
+    assert_eq!(expected.len(), actual.len());
+    for i in 0..expected.len() {
+        if expected[i] != actual[i] {
+            panic!("outputs differ!  at output offset {}, expected {} found {}", i, expected[i], actual[i]);
+        }
+    }
+}
+
+fn test_gzip_roundtrip(level: i32, in_chunk: usize, out_chunk: usize) {
+    let original = read_file("zlib-1.2.8.tar");
+    let mut deflater = Deflater::new_gzip(level);
+    let compressed = deflate_all(&mut deflater, original.as_slice(), in_chunk, out_chunk);
+    println!("compressed {} bytes to {} bytes", original.len(), compressed.len());
+    assert!(compressed.len() < original.len());
+
+    let mut inflater = Inflater::new_gzip();
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(original.as_slice(), decompressed.as_slice());
+}
+
+#[test]
+fn test_deflate_gzip_large_bufs() {
+    test_gzip_roundtrip(Z_DEFAULT_COMPRESSION, 0x10000, 0x10000);
+}
+
+#[test]
+fn test_deflate_gzip_tiny_bufs() {
+    test_gzip_roundtrip(Z_DEFAULT_COMPRESSION, 0x40, 0x40);
+}
+
+#[test]
+fn test_deflate_gzip_tiny_inbuf_large_outbuf() {
+    test_gzip_roundtrip(Z_DEFAULT_COMPRESSION, 0x40, 0x10000);
+}
+
+#[test]
+fn test_deflate_gzip_large_inbuf_tiny_outbuf() {
+    test_gzip_roundtrip(Z_DEFAULT_COMPRESSION, 0x10000, 0x40);
+}
+
+#[test]
+fn test_deflate_gzip_fast() {
+    test_gzip_roundtrip(1, 0x1000, 0x1000);
+}
+
+#[test]
+fn test_deflate_gzip_stored() {
+    let original = read_file("tests/hamlet.tar");
+    let mut deflater = Deflater::new_gzip(0);
+    let compressed = deflate_all(&mut deflater, original.as_slice(), 0x1000, 0x1000);
+    assert!(compressed.len() > original.len());
+
+    let mut inflater = Inflater::new_gzip();
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(original.as_slice(), decompressed.as_slice());
+}
+
+#[test]
+fn test_deflate_raw() {
+    let original = read_file("tests/hamlet.tar");
+    let mut deflater = Deflater::new_raw(Z_DEFAULT_COMPRESSION, WINDOW_BITS_DEFAULT);
+    let compressed = deflate_all(&mut deflater, original.as_slice(), 0x1000, 0x1000);
+
+    let mut inflater = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(original.as_slice(), decompressed.as_slice());
+}
+
+#[test]
+fn test_deflate_zlib_header_and_trailer() {
+    let input = b"hello, hello, hello, hello";
+    let mut deflater = Deflater::new_zlib(Z_DEFAULT_COMPRESSION);
+    let compressed = deflate_all(&mut deflater, input, 0x100, 0x100);
+
+    // CMF/FLG for a 32K window at the default level
+    assert_eq!(compressed[0], 0x78);
+    assert_eq!(compressed[1], 0x9c);
+
+    // Adler-32 of the input, in big-endian order
+    let n = compressed.len();
+    assert_eq!(&compressed[n - 4..], [0x7c, 0x16, 0x09, 0x35].as_slice());
+
+    // The body is a raw DEFLATE stream.
+    let mut inflater = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    let decompressed = inflate_all(&mut inflater, &compressed[2..n - 4]);
+    check_same(input, decompressed.as_slice());
+}
+
+#[test]
+fn test_deflate_sync_flush() {
+    let mut deflater = Deflater::new_raw(Z_DEFAULT_COMPRESSION, WINDOW_BITS_DEFAULT);
+    let mut outbuf: Vec<u8> = vec![0u8; 0x100];
+
+    // A sync flush emits all pending output, followed by an empty stored block.
+    match deflater.deflate(Flush::SyncFlush, b"abcabcabc", outbuf.as_mut_slice()) {
+        DeflateResult::Encoded(9, n) => {
+            assert!(n >= 4);
+            assert_eq!(&outbuf[n - 4..n], [0x00, 0x00, 0xff, 0xff].as_slice());
+        }
+        other => panic!("unexpected result from deflate: {:?}", other)
+    }
+
+    // Flushing again with no new input makes no progress.
+    assert_eq!(deflater.deflate(Flush::SyncFlush, &[], outbuf.as_mut_slice()), DeflateResult::BufError);
+
+    match deflater.deflate(Flush::Finish, &[], outbuf.as_mut_slice()) {
+        DeflateResult::Finished(0, _) => {}
+        other => panic!("unexpected result from deflate: {:?}", other)
+    }
+
+    // After the stream is finished, only Flush::Finish is accepted.
+    assert_eq!(deflater.deflate(Flush::None, &[], outbuf.as_mut_slice()), DeflateResult::StreamError);
+}
+
+fn test_deflate_writer(outbufsize: usize, chunk_size: usize) {
+    let original = read_file("zlib-1.2.8.tar");
+
+    let mut writer = DeflateWriter::new_gzip(Z_DEFAULT_COMPRESSION, outbufsize, Vec::new());
+    for chunk in original.as_slice().chunks(chunk_size) {
+        writer.write_all(chunk).unwrap();
+    }
+    let compressed = writer.finish().unwrap();
+    println!("compressed {} bytes to {} bytes", original.len(), compressed.len());
+
+    let mut reader = InflateReader::new_gzip(0x1000, io::Cursor::new(compressed));
+    let decompressed = read_all(&mut reader).unwrap();
+    check_same(original.as_slice(), decompressed.as_slice());
+}
+
+#[test]
+fn test_deflate_writer_large_bufs() {
+    test_deflate_writer(0x10000, 0x10000);
+}
+
+#[test]
+fn test_deflate_writer_tiny_bufs() {
+    test_deflate_writer(0x40, 0x40);
+}
+
+#[test]
+fn test_deflate_writer_flush() {
+    let mut writer = DeflateWriter::new_raw(Z_DEFAULT_COMPRESSION, 0x1000, Vec::new());
+    writer.write_all(b"hello, hello, hello, hello").unwrap();
+    writer.flush().unwrap();
+
+    // Everything written so far can be decompressed, even though the stream is not finished.
+    let flushed = writer.inner().to_vec();
+    let mut inflater = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    let mut outbuf: Vec<u8> = vec![0u8; 0x100];
+    match inflater.inflate(None, flushed.as_slice(), outbuf.as_mut_slice()) {
+        InflateResult::Decoded(_, n) => {
+            check_same(b"hello, hello, hello, hello", &outbuf[..n]);
+        }
+        _ => panic!("expected inflate to decode the flushed data")
+    }
+
+    // Flushing again without writing anything adds nothing.
+    writer.flush().unwrap();
+    assert_eq!(writer.inner().len(), flushed.len());
+
+    writer.write_all(b" world").unwrap();
+    let compressed = writer.finish().unwrap();
+    let mut inflater = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(b"hello, hello, hello, hello world", decompressed.as_slice());
+}
+
+// Compresses tests/hamlet.tar as a raw stream with the given parameters, checks that it
+// decompresses correctly, and returns the compressed size.
+fn test_raw_roundtrip(level: i32, strategy: usize) -> usize {
+    let original = read_file("tests/hamlet.tar");
+    let mut deflater = Deflater::new_raw(level, WINDOW_BITS_DEFAULT);
+    assert_eq!(deflater.set_params(level, strategy), DeflateResult::Encoded(0, 0));
+    let compressed = deflate_all(&mut deflater, original.as_slice(), 0x1000, 0x1000);
+    println!("level {} strategy {}: compressed {} bytes to {} bytes", level, strategy, original.len(), compressed.len());
+
+    let mut inflater = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(original.as_slice(), decompressed.as_slice());
+    compressed.len()
+}
+
+#[test]
+fn test_deflate_all_levels() {
+    let mut sizes: Vec<usize> = Vec::new();
+    for level in 0..10 {
+        sizes.push(test_raw_roundtrip(level, Z_DEFAULT_STRATEGY));
+    }
+
+    // Level 0 only stores; the fast and slow levels each compress better as the level increases.
+    assert!(sizes[1] < sizes[0]);
+    assert!(sizes[3] <= sizes[1]);
+    assert!(sizes[9] <= sizes[4]);
+    assert!(sizes[4] < sizes[1]);
+}
+
+#[test]
+fn test_deflate_strategy_filtered() {
+    test_raw_roundtrip(Z_DEFAULT_COMPRESSION, Z_FILTERED);
+}
+
+#[test]
+fn test_deflate_strategy_huffman_only() {
+    let huffman_size = test_raw_roundtrip(Z_DEFAULT_COMPRESSION, Z_HUFFMAN_ONLY);
+    let default_size = test_raw_roundtrip(Z_DEFAULT_COMPRESSION, Z_DEFAULT_STRATEGY);
+    assert!(huffman_size > default_size);
+}
+
+#[test]
+fn test_deflate_strategy_rle() {
+    test_raw_roundtrip(Z_DEFAULT_COMPRESSION, Z_RLE);
+    test_raw_roundtrip(1, Z_RLE);
+}
+
+#[test]
+fn test_deflate_strategy_fixed() {
+    test_raw_roundtrip(Z_DEFAULT_COMPRESSION, Z_FIXED);
+    test_raw_roundtrip(9, Z_FIXED);
+}
+
+#[test]
+fn test_deflate_level_0_all_strategies() {
+    // At level 0, a Huffman-only or RLE block can outgrow a small window, so it can no
+    // longer be sent as a stored block.
+    let original = read_file("tests/hamlet.tar");
+    let input = original.as_slice();
+    for &window_bits in [9, 12, WINDOW_BITS_DEFAULT].iter() {
+        for &strategy in [Z_DEFAULT_STRATEGY, Z_FILTERED, Z_HUFFMAN_ONLY, Z_RLE, Z_FIXED].iter() {
+            let mut deflater = Deflater::new_raw(0, window_bits);
+            assert_eq!(deflater.set_params(0, strategy), DeflateResult::Encoded(0, 0));
+            let compressed = deflate_all(&mut deflater, input, 0x1000, 0x1000);
+
+            let mut inflater = Inflater::new_inflate(window_bits);
+            let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+            check_same(input, decompressed.as_slice());
+        }
+    }
+}
+
+#[test]
+fn test_deflate_header_level_bytes() {
+    // (level, strategy, gzip XFL, zlib FLEVEL)
+    let cases: [(i32, usize, u8, u8); 10] = [
+        (0, Z_DEFAULT_STRATEGY, 4, 0),
+        (1, Z_DEFAULT_STRATEGY, 4, 0),
+        (2, Z_DEFAULT_STRATEGY, 0, 1),
+        (6, Z_DEFAULT_STRATEGY, 0, 2),
+        (7, Z_DEFAULT_STRATEGY, 0, 3),
+        (9, Z_DEFAULT_STRATEGY, 2, 3),
+        (6, Z_FILTERED, 0, 2),
+        (6, Z_HUFFMAN_ONLY, 4, 0),
+        (6, Z_RLE, 4, 0),
+        (6, Z_FIXED, 4, 0),
+    ];
+    for &(level, strategy, xfl, flevel) in cases.iter() {
+        let mut deflater = Deflater::new_gzip(level);
+        assert_eq!(deflater.set_params(level, strategy), DeflateResult::Encoded(0, 0));
+        let compressed = deflate_all(&mut deflater, b"hello, hello", 0x1000, 0x1000);
+        assert_eq!(compressed[8], xfl, "gzip XFL for level {} strategy {}", level, strategy);
+
+        let mut deflater = Deflater::new_zlib(level);
+        assert_eq!(deflater.set_params(level, strategy), DeflateResult::Encoded(0, 0));
+        let compressed = deflate_all(&mut deflater, b"hello, hello", 0x1000, 0x1000);
+        assert_eq!(compressed[1] >> 6, flevel, "zlib FLEVEL for level {} strategy {}", level, strategy);
+        assert_eq!(((compressed[0] as u32) << 8 | compressed[1] as u32) % 31, 0);
+    }
+
+    for &(level, xfl) in [(1, 4u8), (6, 0), (9, 2)].iter() {
+        let mut writer = ParallelGzipWriter::new(level, 2, Vec::new());
+        writer.write_all(b"hello, hello").unwrap();
+        let compressed = writer.finish().unwrap();
+        assert_eq!(compressed[8], xfl, "parallel gzip XFL for level {}", level);
+    }
+}
+
+#[test]
+fn test_deflate_set_params_errors() {
+    let mut deflater = Deflater::new_raw(Z_DEFAULT_COMPRESSION, WINDOW_BITS_DEFAULT);
+    assert_eq!(deflater.set_params(10, Z_DEFAULT_STRATEGY), DeflateResult::StreamError);
+    assert_eq!(deflater.set_params(6, Z_FIXED + 1), DeflateResult::StreamError);
+}
+
+#[test]
+fn test_deflate_set_params_midstream() {
+    let original = read_file("tests/hamlet.tar");
+    let half = original.len() / 2;
+    let mut deflater = Deflater::new_gzip(1);
+    let mut compressed: Vec<u8> = Vec::new();
+    let mut outbuf: Vec<u8> = vec![0u8; 0x100000];
+
+    match deflater.deflate(Flush::None, &original[..half], outbuf.as_mut_slice()) {
+        DeflateResult::Encoded(n, out_bytes) => {
+            assert_eq!(n, half);
+            compressed.extend_from_slice(&outbuf[..out_bytes]);
+        }
+        other => panic!("unexpected result from deflate: {:?}", other)
+    }
+
+    // Changing to a slow level requires the current block to be flushed first.
+    assert_eq!(deflater.set_params(9, Z_FILTERED), DeflateResult::BufError);
+    match deflater.deflate(Flush::Block, &[], outbuf.as_mut_slice()) {
+        DeflateResult::Encoded(0, out_bytes) => {
+            compressed.extend_from_slice(&outbuf[..out_bytes]);
+        }
+        other => panic!("unexpected result from deflate: {:?}", other)
+    }
+    assert_eq!(deflater.set_params(9, Z_FILTERED), DeflateResult::Encoded(0, 0));
+
+    compressed.extend_from_slice(deflate_all(&mut deflater, &original[half..], 0x1000, 0x1000).as_slice());
+
+    let mut inflater = Inflater::new_gzip();
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(original.as_slice(), decompressed.as_slice());
+}
+
+// Made by the C zlib, with compressobj(6, DEFLATED, 15, 8, 0, zdict=DICTIONARY_TEXT), and
+// with window bits -15 for the raw stream.
+static DICTIONARY_TEXT: &[u8] = b"To be, or not to be, that is the question:\n";
+static DICTIONARY_MESSAGE: &[u8] = b"To be, or not to be: that is the question.  The rest is silence.\n";
+static DICTIONARY_ZLIB: [u8; 37] = [
+    0x78, 0xbb, 0x36, 0x1e, 0x0e, 0x69, 0x0b, 0xc1, 0x54, 0x6a, 0x85, 0x55, 0xa9, 0x9e, 0x82, 0x42,
+    0x08, 0x90, 0x5b, 0x04, 0xe4, 0x81, 0xa4, 0x8a, 0x33, 0x73, 0x52, 0xf3, 0x92, 0x53, 0xf5, 0xb8,
+    0x00, 0xcb, 0xb4, 0x15, 0xd7];
+const DICTIONARY_ID: u32 = 0x361e0e69;
+
+#[test]
+fn test_deflate_set_dictionary_zlib() {
+    let mut deflater = Deflater::new_zlib(Z_DEFAULT_COMPRESSION);
+    assert_eq!(deflater.set_dictionary(DICTIONARY_TEXT), ZERR::Ok);
+    let compressed = deflate_all(&mut deflater, DICTIONARY_MESSAGE, 0x100, 0x100);
+    check_same(&DICTIONARY_ZLIB, compressed.as_slice());
+
+    let mut inflater = Inflater::new_zlib();
+    match inflater.inflate(None, compressed.as_slice(), [0u8; 0x100].as_mut_slice()) {
+        InflateResult::Decoded(6, 0) => {}
+        other => panic!("unexpected result from inflate: {:?}", other)
+    }
+    match inflater.inflate(None, &compressed[6..], [0u8; 0x100].as_mut_slice()) {
+        InflateResult::NeedDictionary(dictid) => assert_eq!(dictid, DICTIONARY_ID),
+        other => panic!("unexpected result from inflate: {:?}", other)
+    }
+    assert_eq!(inflater.set_dictionary(DICTIONARY_TEXT), ZERR::Ok);
+    let decompressed = inflate_all(&mut inflater, &compressed[6..]);
+    check_same(DICTIONARY_MESSAGE, decompressed.as_slice());
+}
+
+#[test]
+fn test_deflate_set_dictionary_raw() {
+    let mut deflater = Deflater::new_raw(Z_DEFAULT_COMPRESSION, WINDOW_BITS_DEFAULT);
+    assert_eq!(deflater.set_dictionary(DICTIONARY_TEXT), ZERR::Ok);
+    let compressed = deflate_all(&mut deflater, DICTIONARY_MESSAGE, 0x100, 0x100);
+    check_same(&DICTIONARY_ZLIB[6..33], compressed.as_slice());
+
+    let mut inflater = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(inflater.set_dictionary(DICTIONARY_TEXT), ZERR::Ok);
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(DICTIONARY_MESSAGE, decompressed.as_slice());
+
+    // A dictionary longer than the window is trimmed to its last 32 KiB.
+    let original = read_file("tests/hamlet.tar");
+    let (dictionary, rest) = original.split_at(100000);
+    let mut deflater = Deflater::new_raw(Z_DEFAULT_COMPRESSION, WINDOW_BITS_DEFAULT);
+    assert_eq!(deflater.set_dictionary(dictionary), ZERR::Ok);
+    let compressed = deflate_all(&mut deflater, rest, 0x1000, 0x1000);
+    let mut inflater = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(inflater.set_dictionary(&dictionary[100000 - 0x8000..]), ZERR::Ok);
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(rest, decompressed.as_slice());
+}
+
+#[test]
+fn test_deflate_set_dictionary_errors() {
+    // GZIP streams cannot have a dictionary.
+    let mut deflater = Deflater::new_gzip(Z_DEFAULT_COMPRESSION);
+    assert_eq!(deflater.set_dictionary(DICTIONARY_TEXT), ZERR::StreamError);
+
+    // For zlib streams, the dictionary ID goes in the header, so it must come first.
+    let mut deflater = Deflater::new_zlib(Z_DEFAULT_COMPRESSION);
+    let mut outbuf: Vec<u8> = vec![0u8; 0x100];
+    match deflater.deflate(Flush::None, b"abc", outbuf.as_mut_slice()) {
+        DeflateResult::Encoded(3, _) => {}
+        other => panic!("unexpected result from deflate: {:?}", other)
+    }
+    assert_eq!(deflater.set_dictionary(DICTIONARY_TEXT), ZERR::StreamError);
+}
+
+// Compresses 'original' with a ParallelGzipWriter, writing it in chunks of 'chunk_size' bytes,
+// checks that it decompresses correctly, and returns the compressed data.
+fn parallel_gzip_roundtrip(original: &[u8], threads: usize, block_size: usize, chunk_size: usize) -> Vec<u8> {
+    let mut writer = ParallelGzipWriter::new_with_block_size(Z_DEFAULT_COMPRESSION, threads, block_size, Vec::new());
+    for chunk in original.chunks(chunk_size) {
+        writer.write_all(chunk).unwrap();
+    }
+    let compressed = writer.finish().unwrap();
+    println!("threads {} block_size {}: compressed {} bytes to {} bytes", threads, block_size, original.len(), compressed.len());
+
+    // InflateReader checks the CRC-32 and length in the trailer.
+    {
+        let mut reader = InflateReader::new_gzip(0x1000, io::Cursor::new(compressed.as_slice()));
+        let decompressed = read_all(&mut reader).unwrap();
+        check_same(original, decompressed.as_slice());
+    }
+    compressed
+}
+
+#[test]
+fn test_parallel_gzip_writer() {
+    let original = read_file("zlib-1.2.8.tar");
+    let compressed = parallel_gzip_roundtrip(original.as_slice(), 4, PARALLEL_BLOCK_SIZE_DEFAULT, 0x10000);
+
+    // The same header as Deflater::new_gzip()
+    assert_eq!(&compressed[..10], [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3].as_slice());
+
+    // With the previous 32 KiB as the dictionary, the output is barely larger than from a
+    // single Deflater.
+    let mut deflater = Deflater::new_gzip(Z_DEFAULT_COMPRESSION);
+    let serial = deflate_all(&mut deflater, original.as_slice(), 0x10000, 0x10000);
+    println!("serial: {} bytes, parallel: {} bytes", serial.len(), compressed.len());
+    assert!(compressed.len() < serial.len() + serial.len() / 100);
+}
+
+#[test]
+fn test_parallel_gzip_writer_small_blocks() {
+    // Blocks smaller than the dictionary, so the dictionary spans several blocks
+    let original = read_file("tests/hamlet.tar");
+    parallel_gzip_roundtrip(original.as_slice(), 3, 5000, 777);
+    parallel_gzip_roundtrip(original.as_slice(), 1, 0x10000, 0x10000);
+}
+
+#[test]
+fn test_parallel_gzip_writer_empty() {
+    let compressed = parallel_gzip_roundtrip(&[], 2, PARALLEL_BLOCK_SIZE_DEFAULT, 1);
+    assert_eq!(compressed.len(), 20);
+}
+
+#[test]
+fn test_parallel_gzip_writer_flush() {
+    let mut writer = ParallelGzipWriter::new(Z_DEFAULT_COMPRESSION, 2, Vec::new());
+    writer.write_all(b"hello, hello, hello, hello").unwrap();
+    writer.flush().unwrap();
+
+    // Everything written so far can be decompressed, even though the stream is not finished.
+    let flushed = writer.inner().to_vec();
+    let mut inflater = Inflater::new_gzip();
+    let mut outbuf: Vec<u8> = vec![0u8; 0x100];
+    match inflater.inflate(None, flushed.as_slice(), outbuf.as_mut_slice()) {
+        InflateResult::Decoded(_, n) => {
+            check_same(b"hello, hello, hello, hello", &outbuf[..n]);
+        }
+        _ => panic!("expected inflate to decode the flushed data")
+    }
+
+    writer.write_all(b" world").unwrap();
+    let compressed = writer.finish().unwrap();
+    let mut inflater = Inflater::new_gzip();
+    let decompressed = inflate_all(&mut inflater, compressed.as_slice());
+    check_same(b"hello, hello, hello, hello world", decompressed.as_slice());
+}
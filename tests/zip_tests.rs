@@ -0,0 +1,346 @@
+extern crate zlib;
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use zlib::Z_DEFAULT_COMPRESSION;
+use zlib::zip::{ZipArchive, ZipWriter, DosDateTime, STORED, DEFLATED};
+
+fn read_file(filename: &str) -> Vec<u8> {
+    match fs::read(filename) {
+        Ok(data) => data,
+        Err(err) => {
+            panic!("Failed to read {}.  Make sure you run this from the root of the 'zlib' dir.  {:?}", filename, err);
+        }
+    }
+}
+
+fn open_archive(filename: &str) -> ZipArchive<File> {
+    let file = File::open(filename).unwrap();
+    ZipArchive::new(file).unwrap()
+}
+
+fn read_all<R:Read>(src: &mut R) -> io::Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    src.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+const SILENCE: &[u8] = b"The rest is silence.\nThe rest is silence.\nThe rest is silence.\n";
+
+#[test]
+fn test_zip_list_entries() {
+    let archive = open_archive("tests/hamlet.zip");
+    let entries = archive.entries();
+    assert_eq!(entries.len(), 2);
+
+    assert_eq!(entries[0].name, b"hamlet.tar".to_vec());
+    assert_eq!(entries[0].method, DEFLATED);
+    assert_eq!(entries[0].crc32, 0xf07b6e50);
+    assert_eq!(entries[0].compressed_size, 77464);
+    assert_eq!(entries[0].uncompressed_size, 195072);
+    assert_eq!(entries[0].header_offset, 0);
+    assert!(!entries[0].is_dir());
+
+    assert_eq!(entries[1].name, b"silence.txt".to_vec());
+    assert_eq!(entries[1].crc32, 0xf4c8c10d);
+    assert_eq!(entries[1].uncompressed_size, 63);
+    assert_eq!(entries[1].header_offset, 77504);
+
+    assert_eq!(archive.find(b"silence.txt"), Some(1));
+    assert_eq!(archive.find(b"missing.txt"), None);
+    assert_eq!(archive.comment(), b"");
+}
+
+#[test]
+fn test_zip_read_entries() {
+    let mut archive = open_archive("tests/hamlet.zip");
+    let hamlet = read_file("tests/hamlet.tar");
+
+    // Read them out of order, to make sure that each read seeks to its entry.
+    let output = read_all(&mut archive.read_entry(1).unwrap()).unwrap();
+    assert_eq!(output.as_slice(), SILENCE);
+    let output = read_all(&mut archive.read_entry(0).unwrap()).unwrap();
+    assert!(output == hamlet);
+    let output = read_all(&mut archive.read_entry(1).unwrap()).unwrap();
+    assert_eq!(output.as_slice(), SILENCE);
+}
+
+#[test]
+fn test_zip64() {
+    // Made by Info-ZIP with -fz, so it has ZIP64 extra fields and a ZIP64 end of central
+    // directory record, even though it is small.
+    let mut archive = open_archive("tests/zip64.zip");
+    assert_eq!(archive.comment(), b"zip64 test archive");
+
+    {
+        let entries = archive.entries();
+        assert_eq!(entries.len(), 3);
+
+        assert_eq!(entries[0].name, b"silence.txt".to_vec());
+        assert_eq!(entries[0].uncompressed_size, 63);
+
+        assert_eq!(entries[1].name, b"check.txt".to_vec());
+        assert_eq!(entries[1].method, STORED);
+        assert_eq!(entries[1].crc32, 0xcbf43926);
+        assert_eq!(entries[1].compressed_size, 9);
+        assert_eq!(entries[1].uncompressed_size, 9);
+        assert_eq!(entries[1].header_offset, 87);
+        assert_eq!(entries[1].modified, DosDateTime { year: 2015, month: 1, day: 11, hour: 12, minute: 34, second: 56 });
+        assert_eq!(entries[1].external_attributes >> 16, 0o100644);
+
+        assert_eq!(entries[2].name, b"dir/".to_vec());
+        assert!(entries[2].is_dir());
+        assert_eq!(entries[2].uncompressed_size, 0);
+    }
+
+    let output = read_all(&mut archive.read_entry(0).unwrap()).unwrap();
+    assert_eq!(output.as_slice(), SILENCE);
+    let output = read_all(&mut archive.read_entry(1).unwrap()).unwrap();
+    assert_eq!(output.as_slice(), b"123456789");
+    let output = read_all(&mut archive.read_entry(2).unwrap()).unwrap();
+    assert_eq!(output.len(), 0);
+}
+
+#[test]
+fn test_zip_dos_date_time() {
+    let t = DosDateTime { year: 2015, month: 1, day: 11, hour: 12, minute: 34, second: 56 };
+    let (date, time) = t.to_dos();
+    assert_eq!(date, (35 << 9) | (1 << 5) | 11);
+    assert_eq!(time, (12 << 11) | (34 << 5) | 28);
+    assert_eq!(DosDateTime::from_dos(date, time), t);
+
+    // The format holds the years 1980 to 2107; other years are clamped.
+    let first = DosDateTime { year: 1980, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+    let last = DosDateTime { year: 2107, month: 12, day: 31, hour: 23, minute: 59, second: 58 };
+    assert_eq!(first.to_dos(), ((1 << 5) | 1, 0));
+    assert_eq!(last.to_dos(), ((127 << 9) | (12 << 5) | 31, (23 << 11) | (59 << 5) | 29));
+    for &(y, expected) in [(0, first), (1979, first), (2108, last), (0xffff, last)].iter() {
+        let t = DosDateTime { year: y, month: 6, day: 15, hour: 12, minute: 30, second: 30 };
+        let (date, time) = t.to_dos();
+        assert_eq!(DosDateTime::from_dos(date, time), expected);
+    }
+}
+
+#[test]
+fn test_zip_crc_mismatch() {
+    // Change the stored data of check.txt, which starts after the 30-byte local header, the
+    // 9-byte name, and the 20-byte ZIP64 extra field.
+    let mut data = read_file("tests/zip64.zip");
+    assert_eq!(&data[87 + 59..87 + 68], b"123456789");
+    data[87 + 59] = b'0';
+
+    let mut archive = ZipArchive::new(io::Cursor::new(data.as_slice())).unwrap();
+    let err = read_all(&mut archive.read_entry(1).unwrap()).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "incorrect data check: in zip entry 'check.txt': expected 0xcbf43926, computed 0xdc8f2d65");
+
+    // The other entries are still readable.
+    let output = read_all(&mut archive.read_entry(0).unwrap()).unwrap();
+    assert_eq!(output.as_slice(), SILENCE);
+}
+
+#[test]
+fn test_zip_not_an_archive() {
+    let data = read_file("tests/hamlet.tar.gz");
+    let err = ZipArchive::new(io::Cursor::new(data.as_slice())).err().expect("expected ZipArchive::new() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "not a zip archive: no end of central directory record found");
+
+    let data = [0u8; 10];
+    let err = ZipArchive::new(io::Cursor::new(data.as_slice())).err().expect("expected ZipArchive::new() to fail");
+    assert_eq!(err.to_string(), "not a zip archive: archive is only 10 bytes");
+}
+
+#[test]
+fn test_zip_central_directory_overflow() {
+    // A ZIP64 end of central directory record whose central directory offset plus size
+    // overflows 64 bits.
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(&0x06064b50u32.to_le_bytes());
+    data.extend_from_slice(&44u64.to_le_bytes());           // size of the rest of the record
+    data.extend_from_slice(&[45, 0, 45, 0]);                // versions
+    data.extend_from_slice(&[0u8; 8]);                      // disks
+    data.extend_from_slice(&[0u8; 16]);                     // entries
+    data.extend_from_slice(&16u64.to_le_bytes());           // central directory size
+    data.extend_from_slice(&u64::MAX.to_le_bytes());        // central directory offset
+    data.extend_from_slice(&0x07064b50u32.to_le_bytes());
+    data.extend_from_slice(&[0u8; 4]);
+    data.extend_from_slice(&0u64.to_le_bytes());            // offset of the ZIP64 record
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    data.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+    data.extend_from_slice(&[0xff; 8]);
+    data.extend_from_slice(&[0, 0]);
+
+    let err = ZipArchive::new(io::Cursor::new(data.as_slice())).err().expect("expected ZipArchive::new() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("invalid zip central directory: "));
+}
+
+const MODIFIED: DosDateTime = DosDateTime { year: 2015, month: 1, day: 11, hour: 12, minute: 34, second: 56 };
+
+#[test]
+fn test_zip_writer_round_trip() {
+    let hamlet = read_file("tests/hamlet.tar");
+    let mut writer = ZipWriter::new(Z_DEFAULT_COMPRESSION, Vec::new());
+    writer.start_entry("hamlet.tar", DEFLATED, MODIFIED).unwrap();
+    for chunk in hamlet.chunks(1000) {
+        writer.write_all(chunk).unwrap();
+    }
+    writer.add_entry("check.txt", STORED, MODIFIED, b"123456789").unwrap();
+    writer.add_entry("silence.txt", DEFLATED, MODIFIED, SILENCE).unwrap();
+    writer.add_entry("dir/", STORED, MODIFIED, &[]).unwrap();
+    writer.start_entry("dir/caf\u{e9}.txt", STORED, MODIFIED).unwrap();
+    writer.write_all(SILENCE).unwrap();
+    writer.start_entry("empty.txt", DEFLATED, MODIFIED).unwrap();
+    writer.set_comment(b"written by ZipWriter").unwrap();
+    let data = writer.finish().unwrap();
+
+    let mut archive = ZipArchive::new(io::Cursor::new(data.as_slice())).unwrap();
+    assert_eq!(archive.comment(), b"written by ZipWriter");
+    {
+        let entries = archive.entries();
+        assert_eq!(entries.len(), 6);
+        let names: Vec<&[u8]> = entries.iter().map(|e| e.name.as_slice()).collect();
+        assert_eq!(names, vec![b"hamlet.tar".as_slice(), b"check.txt".as_slice(), b"silence.txt".as_slice(),
+            b"dir/".as_slice(), "dir/caf\u{e9}.txt".as_bytes(), b"empty.txt".as_slice()]);
+
+        // Entries written with start_entry() have data descriptors.
+        assert_eq!(entries[0].flags, 8);
+        assert_eq!(entries[0].method, DEFLATED);
+        assert_eq!(entries[0].crc32, 0xf07b6e50);
+        assert_eq!(entries[0].uncompressed_size, 195072);
+        assert!(entries[0].compressed_size < 80000);
+        assert_eq!(entries[0].modified, MODIFIED);
+
+        assert_eq!(entries[1].flags, 0);
+        assert_eq!(entries[1].method, STORED);
+        assert_eq!(entries[1].crc32, 0xcbf43926);
+        assert_eq!(entries[1].compressed_size, 9);
+
+        assert!(entries[3].is_dir());
+        assert!(entries[4].is_utf8());
+        assert!(!entries[3].is_utf8());
+        assert_eq!(entries[5].uncompressed_size, 0);
+    }
+
+    let expected = [hamlet.as_slice(), b"123456789".as_slice(), SILENCE, b"".as_slice(), SILENCE, b"".as_slice()];
+    for (i, &expected) in expected.iter().enumerate() {
+        let output = read_all(&mut archive.read_entry(i).unwrap()).unwrap();
+        assert!(output.as_slice() == expected);
+    }
+}
+
+#[test]
+fn test_zip_read_entry_out_of_range() {
+    let mut writer = ZipWriter::new(Z_DEFAULT_COMPRESSION, Vec::new());
+    writer.add_entry("a.txt", STORED, MODIFIED, b"a").unwrap();
+    let data = writer.finish().unwrap();
+
+    let mut archive = ZipArchive::new(io::Cursor::new(data.as_slice())).unwrap();
+    let err = archive.read_entry(1).err().expect("expected read_entry() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "zip entry index is out of range: index 1, but the archive has 1 entries");
+}
+
+#[test]
+fn test_zip_writer_errors() {
+    let mut writer = ZipWriter::new(Z_DEFAULT_COMPRESSION, Vec::new());
+    let err = writer.write_all(b"data").expect_err("expected write() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let err = writer.start_entry("a.txt", 12, MODIFIED).expect_err("expected start_entry() to fail");
+    assert_eq!(err.to_string(), "unsupported zip compression method: entry 'a.txt' uses method 12");
+
+    let err = writer.set_comment(&[b'x'; 0x10000]).expect_err("expected set_comment() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    writer.set_comment(&[b'x'; 0xffff]).unwrap();
+}
+
+#[test]
+fn test_zip_writer_streamed_entry_layout() {
+    // An entry written with start_entry() has a ZIP64 extra field in its local header, with
+    // the sizes set to 0, and a data descriptor with 64-bit sizes.
+    let mut writer = ZipWriter::new(Z_DEFAULT_COMPRESSION, Vec::new());
+    writer.start_entry("a.txt", STORED, MODIFIED).unwrap();
+    writer.write_all(b"hello").unwrap();
+    let data = writer.finish().unwrap();
+
+    assert_eq!(&data[0..4], [0x50, 0x4b, 0x03, 0x04].as_slice());
+    assert_eq!(&data[4..6], [45, 0].as_slice());                           // version needed to extract
+    assert_eq!(&data[6..8], [8, 0].as_slice());                            // flags
+    assert_eq!(&data[14..18], [0, 0, 0, 0].as_slice());                    // crc32
+    assert_eq!(&data[18..26], [0xff; 8].as_slice());                       // sizes
+    assert_eq!(&data[26..30], [5, 0, 20, 0].as_slice());                   // name and extra lengths
+    assert_eq!(&data[30..35], b"a.txt".as_slice());
+    assert_eq!(&data[35..39], [1, 0, 16, 0].as_slice());                   // ZIP64 extra field
+    assert_eq!(&data[39..55], [0u8; 16].as_slice());
+    assert_eq!(&data[55..60], b"hello".as_slice());
+
+    let crc: u32 = 0x3610a686;
+    let mut descriptor: Vec<u8> = vec![0x50, 0x4b, 0x07, 0x08];
+    descriptor.extend_from_slice(&crc.to_le_bytes());
+    descriptor.extend_from_slice(&5u64.to_le_bytes());
+    descriptor.extend_from_slice(&5u64.to_le_bytes());
+    assert_eq!(&data[60..84], descriptor.as_slice());
+    assert_eq!(&data[84..88], [0x50, 0x4b, 0x01, 0x02].as_slice());
+
+    let mut archive = ZipArchive::new(io::Cursor::new(data.as_slice())).unwrap();
+    assert_eq!(archive.entries()[0].crc32, crc);
+    let output = read_all(&mut archive.read_entry(0).unwrap()).unwrap();
+    assert_eq!(output.as_slice(), b"hello");
+}
+
+#[test]
+fn test_zip_writer_zip64_entry_count() {
+    // More than 65535 entries need the ZIP64 end of central directory record.
+    let mut writer = ZipWriter::new(Z_DEFAULT_COMPRESSION, Vec::new());
+    for i in 0..70000usize {
+        writer.add_entry(&format!("{}", i), STORED, MODIFIED, &[]).unwrap();
+    }
+    let data = writer.finish().unwrap();
+
+    let n = data.len();
+    assert_eq!(&data[n - 22..n - 18], [0x50, 0x4b, 0x05, 0x06].as_slice());
+    assert_eq!(&data[n - 14..n - 12], [0xff, 0xff].as_slice());        // total entries
+    assert_eq!(&data[n - 42..n - 38], [0x50, 0x4b, 0x06, 0x07].as_slice());
+
+    let archive = ZipArchive::new(io::Cursor::new(data.as_slice())).unwrap();
+    assert_eq!(archive.entries().len(), 70000);
+    assert_eq!(archive.entries()[69999].name, b"69999".to_vec());
+}
+
+// This test writes and reads 4 GiB of data, which takes about a minute.  Run it with
+// "cargo test -- --ignored".
+#[test]
+#[ignore]
+fn test_zip_writer_zip64_large_entry() {
+    let zeros = [0u8; 0x10000];
+    let mut writer = ZipWriter::new(1, Vec::new());
+    writer.start_entry("zeros", DEFLATED, MODIFIED).unwrap();
+    for _ in 0..0x10001usize {
+        writer.write_all(zeros.as_slice()).unwrap();
+    }
+    writer.add_entry("after.txt", STORED, MODIFIED, b"123456789").unwrap();
+    let data = writer.finish().unwrap();
+
+    let mut archive = ZipArchive::new(io::Cursor::new(data.as_slice())).unwrap();
+    assert_eq!(archive.entries()[0].uncompressed_size, 0x100010000);
+    {
+        let mut reader = archive.read_entry(0).unwrap();
+        let mut buf = [0u8; 0x10000];
+        let mut total: u64 = 0;
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            total += n as u64;
+        }
+        assert_eq!(total, 0x100010000);
+    }
+    let output = read_all(&mut archive.read_entry(1).unwrap()).unwrap();
+    assert_eq!(output.as_slice(), b"123456789");
+}
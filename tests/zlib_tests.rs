@@ -1,21 +1,19 @@
-#![feature(macro_rules)]
-
 extern crate zlib;
 
+use std::fmt::Debug;
+use std::fs;
+use std::fs::File;
 use std::io;
-use std::iter::repeat;
-use std::fmt::Show;
-use std::os;
-use zlib::{WINDOW_BITS_DEFAULT};
-use zlib::inflate::{Inflater,InflateResult};
-use zlib::inflate::InflateReader;
-use std::io::IoErrorKind;
-use std::io::IoError;
+use std::io::{Read, Write};
+use zlib::{Flush, WINDOW_BITS_DEFAULT, ZERR};
+use zlib::{adler32, adler32_combine, crc32_combine};
+use zlib::deflate::{Deflater, DeflateResult, DeflateWriter};
+use zlib::inflate::{Inflater,InflateResult,InflateError,SyncResult,BlockPosition,StreamEnd};
+use zlib::inflate::{InflateReader, BufInflateReader, InflateWriter, GzipIndex};
 
 const INBUF_SIZE :usize = 0x1000;
-const OUTBUF_SIZE :usize = 0x1000;
 
-fn unwrap_or_warn<T,E:Show>(op: Result<T,E>) -> T {
+fn unwrap_or_warn<T,E:Debug>(op: Result<T,E>) -> T {
 	match op {
 		Ok(val) => val,
 		Err(err) => {
@@ -24,6 +22,22 @@ fn unwrap_or_warn<T,E:Show>(op: Result<T,E>) -> T {
 	}
 }
 
+// Reads up to 'len' bytes from 'src' and appends them to 'buf', like the old Reader::push().
+// Returns 0 at the end of 'src'.
+fn push<R:Read>(src: &mut R, len: usize, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let start = buf.len();
+    buf.resize(start + len, 0);
+    let result = src.read(&mut buf[start..]);
+    buf.truncate(start + *result.as_ref().unwrap_or(&0));
+    result
+}
+
+fn read_all<R:Read>(src: &mut R) -> io::Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    src.read_to_end(&mut data)?;
+    Ok(data)
+}
+
 #[test]
 fn test_inflate_large_bufs() {
 	test_inflate(0x10000, 0x10000); // 64 KB
@@ -45,20 +59,19 @@ fn test_large_inbuf_tiny_outbuf() {
 }
 
 fn test_inflate(in_bufsize: usize, out_bufsize: usize) {
-    let input_path = Path::new("zlib-1.2.8.tar.gz");
-    let check_path = Path::new("zlib-1.2.8.tar");			// contains the expected (good) output
+    let input_path = "zlib-1.2.8.tar.gz";
+    let check_path = "zlib-1.2.8.tar";			// contains the expected (good) output
 
     // open compressed input file
-    let mut input_file = io::BufferedReader::new(unwrap_or_warn(io::File::open(&input_path)));
+    let mut input_file = io::BufReader::new(unwrap_or_warn(File::open(input_path)));
 
     // open known-good input file
-    let mut check_file = io::BufferedReader::new(unwrap_or_warn(io::File::open(&check_path)));
+    let mut check_file = io::BufReader::new(unwrap_or_warn(File::open(check_path)));
 
 	println!("successfully opened test files");
 
     let mut input_buffer: Vec<u8> = Vec::with_capacity(in_bufsize);
-    let mut output_buffer: Vec<u8> = Vec::with_capacity(out_bufsize);
-    output_buffer.extend(repeat(0).take(out_bufsize));
+    let mut output_buffer: Vec<u8> = vec![0; out_bufsize];
     let mut check_buffer: Vec<u8> = Vec::new();
 
     let mut input_pos: usize = 0; // index of next byte in input_buffer to read
@@ -67,7 +80,6 @@ fn test_inflate(in_bufsize: usize, out_bufsize: usize) {
 
     let mut state = Inflater::new_gzip();
     let mut input_eof = false;
-    let mut loop_count: usize = 0;
     let mut total_out: u64 = 0;
 
     // Main loop
@@ -75,36 +87,31 @@ fn test_inflate(in_bufsize: usize, out_bufsize: usize) {
         if input_pos == input_buffer.len() && !input_eof {
             input_buffer.clear();
             input_pos = 0;
-            let bytes_read = match input_file.push(in_bufsize, &mut input_buffer) {
+            match push(&mut input_file, in_bufsize, &mut input_buffer) {
+                Ok(0) => {
+                    println!("reached EOF on input");
+                    input_eof = true;
+                }
                 Ok(n) => {
-                    assert!(n > 0);
                     println!("loaded {} input bytes", n);
-                    n
                 }
                 Err(err) => {
-                    if err.kind == io::EndOfFile {
-                        println!("reached EOF on input");
-                        input_eof = true;
-                        0
-                    }
-                    else {
-                        panic!("failed to read input: {}", err);
-                    }
+                    panic!("failed to read input: {}", err);
                 }
-            };
+            }
         }
 
-        match state.inflate(None, input_buffer.slice_from(input_pos), out_data) {
+        match state.inflate(None, &input_buffer[input_pos..], out_data) {
             InflateResult::Eof(_) => {
                 break;
             }
 
-            InflateResult::InvalidData => {
-                println!("InvalidData");
+            InflateResult::Error(err, offset) => {
+                println!("Error: {} at input offset {}", err.description(), offset);
                 break;
             }
 
-            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) | InflateResult::Boundary(input_bytes_read, output_bytes_written) => {
                 // println!("InflateDecoded: input_bytes_read: {} output_bytes_written: {}", input_bytes_read, output_bytes_written);                
                 println!("input_bytes_read = {}, output_bytes_written = {}", input_bytes_read, output_bytes_written);
 
@@ -116,11 +123,11 @@ fn test_inflate(in_bufsize: usize, out_bufsize: usize) {
                     let mut cpos = 0;
                     while cpos < output_bytes_written {
                         let clen_want = output_bytes_written - cpos;
-                	    assert!(check_buffer.len() == 0);
-                	    let clen_got = check_file.push(clen_want, &mut check_buffer).unwrap();
+                	    assert!(check_buffer.is_empty());
+                	    let clen_got = push(&mut check_file, clen_want, &mut check_buffer).unwrap();
                         assert!(clen_got <= clen_want);
 
-                	    for i in range(0, clen_got) {
+                	    for i in 0..clen_got {
                 		    if check_buffer[i] != out_data[cpos + i] {
                 			    panic!("outputs differ!  at output offset {}, expected {} found {}",
                                     total_out + (i as u64),
@@ -134,7 +141,6 @@ fn test_inflate(in_bufsize: usize, out_bufsize: usize) {
                     }
                 }
 
-                loop_count += 1;
                 total_out += output_bytes_written as u64;
             }
 
@@ -142,6 +148,10 @@ fn test_inflate(in_bufsize: usize, out_bufsize: usize) {
                 println!("NeedInput");
                 unimplemented!();
             }
+
+            InflateResult::NeedDictionary(dictid) => {
+                panic!("unexpected NeedDictionary(0x{:08x})", dictid);
+            }
         }
     }
 }
@@ -152,14 +162,11 @@ fn test_inflate_reader_basic() {
 }
 
 fn test_inflate_reader(input_filename: &str, check_filename: &str, in_bufsize: usize, out_bufsize: usize) {
-    let input_path = Path::new(input_filename);
-    let check_path = Path::new(check_filename);     // contains the expected (good) output
-
     // open compressed input file
-    let input_file = io::BufferedReader::new(unwrap_or_warn(io::File::open(&input_path)));
+    let input_file = io::BufReader::new(unwrap_or_warn(File::open(input_filename)));
 
-    // open known-good input file
-    let mut check_file = io::BufferedReader::new(unwrap_or_warn(io::File::open(&check_path)));
+    // open known-good input file (contains the expected (good) output)
+    let mut check_file = io::BufReader::new(unwrap_or_warn(File::open(check_filename)));
 
 	println!("successfully opened test files");
 
@@ -172,16 +179,19 @@ fn test_inflate_reader(input_filename: &str, check_filename: &str, in_bufsize: u
     let mut total_out: u64 = 0;
 
     loop {
-        match inflater.push(out_bufsize, &mut output_buffer) {
+        match push(&mut inflater, out_bufsize, &mut output_buffer) {
+            Ok(0) => {
+                break;
+            }
             Ok(output_bytes_written) => {
                 println!("inflate reader returned {} bytes", output_bytes_written);
 
                 // Check the data that we just received against the same data in the known-good file.
                 if output_bytes_written != 0 {
-                	assert!(check_buffer.len() == 0);
-                	let check_bytes_read = check_file.push(output_bytes_written, &mut check_buffer).unwrap();
+                	assert!(check_buffer.is_empty());
+                	let check_bytes_read = push(&mut check_file, output_bytes_written, &mut check_buffer).unwrap();
                 	assert!(check_bytes_read == output_bytes_written);
-                	for i in range(0, output_bytes_written) {
+                	for i in 0..output_bytes_written {
                 		if check_buffer[i] != output_buffer[i] {
                 			panic!("outputs differ!  at output offset {}, expected {} found {}", total_out + (i as u64), check_buffer[i], output_buffer[i]);
                 		}
@@ -193,10 +203,1493 @@ fn test_inflate_reader(input_filename: &str, check_filename: &str, in_bufsize: u
 
                 total_out += output_bytes_written as u64;
             }
-            Err(_) => {
-                println!("push() returned error, assuming EOF for now");
+            Err(err) => {
+                panic!("read() returned error: {}", err);
+            }
+        }
+    }
+}
+
+// A small JSON message, compressed against a preset dictionary.  The streams were produced
+// by the C zlib, with compressobj(9, DEFLATED, +/-15, 9, 0, zdict=JSON_DICTIONARY).
+static JSON_DICTIONARY: &[u8] = b"{\"user\": \"\", \"action\": \"\", \"timestamp\": , \"tags\": []}";
+static JSON_MESSAGE: &[u8] = b"{\"user\": \"alice\", \"action\": \"login\", \"timestamp\": 1420070400, \"tags\": [\"web\", \"mobile\"]}";
+
+static JSON_ZLIB_WITH_DICT: [u8; 56] = [
+    0x78, 0xf9, 0x98, 0x50, 0x0f, 0x55, 0xab, 0x86, 0x6b, 0x4a, 0xcc, 0xc9,
+    0x4c, 0x4e, 0x45, 0xd5, 0x99, 0x93, 0x9f, 0x9e, 0x99, 0x87, 0xae, 0xdd,
+    0xd0, 0xc4, 0xc8, 0xc0, 0xc0, 0xdc, 0xc0, 0xc4, 0xc0, 0x00, 0xc9, 0x20,
+    0xa5, 0xf2, 0xd4, 0x24, 0x90, 0xc2, 0xdc, 0xfc, 0xa4, 0xcc, 0x9c, 0x54,
+    0xa5, 0xd8, 0x5a, 0x00, 0x91, 0x51, 0x19, 0xe8,
+];
+
+static JSON_RAW_WITH_DICT: [u8; 46] = [
+    0xab, 0x86, 0x6b, 0x4a, 0xcc, 0xc9, 0x4c, 0x4e, 0x45, 0xd5, 0x99, 0x93,
+    0x9f, 0x9e, 0x99, 0x87, 0xae, 0xdd, 0xd0, 0xc4, 0xc8, 0xc0, 0xc0, 0xdc,
+    0xc0, 0xc4, 0xc0, 0x00, 0xc9, 0x20, 0xa5, 0xf2, 0xd4, 0x24, 0x90, 0xc2,
+    0xdc, 0xfc, 0xa4, 0xcc, 0x9c, 0x54, 0xa5, 0xd8, 0x5a, 0x00,
+];
+
+const JSON_DICTIONARY_ID: u32 = 0x98500f55;
+
+// Inflates all of 'input', which must fit in a single 0x1000-byte output buffer.
+fn inflate_small(state: &mut Inflater, input: &[u8]) -> Vec<u8> {
+    inflate_small_into(state, input, 0x1000)
+}
+
+// Inflates 'input' until the end of the stream or the end of the input, into an output buffer
+// of 'output_size' bytes.
+fn inflate_small_into(state: &mut Inflater, input: &[u8], output_size: usize) -> Vec<u8> {
+    let mut output: Vec<u8> = vec![0u8; output_size];
+    let mut input_pos: usize = 0;
+    let mut output_pos: usize = 0;
+    loop {
+        match state.inflate(None, &input[input_pos..], &mut output[output_pos..]) {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output_pos += output_bytes_written;
+            }
+            InflateResult::Eof(_) => {
+                break;
+            }
+            InflateResult::NeedDictionary(dictid) => {
+                panic!("unexpected NeedDictionary(0x{:08x})", dictid);
+            }
+            InflateResult::Boundary(_, _) => {
+                panic!("unexpected Boundary");
+            }
+            InflateResult::NeedInput => {
+                assert_eq!(input_pos, input.len());
+                break;
+            }
+            InflateResult::Error(err, offset) => {
+                panic!("unexpected error: {:?} at input offset {}", err, offset);
+            }
+        }
+    }
+    output.truncate(output_pos);
+    output
+}
+
+// Feeds 'input' to the inflater until it asks for a dictionary, and returns the number of
+// input bytes consumed (the zlib header and the dictionary ID).
+fn inflate_until_need_dictionary(state: &mut Inflater, input: &[u8]) -> usize {
+    let mut output = [0u8; 0x100];
+    let mut input_pos: usize = 0;
+    loop {
+        match state.inflate(None, &input[input_pos..], output.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                assert_eq!(output_bytes_written, 0);
+                input_pos += input_bytes_read;
+            }
+            InflateResult::NeedDictionary(dictid) => {
+                assert_eq!(dictid, JSON_DICTIONARY_ID);
+                return input_pos;
+            }
+            _ => panic!("expected inflate to ask for a dictionary")
+        }
+    }
+}
+
+#[test]
+fn test_inflate_zlib_with_dictionary() {
+    let mut state = Inflater::new_zlib();
+    let input_pos = inflate_until_need_dictionary(&mut state, &JSON_ZLIB_WITH_DICT);
+    assert_eq!(input_pos, 6);
+
+    // Asking again does not make progress, and still reports the same dictionary ID.
+    match state.inflate(None, &JSON_ZLIB_WITH_DICT[input_pos..], [0u8; 0x100].as_mut_slice()) {
+        InflateResult::NeedDictionary(dictid) => assert_eq!(dictid, JSON_DICTIONARY_ID),
+        _ => panic!("expected NeedDictionary")
+    }
+
+    assert_eq!(state.set_dictionary(JSON_DICTIONARY), ZERR::Ok);
+    let output = inflate_small(&mut state, &JSON_ZLIB_WITH_DICT[input_pos..]);
+    assert_eq!(output.as_slice(), JSON_MESSAGE);
+}
+
+#[test]
+fn test_inflate_zlib_wrong_dictionary() {
+    let mut state = Inflater::new_zlib();
+    let input_pos = inflate_until_need_dictionary(&mut state, &JSON_ZLIB_WITH_DICT);
+
+    assert_eq!(state.set_dictionary(b"not the right dictionary"), ZERR::DataError);
+
+    // The stream still needs the right dictionary.
+    match state.inflate(None, &JSON_ZLIB_WITH_DICT[input_pos..], [0u8; 0x100].as_mut_slice()) {
+        InflateResult::NeedDictionary(dictid) => assert_eq!(dictid, JSON_DICTIONARY_ID),
+        _ => panic!("expected NeedDictionary")
+    }
+    assert_eq!(state.set_dictionary(JSON_DICTIONARY), ZERR::Ok);
+    let output = inflate_small(&mut state, &JSON_ZLIB_WITH_DICT[input_pos..]);
+    assert_eq!(output.as_slice(), JSON_MESSAGE);
+}
+
+#[test]
+fn test_inflate_set_dictionary_not_expected() {
+    // A zlib or gzip stream only accepts a dictionary when the stream asks for one.
+    let mut state = Inflater::new_zlib();
+    assert_eq!(state.set_dictionary(JSON_DICTIONARY), ZERR::StreamError);
+    let mut state = Inflater::new_gzip();
+    assert_eq!(state.set_dictionary(JSON_DICTIONARY), ZERR::StreamError);
+}
+
+#[test]
+fn test_inflate_raw_with_dictionary() {
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(state.set_dictionary(JSON_DICTIONARY), ZERR::Ok);
+    let output = inflate_small(&mut state, &JSON_RAW_WITH_DICT);
+    assert_eq!(output.as_slice(), JSON_MESSAGE);
+}
+
+#[test]
+fn test_inflate_reader_with_dictionary() {
+    let mut reader = InflateReader::new_with_inflater(0x1000, Inflater::new_zlib(),
+        io::Cursor::new(&JSON_ZLIB_WITH_DICT));
+
+    // The reader reports an error until the dictionary is provided.
+    assert!(read_all(&mut reader).is_err());
+    assert_eq!(reader.set_dictionary(JSON_DICTIONARY), ZERR::Ok);
+    let output = read_all(&mut reader).unwrap();
+    assert_eq!(output.as_slice(), JSON_MESSAGE);
+}
+
+// A GZIP stream whose header has every optional field: FTEXT, FHCRC, FEXTRA, FNAME and FCOMMENT.
+// mtime = 1420070400, xflags = 2, os = 3 (Unix), extra = "AB" subfield with 4 bytes of data.
+static GZIP_FULL_HEADER: [u8; 72] = [
+    0x1f, 0x8b, 0x08, 0x1f, 0x00, 0x8e, 0xa4, 0x54, 0x02, 0x03, 0x08, 0x00,
+    0x41, 0x42, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04, 0x68, 0x65, 0x6c, 0x6c,
+    0x6f, 0x2e, 0x74, 0x78, 0x74, 0x00, 0x61, 0x20, 0x63, 0x6f, 0x6d, 0x6d,
+    0x65, 0x6e, 0x74, 0x00, 0xda, 0x4c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xd7,
+    0x51, 0xc8, 0x40, 0xa1, 0xd2, 0xab, 0x32, 0x0b, 0x80, 0xec, 0xc4, 0x94,
+    0xd4, 0x22, 0x2e, 0x00, 0x86, 0x5d, 0x8b, 0x1e, 0x21, 0x00, 0x00, 0x00,
+];
+
+#[test]
+fn test_gzip_header_all_fields() {
+    let mut state = Inflater::new_gzip();
+    assert_eq!(state.capture_header(), ZERR::Ok);
+    assert!(state.header().is_none());
+
+    // Feed the stream one byte at a time, so that the variable-length fields are split
+    // across many calls to inflate().
+    let mut output = [0u8; 0x100];
+    let mut input_pos: usize = 0;
+    let mut output_pos: usize = 0;
+    loop {
+        let input_end = if input_pos < GZIP_FULL_HEADER.len() { input_pos + 1 } else { input_pos };
+        match state.inflate(None, &GZIP_FULL_HEADER[input_pos..input_end], &mut output[output_pos..]) {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output_pos += output_bytes_written;
+                if input_pos < 42 {
+                    assert!(state.header().is_none());
+                }
+            }
+            InflateResult::Eof(_) => break,
+            _ => panic!("unexpected inflate result")
+        }
+    }
+    assert_eq!(&output[..output_pos], b"hello, hello, hello, gzip header\n");
+
+    let header = state.header().expect("header should be complete");
+    assert!(header.done);
+    assert!(header.text);
+    assert!(header.hcrc);
+    assert_eq!(header.time, 1420070400);
+    assert_eq!(header.xflags, 2);
+    assert_eq!(header.os, 3);
+    assert_eq!(header.extra_len, 8);
+    assert_eq!(header.extra, Some(b"AB\x04\x00\x01\x02\x03\x04".to_vec()));
+    assert_eq!(header.name, Some(b"hello.txt".to_vec()));
+    assert_eq!(header.comment, Some(b"a comment".to_vec()));
+}
+
+#[test]
+fn test_gzip_header_crc_mismatch() {
+    // The header CRC is the two bytes before the compressed data, at offset 40.
+    let mut input = GZIP_FULL_HEADER.to_vec();
+    input[41] ^= 1;
+    let mut state = Inflater::new_gzip();
+    let mut output = [0u8; 0x100];
+    match state.inflate(None, input.as_slice(), &mut output) {
+        InflateResult::Error(err, offset) => {
+            assert_eq!(err, InflateError::HeaderCrcMismatch);
+            assert_eq!(offset, 40);
+        }
+        other => panic!("expected a header crc mismatch, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_gzip_header_hcrc_verified() {
+    // The header is only reported, with hcrc set, once the header CRC has been checked.
+    let mut output = [0u8; 0x100];
+    let mut state = Inflater::new_gzip();
+    assert_eq!(state.capture_header(), ZERR::Ok);
+    match state.inflate(None, &GZIP_FULL_HEADER[..41], &mut output) {
+        InflateResult::Decoded(41, 0) => {}
+        other => panic!("unexpected inflate result {:?}", other)
+    }
+    assert!(state.header().is_none());
+
+    let mut input = GZIP_FULL_HEADER.to_vec();
+    input[40] ^= 1;
+    let mut state = Inflater::new_gzip();
+    assert_eq!(state.capture_header(), ZERR::Ok);
+    match state.inflate(None, input.as_slice(), &mut output) {
+        InflateResult::Error(InflateError::HeaderCrcMismatch, 40) => {}
+        other => panic!("expected a header crc mismatch, got {:?}", other)
+    }
+    assert!(state.header().is_none());
+
+    let mut state = Inflater::new_gzip();
+    assert_eq!(state.capture_header(), ZERR::Ok);
+    match state.inflate(None, &GZIP_FULL_HEADER[..42], &mut output) {
+        InflateResult::Decoded(42, 0) => {}
+        other => panic!("unexpected inflate result {:?}", other)
+    }
+    assert!(state.header().expect("header should be complete").hcrc);
+}
+
+#[test]
+fn test_gzip_header_reader() {
+    let input_file = io::BufReader::new(unwrap_or_warn(File::open("tests/hamlet.tar.gz")));
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, input_file);
+    assert!(reader.header().is_none());
+
+    let mut buf = [0u8; 0x100];
+    reader.read_exact(&mut buf).unwrap();
+
+    let header = reader.header().expect("header should be complete");
+    assert!(!header.text);
+    assert!(!header.hcrc);
+    assert_eq!(header.time, 0x548f8514);
+    assert_eq!(header.os, 11);
+    assert_eq!(header.extra, None);
+    assert_eq!(header.name, Some(b"hamlet.tar".to_vec()));
+    assert_eq!(header.comment, None);
+}
+
+#[test]
+fn test_gzip_header_not_requested() {
+    // Headers are only saved on request, and only GZIP streams have them.
+    let mut state = Inflater::new_gzip();
+    let mut output = [0u8; 0x100];
+    let mut input_pos: usize = 0;
+    loop {
+        match state.inflate(None, &GZIP_FULL_HEADER[input_pos..], output.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, _) => input_pos += input_bytes_read,
+            InflateResult::Eof(_) => break,
+            _ => panic!("unexpected inflate result")
+        }
+    }
+    assert!(state.header().is_none());
+
+    let mut state = Inflater::new_zlib();
+    assert_eq!(state.capture_header(), ZERR::StreamError);
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(state.capture_header(), ZERR::StreamError);
+}
+
+// Compresses each chunk into a raw DEFLATE stream, followed by the given flush.  Returns the
+// stream, and the offset in the stream just past each flush.
+fn deflate_chunks(chunks: &[&[u8]], flush: Flush) -> (Vec<u8>, Vec<usize>) {
+    let mut deflater = Deflater::new_raw(6, WINDOW_BITS_DEFAULT);
+    let mut output: Vec<u8> = vec![0u8; 0x10000];
+    let mut output_pos: usize = 0;
+    let mut flush_points: Vec<usize> = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let f = if i == chunks.len() - 1 { Flush::Finish } else { flush };
+        match deflater.deflate(f, chunk, &mut output[output_pos..]) {
+            DeflateResult::Encoded(in_bytes, out_bytes) if f != Flush::Finish => {
+                assert_eq!(in_bytes, chunk.len());
+                output_pos += out_bytes;
+            }
+            DeflateResult::Finished(in_bytes, out_bytes) if f == Flush::Finish => {
+                assert_eq!(in_bytes, chunk.len());
+                output_pos += out_bytes;
+            }
+            result => panic!("unexpected deflate result: {:?}", result)
+        }
+        flush_points.push(output_pos);
+    }
+    output.truncate(output_pos);
+    (output, flush_points)
+}
+
+fn sync_test_chunks() -> Vec<Vec<u8>> {
+    (0..4usize).map(|i| {
+        let mut chunk = Vec::new();
+        for j in 0..200usize {
+            chunk.extend_from_slice(format!("chunk {} line {}: the quick brown fox jumps over the lazy dog\n", i, j).as_bytes());
+        }
+        chunk
+    }).collect()
+}
+
+#[test]
+fn test_inflate_sync_lost_start() {
+    let chunks = sync_test_chunks();
+    let chunk_refs: Vec<&[u8]> = chunks.iter().map(|c| c.as_slice()).collect();
+    let (stream, flush_points) = deflate_chunks(chunk_refs.as_slice(), Flush::FullFlush);
+
+    // Start decoding partway through the first chunk, as if the start of the stream was lost.
+    let start = flush_points[0] / 2;
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    let skipped = match state.sync(&stream[start..]) {
+        SyncResult::Found(n) => n,
+        SyncResult::NotFound(n) => panic!("flush point not found, skipped {}", n)
+    };
+    assert_eq!(start + skipped, flush_points[0]);
+
+    let output = inflate_small_into(&mut state, &stream[start + skipped..], 0x10000);
+    let mut expected = Vec::new();
+    for chunk in chunks[1..].iter() {
+        expected.extend_from_slice(chunk.as_slice());
+    }
+    assert!(output == expected);
+}
+
+#[test]
+fn test_inflate_sync_split_input() {
+    let chunks = sync_test_chunks();
+    let chunk_refs: Vec<&[u8]> = chunks.iter().map(|c| c.as_slice()).collect();
+    let (stream, flush_points) = deflate_chunks(chunk_refs.as_slice(), Flush::FullFlush);
+
+    // Feed sync() one byte at a time, starting partway through the second chunk.  The
+    // 00 00 FF FF pattern is split across calls.
+    let mut pos = (flush_points[0] + flush_points[1]) / 2;
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    loop {
+        match state.sync(&stream[pos..pos + 1]) {
+            SyncResult::NotFound(n) => {
+                assert_eq!(n, 1);
+                pos += 1;
+            }
+            SyncResult::Found(n) => {
+                assert_eq!(n, 1);
+                pos += 1;
+                break;
+            }
+        }
+    }
+    assert_eq!(pos, flush_points[1]);
+
+    // inflate() does not decode anything until a flush point is found.
+    let mut state2 = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(state2.sync(&[]), SyncResult::NotFound(0));
+    match state2.inflate(None, &stream[flush_points[1]..], [0u8; 0x100].as_mut_slice()) {
+        InflateResult::Error(InflateError::NotSynchronized, _) => (),
+        _ => panic!("expected NotSynchronized")
+    }
+
+    let output = inflate_small_into(&mut state, &stream[pos..], 0x10000);
+    let mut expected = Vec::new();
+    for chunk in chunks[2..].iter() {
+        expected.extend_from_slice(chunk.as_slice());
+    }
+    assert!(output == expected);
+}
+
+#[test]
+fn test_inflate_sync_point() {
+    let chunks: [&[u8]; 2] = [b"before the sync flush", b"after the sync flush"];
+    let (stream, flush_points) = deflate_chunks(&chunks, Flush::SyncFlush);
+
+    // Stop just before the length bytes of the empty stored block written by the flush.
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert!(!state.sync_point());
+    let output = inflate_small_into(&mut state, &stream[..flush_points[0] - 4], 0x100);
+    assert_eq!(output.as_slice(), chunks[0]);
+    assert!(state.sync_point());
+}
+
+// Inflates 'input' from 'input_pos' until the end of the stream, using small buffers.
+fn inflate_rest(state: &mut Inflater, input: &[u8], mut input_pos: usize) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut buf = [0u8; 0x40];
+    loop {
+        let input_end = std::cmp::min(input_pos + 0x40, input.len());
+        match state.inflate(None, &input[input_pos..input_end], buf.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&buf[..output_bytes_written]);
+            }
+            InflateResult::Eof(_) => break,
+            _ => panic!("unexpected inflate result")
+        }
+    }
+    output
+}
+
+#[test]
+fn test_inflate_clone_checkpoints() {
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+
+    // Decode with small buffers, taking checkpoints at several points in the stream.  Each
+    // checkpoint is (copy of the decoder, input position, output length).
+    let mut state = Inflater::new_gzip();
+    let mut checkpoints: Vec<(Inflater, usize, usize)> = Vec::new();
+    let mut output = Vec::new();
+    let mut buf = [0u8; 0x40];
+    let mut input_pos: usize = 0;
+    let mut calls: usize = 0;
+    loop {
+        if calls == 1 || calls == 100 || calls == 1000 || calls == 3000 {
+            checkpoints.push((state.clone(), input_pos, output.len()));
+        }
+        calls += 1;
+        let input_end = std::cmp::min(input_pos + 0x40, input.len());
+        match state.inflate(None, &input[input_pos..input_end], buf.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&buf[..output_bytes_written]);
+            }
+            InflateResult::Eof(_) => break,
+            _ => panic!("unexpected inflate result")
+        }
+    }
+    assert!(output == expected);
+    assert_eq!(checkpoints.len(), 4);
+
+    // Each copy resumes from its checkpoint, even though the original has since run to the
+    // end of the stream.
+    for &mut (ref mut copy, checkpoint_input_pos, checkpoint_output_len) in checkpoints.iter_mut() {
+        let rest = inflate_rest(copy, input.as_slice(), checkpoint_input_pos);
+        assert!(rest.as_slice() == &expected[checkpoint_output_len..]);
+    }
+}
+
+#[test]
+fn test_inflate_block_position() {
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+
+    let mut state = Inflater::new_gzip();
+    assert_eq!(state.mark(), -1 << 16);
+
+    // With Flush::Block, inflate() returns at the start of each block.  At each
+    // block boundary, no code is partially decoded and nothing remains to be copied.
+    let mut output: Vec<u8> = Vec::new();
+    let mut buf: Vec<u8> = vec![0u8; 0x10000];
+    let mut input_pos: usize = 0;
+    let mut boundaries: Vec<(usize, bool)> = Vec::new();
+    loop {
+        let at_boundary = match state.inflate(Some(Flush::Block), &input[input_pos..], buf.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&buf[..output_bytes_written]);
+                false
+            }
+            InflateResult::Boundary(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&buf[..output_bytes_written]);
+                true
+            }
+            InflateResult::Eof(_) => break,
+            _ => panic!("unexpected inflate result")
+        };
+        let position = state.block_position();
+        assert!(!position.after_block_header);
+        assert_eq!(position.at_block_boundary, at_boundary);
+        if position.at_block_boundary {
+            assert!(position.bits < 8);
+            assert_eq!(state.mark(), -1 << 16);
+            boundaries.push((output.len(), position.last_block));
+        }
+    }
+    assert!(output == expected);
+    assert!(state.block_position().last_block);
+
+    // The decoder also stops after the end of the last block, before the trailer.
+    assert!(boundaries.len() > 2);
+    let (final_output_len, final_last_block) = boundaries.pop().unwrap();
+    assert_eq!(final_output_len, expected.len());
+    assert!(final_last_block);
+    assert!(boundaries.iter().all(|&(_, last_block)| !last_block));
+
+    // With Flush::Trees, inflate() also returns just after each block header.
+    let mut state = Inflater::new_gzip();
+    let mut input_pos: usize = 0;
+    let mut headers: usize = 0;
+    loop {
+        let at_boundary = match state.inflate(Some(Flush::Trees), &input[input_pos..], buf.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, _) => {
+                input_pos += input_bytes_read;
+                false
+            }
+            InflateResult::Boundary(input_bytes_read, _) => {
+                input_pos += input_bytes_read;
+                true
+            }
+            InflateResult::Eof(_) => break,
+            _ => panic!("unexpected inflate result")
+        };
+        let position = state.block_position();
+        assert_eq!(position.at_block_boundary || position.after_block_header, at_boundary);
+        if position.after_block_header {
+            assert!(!position.at_block_boundary);
+            headers += 1;
+        }
+    }
+    assert_eq!(headers, boundaries.len());
+}
+
+#[test]
+fn test_inflate_block_position_initial() {
+    let state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(state.block_position(), BlockPosition {
+        at_block_boundary: false,
+        after_block_header: false,
+        last_block: false,
+        bits: 0,
+    });
+}
+
+// Inflates 'input' with the given flush, passing at most 'input_chunk' bytes to each call.
+// Returns the output, and the (input position, output length, block position) at each
+// InflateResult::Boundary.
+fn inflate_boundaries(state: &mut Inflater, flush: Flush, input: &[u8], input_chunk: usize)
+    -> (Vec<u8>, Vec<(usize, usize, BlockPosition)>) {
+    let mut output: Vec<u8> = Vec::new();
+    let mut boundaries = Vec::new();
+    let mut buf = [0u8; 0x1000];
+    let mut input_pos: usize = 0;
+    loop {
+        let input_end = std::cmp::min(input_pos + input_chunk, input.len());
+        match state.inflate(Some(flush), &input[input_pos..input_end], buf.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&buf[..output_bytes_written]);
+            }
+            InflateResult::Boundary(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&buf[..output_bytes_written]);
+                boundaries.push((input_pos, output.len(), state.block_position()));
+            }
+            InflateResult::Eof(_) => break,
+            _ => panic!("unexpected inflate result")
+        }
+    }
+    assert_eq!(input_pos, input.len());
+    (output, boundaries)
+}
+
+#[test]
+fn test_inflate_flush_block_raw() {
+    // Each sync flush ends the current block and adds an empty stored block, so this stream
+    // has five blocks: data, stored, data, stored, data.
+    let chunks: [&[u8]; 3] = [
+        b"first chunk, first chunk, first chunk",
+        b"second chunk",
+        b"third and last chunk, third and last chunk"];
+    let (stream, flush_points) = deflate_chunks(&chunks, Flush::SyncFlush);
+    let len1 = chunks[0].len();
+    let len2 = len1 + chunks[1].len();
+    let len3 = len2 + chunks[2].len();
+
+    // A raw stream starts at a block boundary, so the first stop is at the end of the first
+    // block.  The last stop is at the end of the last block.
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    let (output, boundaries) = inflate_boundaries(&mut state, Flush::Block, stream.as_slice(), 0x1000);
+    assert_eq!(output.len(), len3);
+    let stops: Vec<usize> = boundaries.iter().map(|&(_, output_len, _)| output_len).collect();
+    assert_eq!(stops, vec![len1, len1, len2, len2, len3]);
+    for (i, &(input_pos, _, position)) in boundaries.iter().enumerate() {
+        assert!(position.at_block_boundary);
+        assert_eq!(position.last_block, i == 4);
+        // Each stored block ends on a byte boundary, at a flush point.
+        if i == 1 || i == 3 {
+            assert_eq!(input_pos, flush_points[i / 2]);
+            assert_eq!(position.bits, 0);
+        }
+    }
+
+    // The decoder stops at exactly the same points if the input arrives one byte at a time.
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    let (output_tiny, boundaries_tiny) = inflate_boundaries(&mut state, Flush::Block, stream.as_slice(), 1);
+    assert!(output_tiny == output);
+    assert!(boundaries_tiny == boundaries);
+}
+
+#[test]
+fn test_inflate_flush_trees_raw() {
+    let chunks: [&[u8]; 3] = [
+        b"first chunk, first chunk, first chunk",
+        b"second chunk",
+        b"third and last chunk, third and last chunk"];
+    let (stream, _) = deflate_chunks(&chunks, Flush::SyncFlush);
+
+    // With Flush::Trees, the decoder also stops after each block header, before any data
+    // from the block has been written.
+    for &input_chunk in [0x1000usize, 1].iter() {
+        let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+        let (output, boundaries) = inflate_boundaries(&mut state, Flush::Trees, stream.as_slice(), input_chunk);
+        assert!(output == chunks.concat());
+        assert_eq!(boundaries.len(), 10);
+        for (i, &(_, output_len, position)) in boundaries.iter().enumerate() {
+            if i % 2 == 0 {
+                assert!(position.after_block_header);
+                assert!(!position.at_block_boundary);
+            }
+            else {
+                assert!(position.at_block_boundary);
+                assert!(!position.after_block_header);
+            }
+            assert_eq!(position.last_block, i >= 8);
+            // nothing is written between the end of a block and the next block header
+            if i > 0 && i % 2 == 0 {
+                assert_eq!(output_len, boundaries[i - 1].1);
+            }
+        }
+    }
+}
+
+// Inflates 'input' in one call per result, and returns the error that stops decoding.
+fn inflate_until_error(state: &mut Inflater, input: &[u8]) -> (InflateError, u64) {
+    let mut output: Vec<u8> = vec![0u8; 0x10000];
+    let mut input_pos: usize = 0;
+    loop {
+        match state.inflate(None, &input[input_pos..], output.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, _) => input_pos += input_bytes_read,
+            InflateResult::Error(err, offset) => return (err, offset),
+            _ => panic!("expected inflate to fail")
+        }
+    }
+}
+
+#[test]
+fn test_inflate_error_headers() {
+    // zlib header with a bad FCHECK
+    let mut state = Inflater::new_zlib();
+    assert_eq!(inflate_until_error(&mut state, &[0x78, 0x9d, 0x03, 0x00]), (InflateError::IncorrectHeaderCheck, 0));
+
+    // zlib header with a method other than deflate
+    let mut state = Inflater::new_zlib();
+    assert_eq!(inflate_until_error(&mut state, &[0x77, 0x85, 0x03, 0x00]), (InflateError::UnknownCompressionMethod, 0));
+
+    // gzip header with reserved flags set
+    let mut state = Inflater::new_gzip();
+    assert_eq!(inflate_until_error(&mut state, &[0x1f, 0x8b, 0x08, 0xe0, 0, 0, 0, 0, 0, 3]),
+        (InflateError::UnknownHeaderFlags, 2));
+
+    // Once decoding has failed, inflate() keeps reporting the same error.
+    match state.inflate(None, &[0x00], [0u8; 0x10].as_mut_slice()) {
+        InflateResult::Error(err, offset) => assert_eq!((err, offset), (InflateError::UnknownHeaderFlags, 2)),
+        _ => panic!("expected an error")
+    }
+}
+
+#[test]
+fn test_inflate_error_blocks() {
+    // BFINAL = 1, BTYPE = 3 (reserved)
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(inflate_until_error(&mut state, &[0x07]), (InflateError::InvalidBlockType, 0));
+
+    // stored block whose NLEN is not the complement of LEN
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(inflate_until_error(&mut state, &[0x01, 0x05, 0x00, 0x00, 0x00, 0x41]),
+        (InflateError::InvalidStoredBlockLengths, 1));
+
+    // fixed block that starts with a match, although there is no data in the window yet
+    let mut state = Inflater::new_inflate(WINDOW_BITS_DEFAULT);
+    assert_eq!(inflate_until_error(&mut state, &[0x03, 0x02, 0x00, 0x00]).0,
+        InflateError::InvalidDistanceTooFarBack);
+}
+
+#[test]
+fn test_inflate_error_corrupt_data() {
+    // Damage the compressed data in the first block.  The error is found in inflate_fast().
+    let mut input = fs::read("tests/hamlet.tar.gz").unwrap();
+    for b in input[1000..1016].iter_mut() {
+        *b = 0xff;
+    }
+    let mut state = Inflater::new_gzip();
+    let (err, offset) = inflate_until_error(&mut state, input.as_slice());
+    assert_eq!(err, InflateError::InvalidDistanceTooFarBack);
+    assert!((1000..1100).contains(&offset), "offset = {}", offset);
+
+    // InflateReader reports the message and the offset.
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(input.as_slice()));
+    let err = read_all(&mut reader).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), format!("invalid distance too far back: at input offset {}", offset));
+}
+
+// Compresses 'data' into a single GZIP member.
+fn gzip_member(data: &[u8]) -> Vec<u8> {
+    let mut writer = DeflateWriter::new_gzip(6, 0x1000, Vec::new());
+    writer.write_all(data).unwrap();
+    writer.finish().unwrap()
+}
+
+// Three members: hamlet.tar.gz, a short member, and an empty member.
+fn multi_member_input() -> (Vec<u8>, Vec<u8>) {
+    let mut input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let mut expected = fs::read("tests/hamlet.tar").unwrap();
+    input.extend_from_slice(gzip_member(b"second member\n").as_slice());
+    expected.extend_from_slice(b"second member\n");
+    input.extend_from_slice(gzip_member(b"").as_slice());
+    (input, expected)
+}
+
+#[test]
+fn test_inflate_reader_multi_member() {
+    let (input, expected) = multi_member_input();
+
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(input.as_slice()));
+    reader.set_multi_member(true);
+    let output = read_all(&mut reader).unwrap();
+    assert!(output == expected);
+
+    // By default, only the first member is decoded.
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(input.as_slice()));
+    let output = read_all(&mut reader).unwrap();
+    assert_eq!(output.len(), 195072);
+}
+
+#[test]
+fn test_inflate_reader_multi_member_header() {
+    // The header is that of the member being decoded.
+    let (input, _) = multi_member_input();
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(input.as_slice()));
+    reader.set_multi_member(true);
+    let mut buf = [0u8; 0x100];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(reader.header().unwrap().name, Some(b"hamlet.tar".to_vec()));
+    read_all(&mut reader).unwrap();
+    assert_eq!(reader.header().unwrap().name, None);
+}
+
+#[test]
+fn test_inflate_reader_zero_padding() {
+    let (mut input, expected) = multi_member_input();
+    let padding = 0x2000 - input.len() % 0x2000;
+    input.resize(input.len() + padding, 0);
+
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(input.as_slice()));
+    reader.set_multi_member(true);
+    reader.set_ignore_zero_padding(true);
+    let output = read_all(&mut reader).unwrap();
+    assert!(output == expected);
+
+    // Without the option, the padding is not a valid GZIP header.
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(input.as_slice()));
+    reader.set_multi_member(true);
+    let err = read_all(&mut reader).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.to_string(), format!("incorrect header check: at input offset {}", input.len() - padding));
+
+    // Anything other than zeros after the padding is an error.
+    let mut garbage = input.clone();
+    garbage.push(1);
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(garbage.as_slice()));
+    reader.set_multi_member(true);
+    reader.set_ignore_zero_padding(true);
+    let err = read_all(&mut reader).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.to_string(), format!("trailing data after zero padding: at input offset {}", input.len()));
+}
+
+#[test]
+fn test_buf_inflate_reader() {
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+    let mut input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let stream_len = input.len();
+    input.extend_from_slice(b"data after the stream");
+
+    // The reader decodes from the BufReader's buffer, and leaves the rest of the input there.
+    for &capacity in [1usize, 7, 0x1000, 0x100000].iter() {
+        let src = io::BufReader::with_capacity(capacity, io::Cursor::new(input.as_slice()));
+        let mut reader = BufInflateReader::new_gzip(src);
+        let output = read_all(&mut reader).unwrap();
+        assert!(output == expected);
+        assert_eq!(reader.header().unwrap().name, Some(b"hamlet.tar".to_vec()));
+        assert_eq!(reader.total_in(), stream_len as u64);
+        let rest = read_all(&mut reader.into_inner()).unwrap();
+        assert_eq!(rest.as_slice(), b"data after the stream");
+    }
+}
+
+#[test]
+fn test_buf_inflate_reader_multi_member() {
+    let (mut input, expected) = multi_member_input();
+    let padding = 0x2000 - input.len() % 0x2000;
+    input.resize(input.len() + padding, 0);
+
+    for &capacity in [7usize, 0x1000].iter() {
+        let src = io::BufReader::with_capacity(capacity, io::Cursor::new(input.as_slice()));
+        let mut reader = BufInflateReader::new_gzip(src);
+        reader.set_multi_member(true);
+        reader.set_ignore_zero_padding(true);
+        let output = read_all(&mut reader).unwrap();
+        assert!(output == expected);
+        assert_eq!(reader.total_in(), input.len() as u64);
+    }
+
+    // Anything other than zeros after the padding is an error.
+    input.push(1);
+    let src = io::BufReader::with_capacity(7, io::Cursor::new(input.as_slice()));
+    let mut reader = BufInflateReader::new_gzip(src);
+    reader.set_multi_member(true);
+    reader.set_ignore_zero_padding(true);
+    let err = read_all(&mut reader).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.to_string(), format!("trailing data after zero padding: at input offset {}", input.len() - 1));
+}
+
+#[test]
+fn test_buf_inflate_reader_error_trailer() {
+    let mut input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let n = input.len();
+    input[n - 5] ^= 1;
+
+    let mut reader = BufInflateReader::new_gzip(io::Cursor::new(input.as_slice()));
+    let err = read_all(&mut reader).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with(&format!("incorrect data check: at input offset {}: ", n - 8)));
+}
+
+#[test]
+fn test_inflate_reader_remainder() {
+    // Some data after each stream, longer than the reader's input buffer.
+    let hamlet = fs::read("tests/hamlet.tar").unwrap();
+    let trailing = &hamlet[..0x1800];
+    let streams = [
+        write_deflate_stream(DeflateWriter::new_zlib(6, 0x1000, Vec::new()), hamlet.as_slice()),
+        write_deflate_stream(DeflateWriter::new_gzip(6, 0x1000, Vec::new()), hamlet.as_slice()),
+        write_deflate_stream(DeflateWriter::new_raw(6, 0x1000, Vec::new()), hamlet.as_slice()),
+        write_deflate_stream(DeflateWriter::new_raw(6, 0x1000, Vec::new()), b""),
+    ];
+    let inflaters = [Inflater::new_zlib(), Inflater::new_gzip(),
+        Inflater::new_raw(WINDOW_BITS_DEFAULT), Inflater::new_raw(WINDOW_BITS_DEFAULT)];
+    for (stream, inflater) in streams.iter().zip(inflaters) {
+        let mut input = stream.clone();
+        input.extend_from_slice(trailing);
+
+        let mut reader = InflateReader::new_with_inflater(INBUF_SIZE, inflater, io::Cursor::new(input.as_slice()));
+        let output = read_all(&mut reader).unwrap();
+        assert_eq!(output.len(), if stream.len() > 2 { hamlet.len() } else { 0 });
+        let (mut src, mut remainder, total_in) = reader.into_inner_with_remainder();
+        assert_eq!(total_in, stream.len() as u64);
+        assert!(!remainder.is_empty());
+        src.read_to_end(&mut remainder).unwrap();
+        assert!(remainder.as_slice() == trailing);
+    }
+}
+
+#[test]
+fn test_inflate_unused_input() {
+    let mut state = Inflater::new_raw(WINDOW_BITS_DEFAULT);
+    assert_eq!(state.unused_input(), Vec::<u8>::new());
+    // The low bits are the rest of a partly used byte, and are skipped.
+    state.prime(4, 0x5);
+    state.prime(16, 0xabcd);
+    assert_eq!(state.unused_input(), vec![0xcd, 0xab]);
+}
+
+fn write_deflate_stream(mut writer: DeflateWriter<Vec<u8>>, data: &[u8]) -> Vec<u8> {
+    writer.write_all(data).unwrap();
+    writer.finish().unwrap()
+}
+
+// Reads from 'reader' until it fails, and returns the data that it returned first.
+fn read_until_error<R:Read>(reader: &mut R) -> (Vec<u8>, io::Error) {
+    let mut output: Vec<u8> = Vec::new();
+    loop {
+        match push(reader, 0x1000, &mut output) {
+            Ok(0) => panic!("expected read() to fail, after {} bytes", output.len()),
+            Ok(_) => (),
+            Err(err) => return (output, err)
+        }
+    }
+}
+
+#[test]
+fn test_inflate_reader_truncated() {
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    for &len in [0usize, 1, 10, 20, 21, 1000, 40000, input.len() - 8, input.len() - 4, input.len() - 1].iter() {
+        let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(&input[..len]));
+        let (output, err) = read_until_error(&mut reader);
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), format!("unexpected end of compressed stream: at input offset {}", len));
+        assert!(output == expected[..output.len()]);
+        if len >= input.len() - 8 {
+            assert_eq!(output.len(), expected.len());
+        }
+
+        let mut reader = BufInflateReader::new_gzip(io::BufReader::with_capacity(7, io::Cursor::new(&input[..len])));
+        let (output, err) = read_until_error(&mut reader);
+        assert_eq!(err.to_string(), format!("unexpected end of compressed stream: at input offset {}", len));
+        assert!(output == expected[..output.len()]);
+    }
+}
+
+#[test]
+fn test_inflate_reader_truncated_every_offset() {
+    // Decoding the whole file for every offset would be slow, so each reader starts from a
+    // copy of an Inflater that has already decoded the input up to the last multiple of STEP
+    // bytes before the end of the truncated input.
+    const STEP: usize = 256;
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+
+    let mut base = Inflater::new_gzip();
+    let mut base_in: usize = 0;
+    let mut base_out: usize = 0;
+    let mut scratch = vec![0u8; 0x10000];
+    for len in 0..input.len() + 1 {
+        while base_in + STEP <= len {
+            let end = base_in + STEP;
+            while base_in < end {
+                match base.inflate(None, &input[base_in..end], scratch.as_mut_slice()) {
+                    InflateResult::Decoded(in_bytes, out_bytes) => {
+                        base_in += in_bytes;
+                        base_out += out_bytes;
+                    }
+                    result => panic!("unexpected result at input offset {}: {:?}", base_in, result)
+                }
+            }
+        }
+
+        let mut reader = InflateReader::new_with_inflater(INBUF_SIZE, base.clone(), io::Cursor::new(&input[base_in..len]));
+        if len == input.len() {
+            let output = read_all(&mut reader).unwrap();
+            assert!(output == expected[base_out..]);
+            break;
+        }
+        let (output, err) = read_until_error(&mut reader);
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "truncated at {}: {}", len, err);
+        assert_eq!(err.to_string(), format!("unexpected end of compressed stream: at input offset {}", len - base_in));
+        assert!(output == expected[base_out..base_out + output.len()], "wrong data, truncated at {}", len);
+    }
+}
+
+#[test]
+fn test_inflate_writer() {
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    for &chunk_size in [1usize, 7, 0x1000, input.len()].iter() {
+        let mut writer = InflateWriter::new_gzip(0x1000, Vec::new());
+        for chunk in input.chunks(chunk_size) {
+            writer.write_all(chunk).unwrap();
+        }
+        assert_eq!(writer.header().unwrap().name, Some(b"hamlet.tar".to_vec()));
+        assert_eq!(writer.total_in(), input.len() as u64);
+        let output = writer.finish().unwrap();
+        assert!(output == expected);
+    }
+
+    // zlib and raw DEFLATE streams
+    let zlib_stream = write_deflate_stream(DeflateWriter::new_zlib(6, 0x1000, Vec::new()), expected.as_slice());
+    let mut writer = InflateWriter::new_zlib(0x1000, Vec::new());
+    writer.write_all(zlib_stream.as_slice()).unwrap();
+    assert!(writer.finish().unwrap() == expected);
+
+    let raw_stream = write_deflate_stream(DeflateWriter::new_raw(6, 0x1000, Vec::new()), expected.as_slice());
+    let mut writer = InflateWriter::new_raw(0x1000, Vec::new());
+    writer.write_all(raw_stream.as_slice()).unwrap();
+    assert!(writer.finish().unwrap() == expected);
+}
+
+#[test]
+fn test_inflate_writer_truncated() {
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    for &len in [0usize, 10, 1000, input.len() - 8, input.len() - 1].iter() {
+        let mut writer = InflateWriter::new_gzip(0x1000, Vec::new());
+        writer.write_all(&input[..len]).unwrap();
+        let err = writer.finish().expect_err("expected finish() to fail");
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), format!("unexpected end of compressed stream: at input offset {}", len));
+    }
+}
+
+#[test]
+fn test_inflate_writer_trailing_data() {
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+    let mut input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let stream_len = input.len();
+    input.extend_from_slice(b"more data");
+
+    // write() stops at the end of the stream, and then rejects more data.
+    let mut writer = InflateWriter::new_gzip(0x1000, Vec::new());
+    assert_eq!(writer.write(input.as_slice()).unwrap(), stream_len);
+    let err = writer.write(&input[stream_len..]).expect_err("expected write() to fail");
+    assert_eq!(err.to_string(), format!("data after the end of the compressed stream: at input offset {}", stream_len));
+    assert_eq!(writer.write(&[]).unwrap(), 0);
+    assert!(writer.finish().unwrap() == expected);
+}
+
+#[test]
+fn test_inflate_writer_error() {
+    let mut input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let n = input.len();
+    input[n - 1] ^= 0x80;
+
+    let mut writer = InflateWriter::new_gzip(0x1000, Vec::new());
+    let err = writer.write_all(input.as_slice()).expect_err("expected write() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), format!("incorrect length check: at input offset {}: expected {} bytes, decoded 195072 bytes",
+        n - 4, 195072 + 0x80000000u32));
+}
+
+#[test]
+fn test_inflate_writer_dictionary() {
+    let mut writer = InflateWriter::new_zlib(0x1000, Vec::new());
+
+    // The first write() uses only the zlib header and the dictionary ID.  The next one fails.
+    let n = writer.write(&JSON_ZLIB_WITH_DICT).unwrap();
+    assert_eq!(n, 6);
+    let err = writer.write(&JSON_ZLIB_WITH_DICT[n..]).expect_err("expected write() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(writer.total_in(), 6);
+
+    // Set the dictionary, and write the rest of the data again.
+    assert_eq!(writer.set_dictionary(JSON_DICTIONARY), ZERR::Ok);
+    writer.write_all(&JSON_ZLIB_WITH_DICT[n..]).unwrap();
+    assert_eq!(writer.finish().unwrap().as_slice(), JSON_MESSAGE);
+
+    // write_all() reports the error, since the second write() fails.
+    let mut writer = InflateWriter::new_zlib(0x1000, Vec::new());
+    let err = writer.write_all(&JSON_ZLIB_WITH_DICT).expect_err("expected write_all() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let n = writer.total_in() as usize;
+    assert_eq!(writer.set_dictionary(JSON_DICTIONARY), ZERR::Ok);
+    writer.write_all(&JSON_ZLIB_WITH_DICT[n..]).unwrap();
+    assert_eq!(writer.finish().unwrap().as_slice(), JSON_MESSAGE);
+}
+
+const AUTO_TEST_MESSAGE: &[u8] = b"zlib, gzip, or raw deflate?  zlib, gzip, or raw deflate?\n";
+
+fn write_auto_test_message(mut writer: DeflateWriter<Vec<u8>>) -> Vec<u8> {
+    writer.write_all(AUTO_TEST_MESSAGE).unwrap();
+    writer.finish().unwrap()
+}
+
+// Compresses AUTO_TEST_MESSAGE as a zlib stream, a GZIP stream, and a raw DEFLATE stream.
+fn auto_test_streams() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    (write_auto_test_message(DeflateWriter::new_zlib(6, 0x1000, Vec::new())),
+     write_auto_test_message(DeflateWriter::new_gzip(6, 0x1000, Vec::new())),
+     write_auto_test_message(DeflateWriter::new_raw(6, 0x1000, Vec::new())))
+}
+
+#[test]
+fn test_inflate_auto_zlib_or_gzip() {
+    let (zlib_stream, gzip_stream, raw_stream) = auto_test_streams();
+
+    let mut state = Inflater::new_auto();
+    assert_eq!(inflate_small(&mut state, zlib_stream.as_slice()).as_slice(), AUTO_TEST_MESSAGE);
+
+    let mut state = Inflater::new_auto();
+    assert_eq!(state.capture_header(), ZERR::Ok);
+    assert_eq!(inflate_small(&mut state, gzip_stream.as_slice()).as_slice(), AUTO_TEST_MESSAGE);
+    assert!(state.header().is_some());
+
+    // Without the fallback, a raw DEFLATE stream is rejected.
+    let mut state = Inflater::new_auto();
+    let mut output = [0u8; 0x100];
+    match state.inflate(None, raw_stream.as_slice(), output.as_mut_slice()) {
+        InflateResult::Error(err, offset) => {
+            assert_eq!(err, InflateError::IncorrectHeaderCheck);
+            assert_eq!(offset, 0);
+        }
+        _ => panic!("expected raw DEFLATE to be rejected")
+    }
+}
+
+#[test]
+fn test_inflate_auto_raw_fallback() {
+    let (zlib_stream, gzip_stream, raw_stream) = auto_test_streams();
+    for stream in [&zlib_stream, &gzip_stream, &raw_stream].iter() {
+        let mut state = Inflater::new_auto_with_raw_fallback();
+        assert_eq!(inflate_small(&mut state, stream.as_slice()).as_slice(), AUTO_TEST_MESSAGE);
+    }
+
+    // Detection starts over after reset(), so one Inflater can decode a mix of formats.
+    let mut state = Inflater::new_auto_with_raw_fallback();
+    for stream in [&raw_stream, &zlib_stream, &raw_stream, &gzip_stream].iter() {
+        state.reset();
+        assert_eq!(inflate_small(&mut state, stream.as_slice()).as_slice(), AUTO_TEST_MESSAGE);
+    }
+}
+
+#[test]
+fn test_inflate_reader_auto_raw_fallback() {
+    let (_, _, raw_stream) = auto_test_streams();
+    let mut reader = InflateReader::new_with_inflater(INBUF_SIZE,
+        Inflater::new_auto_with_raw_fallback(), io::Cursor::new(raw_stream.as_slice()));
+    assert_eq!(read_all(&mut reader).unwrap().as_slice(), AUTO_TEST_MESSAGE);
+}
+
+fn read_u16_le(buf: &[u8], pos: usize) -> usize {
+    (buf[pos] as usize) | ((buf[pos + 1] as usize) << 8)
+}
+
+fn read_u32_le(buf: &[u8], pos: usize) -> usize {
+    read_u16_le(buf, pos) | (read_u16_le(buf, pos + 2) << 16)
+}
+
+// An entry in a ZIP file, found by walking the local file headers.
+struct ZipEntry {
+    name: Vec<u8>,
+    data_offset: usize,
+    compressed_size: usize,
+    uncompressed_size: usize,
+}
+
+// Returns the entries of a ZIP file that stores the sizes in the local file headers.
+fn zip_entries(zip: &[u8]) -> Vec<ZipEntry> {
+    let mut entries = Vec::new();
+    let mut pos: usize = 0;
+    while read_u32_le(zip, pos) == 0x04034b50 {
+        assert_eq!(read_u16_le(zip, pos + 6) & 8, 0);   // no data descriptor
+        assert_eq!(read_u16_le(zip, pos + 8), 8);       // deflated
+        let name_len = read_u16_le(zip, pos + 26);
+        let extra_len = read_u16_le(zip, pos + 28);
+        let entry = ZipEntry {
+            name: zip[pos + 30..pos + 30 + name_len].to_vec(),
+            data_offset: pos + 30 + name_len + extra_len,
+            compressed_size: read_u32_le(zip, pos + 18),
+            uncompressed_size: read_u32_le(zip, pos + 22),
+        };
+        pos = entry.data_offset + entry.compressed_size;
+        entries.push(entry);
+    }
+    entries
+}
+
+// Inflates a raw DEFLATE stream at the start of 'input', passing at most 'input_chunk' bytes
+// to each call.  Returns the output, and the number of input bytes in the stream.
+fn inflate_raw_entry(state: &mut Inflater, input: &[u8], input_chunk: usize) -> (Vec<u8>, usize) {
+    let mut output = Vec::new();
+    let mut buf: Vec<u8> = vec![0u8; 0x1000];
+    let mut input_pos: usize = 0;
+    loop {
+        let input_end = std::cmp::min(input_pos + input_chunk, input.len());
+        match state.inflate(None, &input[input_pos..input_end], buf.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, output_bytes_written) => {
+                input_pos += input_bytes_read;
+                output.extend_from_slice(&buf[..output_bytes_written]);
+            }
+            InflateResult::Eof(_) => break,
+            result => panic!("unexpected inflate result: {:?}", result)
+        }
+    }
+    (output, input_pos)
+}
+
+#[test]
+fn test_inflate_raw_zip_entries() {
+    let zip = fs::read("tests/hamlet.zip").unwrap();
+    let hamlet = fs::read("tests/hamlet.tar").unwrap();
+    let silence = b"The rest is silence.\nThe rest is silence.\nThe rest is silence.\n";
+
+    let entries = zip_entries(zip.as_slice());
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].name, b"hamlet.tar".to_vec());
+    assert_eq!(entries[1].name, b"silence.txt".to_vec());
+
+    for &input_chunk in [1usize, 0x40, 0x1000, 0x100000].iter() {
+        for (entry, expected) in entries.iter().zip([hamlet.as_slice(), silence.as_slice()].iter()) {
+            // The input runs to the end of the ZIP file, so the inflater must stop at the end of
+            // the stream and leave the next header alone.
+            let mut state = Inflater::new_raw(15);
+            let (output, input_len) = inflate_raw_entry(&mut state, &zip[entry.data_offset..], input_chunk);
+            assert_eq!(input_len, entry.compressed_size);
+            assert_eq!(output.len(), entry.uncompressed_size);
+            assert!(output.as_slice() == *expected);
+
+            // Once done, the stream stays done.
+            let mut buf = [0u8; 0x10];
+            match state.inflate(None, &zip[entry.data_offset + input_len..], buf.as_mut_slice()) {
+                InflateResult::Eof(_) => {}
+                result => panic!("expected Eof, got {:?}", result)
+            }
+        }
+    }
+}
+
+// Compresses 'data' into a raw DEFLATE stream with the given window size.
+fn deflate_raw(data: &[u8], window_bits: usize) -> Vec<u8> {
+    let mut writer = DeflateWriter::new_with_deflater(0x1000, Deflater::new_raw(6, window_bits), Vec::new());
+    writer.write_all(data).unwrap();
+    writer.finish().unwrap()
+}
+
+#[test]
+fn test_inflate_raw_window_size() {
+    let hamlet = fs::read("tests/hamlet.tar").unwrap();
+
+    // A stream compressed with a small window decodes with a window that size or larger.
+    let small = deflate_raw(hamlet.as_slice(), 9);
+    for &window_bits in [9usize, 12, 15].iter() {
+        let mut state = Inflater::new_raw(window_bits);
+        let (output, input_len) = inflate_raw_entry(&mut state, small.as_slice(), 0x40);
+        assert_eq!(input_len, small.len());
+        assert!(output == hamlet);
+    }
+
+    // With a window that is too small, the stream refers to data the inflater has not kept.
+    let large = deflate_raw(hamlet.as_slice(), 15);
+    let mut state = Inflater::new_raw(9);
+    let (err, _) = inflate_until_error(&mut state, large.as_slice());
+    assert_eq!(err, InflateError::InvalidDistanceTooFarBack);
+}
+
+// Inflates 'input' until inflate() returns something other than Decoded, and returns that.
+fn inflate_to_end(state: &mut Inflater, input: &[u8]) -> InflateResult {
+    let mut output: Vec<u8> = vec![0u8; 0x10000];
+    let mut input_pos: usize = 0;
+    loop {
+        match state.inflate(None, &input[input_pos..], output.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, _) => input_pos += input_bytes_read,
+            result => return result
+        }
+    }
+}
+
+// The standard check values for the bytes "123456789".
+const CHECK_INPUT: &[u8] = b"123456789";
+const CHECK_CRC32: u32 = 0xcbf43926;
+const CHECK_ADLER32: u32 = 0x091e01de;
+
+#[test]
+fn test_inflate_stream_end() {
+    let mut state = Inflater::new_gzip();
+    assert_eq!(inflate_to_end(&mut state, gzip_member(CHECK_INPUT).as_slice()),
+        InflateResult::Eof(StreamEnd { check: CHECK_CRC32, total_out: 9, verified: true }));
+
+    let mut writer = DeflateWriter::new_zlib(6, 0x1000, Vec::new());
+    writer.write_all(CHECK_INPUT).unwrap();
+    let zlib_stream = writer.finish().unwrap();
+    let mut state = Inflater::new_zlib();
+    assert_eq!(inflate_to_end(&mut state, zlib_stream.as_slice()),
+        InflateResult::Eof(StreamEnd { check: CHECK_ADLER32, total_out: 9, verified: true }));
+
+    let mut state = Inflater::new_raw(WINDOW_BITS_DEFAULT);
+    assert_eq!(inflate_to_end(&mut state, deflate_raw(CHECK_INPUT, 15).as_slice()),
+        InflateResult::Eof(StreamEnd { check: 0, total_out: 9, verified: false }));
+
+    // A large stream, decoded in many calls.
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let n = input.len();
+    let crc = (input[n - 8] as u32) | ((input[n - 7] as u32) << 8) | ((input[n - 6] as u32) << 16) | ((input[n - 5] as u32) << 24);
+    let mut state = Inflater::new_gzip();
+    let mut output = [0u8; 0x100];
+    let mut input_pos: usize = 0;
+    loop {
+        let input_end = std::cmp::min(input_pos + 0x100, n);
+        match state.inflate(None, &input[input_pos..input_end], output.as_mut_slice()) {
+            InflateResult::Decoded(input_bytes_read, _) => input_pos += input_bytes_read,
+            InflateResult::Eof(end) => {
+                assert_eq!(end, StreamEnd { check: crc, total_out: 195072, verified: true });
                 break;
             }
+            result => panic!("unexpected inflate result: {:?}", result)
         }
     }
 }
+
+#[test]
+fn test_inflate_error_trailers() {
+    let member = gzip_member(CHECK_INPUT);
+    let n = member.len();
+
+    // One bit flipped in the stored CRC-32.
+    let mut input = member.clone();
+    input[n - 8] ^= 1;
+    let mut state = Inflater::new_gzip();
+    assert_eq!(inflate_to_end(&mut state, input.as_slice()),
+        InflateResult::Error(InflateError::IncorrectDataCheck(CHECK_CRC32 ^ 1, CHECK_CRC32), (n - 8) as u64));
+
+    // The stored length is one too large.
+    let mut input = member.clone();
+    input[n - 4] += 1;
+    let mut state = Inflater::new_gzip();
+    assert_eq!(inflate_to_end(&mut state, input.as_slice()),
+        InflateResult::Error(InflateError::IncorrectLengthCheck(10, 9), (n - 4) as u64));
+
+    // One bit flipped in the stored Adler-32, which is big-endian.
+    let mut writer = DeflateWriter::new_zlib(6, 0x1000, Vec::new());
+    writer.write_all(CHECK_INPUT).unwrap();
+    let mut input = writer.finish().unwrap();
+    let n = input.len();
+    input[n - 1] ^= 1;
+    let mut state = Inflater::new_zlib();
+    assert_eq!(inflate_to_end(&mut state, input.as_slice()),
+        InflateResult::Error(InflateError::IncorrectDataCheck(CHECK_ADLER32 ^ 1, CHECK_ADLER32), (n - 4) as u64));
+}
+
+#[test]
+fn test_inflate_reader_error_trailer() {
+    let mut input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let n = input.len();
+    input[n - 1] ^= 0x80;
+
+    let mut reader = InflateReader::new_gzip(INBUF_SIZE, io::Cursor::new(input.as_slice()));
+    let err = read_all(&mut reader).expect_err("expected read_to_end() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), format!("incorrect length check: at input offset {}: expected {} bytes, decoded 195072 bytes",
+        n - 4, 195072 + 0x80000000u32));
+}
+
+// Compresses 'data' into a GZIP stream, ending a DEFLATE block every 'block_size' bytes.
+fn gzip_blocks(data: &[u8], block_size: usize) -> Vec<u8> {
+    let mut deflater = Deflater::new_gzip(6);
+    let mut output: Vec<u8> = vec![0u8; data.len() + 0x1000];
+    let mut output_pos: usize = 0;
+    let chunks: Vec<&[u8]> = data.chunks(block_size).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let flush = if i == chunks.len() - 1 { Flush::Finish } else { Flush::Block };
+        match deflater.deflate(flush, chunk, &mut output[output_pos..]) {
+            DeflateResult::Encoded(in_bytes, out_bytes) | DeflateResult::Finished(in_bytes, out_bytes) => {
+                assert_eq!(in_bytes, chunk.len());
+                output_pos += out_bytes;
+            }
+            result => panic!("unexpected deflate result: {:?}", result)
+        }
+    }
+    output.truncate(output_pos);
+    output
+}
+
+// Checks that 'index' can read the data of the GZIP stream 'input' starting at each of
+// 'offsets'.
+fn check_gzip_index_seek(index: &GzipIndex, input: &[u8], expected: &[u8], offsets: &[u64]) {
+    for &offset in offsets.iter() {
+        let mut reader = index.seek(io::Cursor::new(input), offset).unwrap();
+        let mut buf = [0u8; 1000];
+        let mut len: usize = 0;
+        while len < buf.len() {
+            match reader.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(err) => panic!("unexpected error at offset {}: {:?}", offset, err)
+            }
+        }
+        let start = offset as usize;
+        let end = std::cmp::min(start + buf.len(), expected.len());
+        assert_eq!(len, end - start);
+        assert!(buf[..len] == expected[start..end], "wrong data at offset {}", offset);
+    }
+}
+
+// The offsets at, and next to, each checkpoint, and a few others.
+fn gzip_index_test_offsets(index: &GzipIndex) -> Vec<u64> {
+    let mut offsets: Vec<u64> = vec![0, 1, 1000, 12345, 100000, 195071, 195072];
+    for checkpoint in index.checkpoints().iter() {
+        offsets.push(checkpoint.output_offset);
+        offsets.push(checkpoint.output_offset + 1);
+        if checkpoint.output_offset != 0 {
+            offsets.push(checkpoint.output_offset - 1);
+        }
+    }
+    offsets
+}
+
+#[test]
+fn test_gzip_index_seek() {
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+    let input = gzip_blocks(expected.as_slice(), 5000);
+    let index = GzipIndex::build(&mut io::Cursor::new(input.as_slice()), 0x4000).unwrap();
+    assert_eq!(index.total_out(), 195072);
+
+    let checkpoints = index.checkpoints();
+    assert_eq!(checkpoints[0].output_offset, 0);
+    assert_eq!(checkpoints[0].input_offset, 10);
+    assert_eq!(checkpoints[0].window.len(), 0);
+    for pair in checkpoints.windows(2) {
+        assert_eq!(pair[1].output_offset - pair[0].output_offset, 20000);
+        assert_eq!(pair[1].window.len() as u64, std::cmp::min(pair[1].output_offset, 0x8000));
+    }
+    assert_eq!(checkpoints.len(), 10);
+    assert!(checkpoints.iter().any(|c| c.bits != 0));
+
+    check_gzip_index_seek(&index, input.as_slice(), expected.as_slice(), gzip_index_test_offsets(&index).as_slice());
+
+    let err = index.seek(io::Cursor::new(input.as_slice()), 195073).err().expect("expected seek() to fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_gzip_index_seek_gzip_file() {
+    // This file has only three blocks.
+    let expected = fs::read("tests/hamlet.tar").unwrap();
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let index = GzipIndex::build(&mut io::Cursor::new(input.as_slice()), 0x4000).unwrap();
+    assert_eq!(index.checkpoints().len(), 2);
+    assert_eq!(index.checkpoints()[0].input_offset, 21);     // 10-byte header and "hamlet.tar\0"
+    check_gzip_index_seek(&index, input.as_slice(), expected.as_slice(), gzip_index_test_offsets(&index).as_slice());
+}
+
+#[test]
+fn test_gzip_index_serialize() {
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let index = GzipIndex::build(&mut io::Cursor::new(input.as_slice()), 0x8000).unwrap();
+    let mut data: Vec<u8> = Vec::new();
+    index.write_to(&mut data).unwrap();
+
+    let index2 = GzipIndex::read_from(&mut io::Cursor::new(data.as_slice())).unwrap();
+    assert!(index2 == index);
+    assert_eq!(index2.span(), 0x8000);
+
+    let err = GzipIndex::read_from(&mut io::Cursor::new(b"not an index")).expect_err("expected read_from() to fail");
+    assert_eq!(err.to_string(), "not a gzip index");
+}
+
+#[test]
+fn test_gzip_index_read_invalid_checkpoints() {
+    let input = fs::read("tests/hamlet.tar.gz").unwrap();
+    let index = GzipIndex::build(&mut io::Cursor::new(input.as_slice()), 0x8000).unwrap();
+    let mut data: Vec<u8> = Vec::new();
+    index.write_to(&mut data).unwrap();
+
+    // The header is 32 bytes, and then each checkpoint has 21 bytes before its window.
+    let first = 32;
+    let second = first + 21 + index.checkpoints()[0].window.len();
+    let corruptions: [(usize, u64); 4] = [
+        (second, 0),                    // second checkpoint not after the first
+        (second, 195073),               // past the end of the uncompressed data
+        (first + 8, 0),                 // input offset 0, with bits (below)
+        (8 + 8, 1000),                  // total_out before the second checkpoint
+    ];
+    for (i, &(pos, value)) in corruptions.iter().enumerate() {
+        let mut bad = data.clone();
+        bad[pos..pos + 8].copy_from_slice(&value.to_le_bytes());
+        if i == 2 {
+            bad[first + 16] = 3;
+        }
+        let err = GzipIndex::read_from(&mut io::Cursor::new(bad.as_slice())).expect_err("expected read_from() to fail");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("invalid gzip index checkpoint: "), "{}", err);
+    }
+}
+
+#[test]
+fn test_adler32_combine() {
+    let data = fs::read("tests/hamlet.tar").unwrap();
+    let whole = adler32(1, data.as_slice());
+    assert_eq!(whole, 0xd7fe47fc);
+    for &split in [0usize, 1, 5552, 65521, 100000, data.len() - 1, data.len()].iter() {
+        let (a, b) = data.split_at(split);
+        assert_eq!(adler32_combine(adler32(1, a), adler32(1, b), b.len() as u64), whole);
+    }
+
+    // "abc" followed by 1 MiB of zeros
+    assert_eq!(adler32_combine(adler32(1, b"abc"), 0x00f00001, 1 << 20), 0x16ec0127);
+}
+
+#[test]
+fn test_crc32_combine() {
+    // CRC-32 of tests/hamlet.tar, and of the parts before and after offset 100000
+    assert_eq!(crc32_combine(0x3f608407, 0xfa7d6423, 195072 - 100000), 0xf07b6e50);
+
+    // "abc" followed by 1 MiB of zeros
+    assert_eq!(crc32_combine(0x352441c2, 0xa738ea1c, 1 << 20), 0xb3cf750e);
+
+    // "12345" and "6789"
+    assert_eq!(crc32_combine(0xcbf53a1c, 0x9dbabf87, 4), 0xcbf43926);
+
+    // Combining with an empty sequence changes nothing.
+    assert_eq!(crc32_combine(0xcbf43926, 0, 0), 0xcbf43926);
+}